@@ -0,0 +1,639 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::loader::{self, Loaded, Loader};
+use crate::material::{Material, MaterialDescriptor, MaterialLayout};
+use crate::mesh::{Mesh, MeshData};
+use crate::texture::Texture;
+
+/// A reference to an asset in an [`AssetStorage`]. The asset stays loaded
+/// for as long as any handle to it is around.
+pub struct Handle<T> {
+    index: usize,
+    alive: Arc<()>,
+    asset: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index,
+            alive: self.alive.clone(),
+            asset: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> PartialOrd for Handle<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Handle<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.index.cmp(&other.index)
+    }
+}
+
+#[cfg(test)]
+impl<T> Handle<T> {
+    /// A handle to nothing, for tests that only compare handles.
+    pub(crate) fn detached(index: usize) -> Self {
+        Self {
+            index,
+            alive: Arc::new(()),
+            asset: PhantomData,
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle({})", self.index)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadState {
+    Pending,
+    Ready,
+    Failed(String),
+}
+
+/// What an asset is found by when it's asked for again.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum AssetKey {
+    /// A path or a name given by whoever added it.
+    Name(String),
+    /// The contents it was made from.
+    Hash(u64),
+}
+
+fn hash_of(data: impl Hash) -> AssetKey {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    AssetKey::Hash(hasher.finish())
+}
+
+struct Slot<T> {
+    /// Shown in the inspector and in errors.
+    name: String,
+    keys: Vec<AssetKey>,
+    state: LoadState,
+    asset: Option<T>,
+    /// Shared with every handle, the asset is unused once only this one is
+    /// left.
+    alive: Arc<()>,
+}
+
+/// Assets of one type, each loaded once however many times it's asked for
+/// and dropped by [`AssetStorage::collect_garbage`] once nothing holds a
+/// handle to it.
+pub struct AssetStorage<T> {
+    slots: Vec<Option<Slot<T>>>,
+    free: Vec<usize>,
+    keys: HashMap<AssetKey, usize>,
+}
+
+impl<T> Default for AssetStorage<T> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            keys: HashMap::new(),
+        }
+    }
+}
+
+impl<T> AssetStorage<T> {
+    fn slot(&self, handle: &Handle<T>) -> &Slot<T> {
+        self.slots[handle.index].as_ref().expect("handles keep their slot alive")
+    }
+
+    fn handle(&self, index: usize) -> Handle<T> {
+        let slot = self.slots[index].as_ref().expect("keys only point at live slots");
+        Handle {
+            index,
+            alive: slot.alive.clone(),
+            asset: PhantomData,
+        }
+    }
+
+    /// The asset already stored under any of `keys`, which it's then found by
+    /// from all of them.
+    fn find(&mut self, keys: &[AssetKey]) -> Option<Handle<T>> {
+        let index = keys.iter().find_map(|key| self.keys.get(key).copied())?;
+        for key in keys {
+            if !self.keys.contains_key(key) {
+                self.keys.insert(key.clone(), index);
+                self.slots[index].as_mut().unwrap().keys.push(key.clone());
+            }
+        }
+        Some(self.handle(index))
+    }
+
+    /// A new pending slot found by `keys`, to be filled in by
+    /// [`AssetStorage::complete`].
+    fn reserve(&mut self, name: &str, keys: Vec<AssetKey>) -> Handle<T> {
+        let slot = Slot {
+            name: name.to_string(),
+            keys: keys.clone(),
+            state: LoadState::Pending,
+            asset: None,
+            alive: Arc::new(()),
+        };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.slots[index] = Some(slot);
+                index
+            }
+            None => {
+                self.slots.push(Some(slot));
+                self.slots.len() - 1
+            }
+        };
+        for key in keys {
+            self.keys.insert(key, index);
+        }
+        self.handle(index)
+    }
+
+    /// Where the asset loaded from `path` is, if it's still around.
+    fn find_path(&self, path: &str) -> Option<usize> {
+        self.keys.get(&AssetKey::Name(path.to_string())).copied()
+    }
+
+    /// Whether the asset loaded from `path` has finished loading and can
+    /// be loaded again.
+    fn reloadable(&self, path: &str) -> bool {
+        self.find_path(path)
+            .is_some_and(|index| self.slots[index].as_ref().unwrap().state != LoadState::Pending)
+    }
+
+    fn complete(&mut self, handle: &Handle<T>, result: anyhow::Result<T>) {
+        self.complete_index(handle.index, result);
+    }
+
+    fn complete_index(&mut self, index: usize, result: anyhow::Result<T>) {
+        let slot = self.slots[index].as_mut().unwrap();
+        match result {
+            Ok(asset) => {
+                slot.asset = Some(asset);
+                slot.state = LoadState::Ready;
+            }
+            Err(e) => {
+                log::error!("Failed to load {}: {:#}", slot.name, e);
+                slot.state = LoadState::Failed(format!("{:#}", e));
+            }
+        }
+    }
+
+    /// The asset, once it's ready.
+    pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
+        self.slot(handle).asset.as_ref()
+    }
+
+    pub fn state(&self, handle: &Handle<T>) -> &LoadState {
+        &self.slot(handle).state
+    }
+
+    /// Name and load state of every stored asset.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &LoadState)> {
+        self.slots.iter().flatten().map(|slot| (slot.name.as_str(), &slot.state))
+    }
+
+    /// Drops every asset nothing holds a handle to any more, returning
+    /// where they were. Pending ones wait until they're done, so their slot
+    /// isn't reused while a load is still on its way to it.
+    fn collect_garbage(&mut self) -> Vec<usize> {
+        let mut dropped = Vec::new();
+        for index in 0..self.slots.len() {
+            let unused = self.slots[index]
+                .as_ref()
+                .is_some_and(|slot| slot.state != LoadState::Pending && Arc::strong_count(&slot.alive) == 1);
+            if unused {
+                let slot = self.slots[index].take().unwrap();
+                for key in &slot.keys {
+                    self.keys.remove(key);
+                }
+                self.free.push(index);
+                dropped.push(index);
+            }
+        }
+        dropped
+    }
+}
+
+/// Textures for each slot of a material made with [`Assets::material`].
+#[derive(Default)]
+pub struct MaterialTextures {
+    pub base_color: Option<Handle<Texture>>,
+    pub metallic_roughness: Option<Handle<Texture>>,
+    pub normal: Option<Handle<Texture>>,
+    pub occlusion: Option<Handle<Texture>>,
+    pub emissive: Option<Handle<Texture>>,
+}
+
+impl MaterialTextures {
+    fn all(&self) -> impl Iterator<Item = &Handle<Texture>> {
+        [
+            &self.base_color,
+            &self.metallic_roughness,
+            &self.normal,
+            &self.occlusion,
+            &self.emissive,
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+/// What a material was made from, kept to make it again once its textures
+/// have loaded. Holding the texture handles keeps them loaded for as long as
+/// the material is.
+struct MaterialSource {
+    name: String,
+    /// Everything but the name and textures.
+    desc: MaterialDescriptor<'static>,
+    textures: MaterialTextures,
+}
+
+/// Every texture, mesh, shader and material loaded on the GPU. Asking for
+/// one that's already loaded hands out another handle to it instead of
+/// uploading it again.
+///
+/// Textures loaded from files show up as pending and are stood in for by a
+/// placeholder until [`Assets::poll`] finds them decoded and uploads them.
+/// Meshes loaded from files aren't drawn until then. Natively, files that
+/// change on disk are loaded again and written over the old asset in place.
+pub struct Assets {
+    pub textures: AssetStorage<Texture>,
+    pub meshes: AssetStorage<Mesh>,
+    pub shaders: AssetStorage<wgpu::ShaderModule>,
+    pub materials: AssetStorage<Material>,
+    material_sources: HashMap<usize, MaterialSource>,
+    placeholder: Texture,
+    loader: Loader,
+}
+
+impl Assets {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        Self {
+            textures: AssetStorage::default(),
+            meshes: AssetStorage::default(),
+            shaders: AssetStorage::default(),
+            materials: AssetStorage::default(),
+            material_sources: HashMap::new(),
+            placeholder: placeholder_texture(device, queue),
+            loader: Loader::new(),
+        }
+    }
+
+    /// Starts loading the image file at `path` as an sRGB texture, found
+    /// again by the path.
+    pub fn load_texture(&mut self, path: &str) -> Handle<Texture> {
+        let keys = vec![AssetKey::Name(path.to_string())];
+        if let Some(handle) = self.textures.find(&keys) {
+            return handle;
+        }
+        let handle = self.textures.reserve(path, keys);
+        self.loader.load_image(path);
+        handle
+    }
+
+    /// Starts loading the OBJ model at `path` as a mesh, found again by the
+    /// path.
+    pub fn load_mesh(&mut self, path: &str) -> Handle<Mesh> {
+        let keys = vec![AssetKey::Name(path.to_string())];
+        if let Some(handle) = self.meshes.find(&keys) {
+            return handle;
+        }
+        let handle = self.meshes.reserve(path, keys);
+        self.loader.load_model(path);
+        handle
+    }
+
+    /// The texture, or the placeholder while it's loading or if it failed
+    /// to.
+    pub fn texture_or_placeholder(&self, handle: &Handle<Texture>) -> &Texture {
+        self.textures.get(handle).unwrap_or(&self.placeholder)
+    }
+
+    /// Uploads the textures and meshes that finished loading, and remakes
+    /// the materials using new textures. Returns the textures that are done,
+    /// loaded or not, and those that had to be replaced by a new one to
+    /// reload them; those reloaded in place keep working where they're
+    /// bound.
+    pub fn poll(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, layout: &MaterialLayout) -> Vec<Handle<Texture>> {
+        for path in self.loader.changed_files() {
+            if self.textures.reloadable(&path) {
+                log::info!("Reloading {}", path);
+                self.loader.load_image(&path);
+            }
+            if self.meshes.reloadable(&path) {
+                log::info!("Reloading {}", path);
+                self.loader.load_model(&path);
+            }
+        }
+
+        let mut done = Vec::new();
+        for loaded in self.loader.finished().collect::<Vec<_>>() {
+            match loaded {
+                Loaded::Image { path, image } => done.extend(self.finish_texture(device, queue, &path, image)),
+                Loaded::Model { path, model } => self.finish_mesh(device, queue, &path, model),
+            }
+        }
+        if done.is_empty() {
+            return done;
+        }
+
+        let waiting: Vec<usize> = self
+            .material_sources
+            .iter()
+            .filter(|(_, source)| source.textures.all().any(|texture| done.contains(texture)))
+            .map(|(&index, _)| index)
+            .collect();
+        for index in waiting {
+            let material = self.build_material(device, layout, &self.material_sources[&index]);
+            self.materials.complete_index(index, Ok(material));
+        }
+        done
+    }
+
+    /// Puts a loaded image in place of the texture for `path`, returning it
+    /// unless it was written over the old one in place.
+    fn finish_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &str,
+        image: anyhow::Result<loader::Image>,
+    ) -> Option<Handle<Texture>> {
+        // Dropped while it was loading.
+        let index = self.textures.find_path(path)?;
+        if let Some(texture) = &self.textures.slots[index].as_ref().unwrap().asset {
+            // A reload, which keeps the old texture if the new one is broken,
+            // such as when it's caught half saved.
+            let image = match image {
+                Ok(image) => image,
+                Err(e) => {
+                    log::error!("Failed to reload {}: {:#}", path, e);
+                    return None;
+                }
+            };
+            if texture.write_loaded(queue, &image) {
+                log::info!("Reloaded {} in place", path);
+                return None;
+            }
+            let texture = Texture::from_loaded(device, queue, &image, Some(path));
+            self.textures.complete_index(index, texture);
+            log::info!("Reloaded {} as a new texture", path);
+            return Some(self.textures.handle(index));
+        }
+        let texture = image.and_then(|image| Texture::from_loaded(device, queue, &image, Some(path)));
+        self.textures.complete_index(index, texture);
+        Some(self.textures.handle(index))
+    }
+
+    /// Puts a loaded model in place of the mesh for `path`. Meshes are
+    /// looked up each time they're drawn, so it doesn't matter whether that
+    /// means new buffers.
+    fn finish_mesh(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, path: &str, model: anyhow::Result<MeshData>) {
+        let Some(index) = self.meshes.find_path(path) else {
+            return;
+        };
+        if let Some(mesh) = &mut self.meshes.slots[index].as_mut().unwrap().asset {
+            match model {
+                Ok(model) if mesh.write(queue, &model) => log::info!("Reloaded {} in place", path),
+                Ok(model) => {
+                    *mesh = Mesh::new(device, path, &model);
+                    log::info!("Reloaded {} into new buffers", path);
+                }
+                Err(e) => log::error!("Failed to reload {}: {:#}", path, e),
+            }
+            return;
+        }
+        let mesh = model.map(|model| Mesh::new(device, path, &model));
+        self.meshes.complete_index(index, mesh);
+    }
+
+    /// A texture made some other way, such as generated, found again by
+    /// `name`.
+    pub fn add_texture(&mut self, name: &str, texture: impl FnOnce() -> anyhow::Result<Texture>) -> Handle<Texture> {
+        let keys = vec![AssetKey::Name(name.to_string())];
+        if let Some(handle) = self.textures.find(&keys) {
+            return handle;
+        }
+        let handle = self.textures.reserve(name, keys);
+        self.textures.complete(&handle, texture());
+        handle
+    }
+
+    /// The mesh called `name`, built with `build` the first time it's asked
+    /// for.
+    pub fn mesh(&mut self, name: &str, build: impl FnOnce() -> Mesh) -> Handle<Mesh> {
+        let keys = vec![AssetKey::Name(name.to_string())];
+        if let Some(handle) = self.meshes.find(&keys) {
+            return handle;
+        }
+        let handle = self.meshes.reserve(name, keys);
+        self.meshes.complete(&handle, Ok(build()));
+        handle
+    }
+
+    /// The shader module for `desc`, found again by its WGSL source.
+    pub fn shader(&mut self, device: &wgpu::Device, desc: wgpu::ShaderModuleDescriptor) -> Handle<wgpu::ShaderModule> {
+        let keys = match &desc.source {
+            wgpu::ShaderSource::Wgsl(source) => vec![hash_of(source)],
+            _ => Vec::new(),
+        };
+        if let Some(handle) = self.shaders.find(&keys) {
+            return handle;
+        }
+        let handle = self.shaders.reserve(desc.label.unwrap_or("shader"), keys);
+        self.shaders.complete(&handle, Ok(device.create_shader_module(desc)));
+        handle
+    }
+
+    /// The material called `desc.name`, made the first time it's asked for
+    /// with `textures` in place of the ones in `desc`. The textures stay
+    /// loaded for as long as the material does, and it's made again as any
+    /// of them finish loading.
+    pub fn material(
+        &mut self,
+        device: &wgpu::Device,
+        layout: &MaterialLayout,
+        desc: &MaterialDescriptor,
+        textures: MaterialTextures,
+    ) -> Handle<Material> {
+        let keys = vec![AssetKey::Name(desc.name.to_string())];
+        if let Some(handle) = self.materials.find(&keys) {
+            return handle;
+        }
+
+        let source = MaterialSource {
+            name: desc.name.to_string(),
+            desc: MaterialDescriptor {
+                name: "",
+                base_color_texture: None,
+                metallic_roughness_texture: None,
+                normal_texture: None,
+                occlusion_texture: None,
+                emissive_texture: None,
+                ..*desc
+            },
+            textures,
+        };
+        let material = self.build_material(device, layout, &source);
+        let handle = self.materials.reserve(desc.name, keys);
+        self.materials.complete(&handle, Ok(material));
+        self.material_sources.insert(handle.index, source);
+        handle
+    }
+
+    fn build_material(&self, device: &wgpu::Device, layout: &MaterialLayout, source: &MaterialSource) -> Material {
+        let texture = |handle: &Option<Handle<Texture>>| handle.as_ref().map(|handle| self.texture_or_placeholder(handle));
+        Material::new(device, layout, &MaterialDescriptor {
+            name: &source.name,
+            base_color_texture: texture(&source.textures.base_color),
+            metallic_roughness_texture: texture(&source.textures.metallic_roughness),
+            normal_texture: texture(&source.textures.normal),
+            occlusion_texture: texture(&source.textures.occlusion),
+            emissive_texture: texture(&source.textures.emissive),
+            ..source.desc
+        })
+    }
+
+    /// Frees everything nothing holds a handle to any more. Materials go
+    /// first so the textures only they held go in the same call.
+    pub fn collect_garbage(&mut self) {
+        let materials = self.materials.collect_garbage();
+        for index in &materials {
+            self.material_sources.remove(index);
+        }
+        let dropped = materials.len()
+            + self.meshes.collect_garbage().len()
+            + self.shaders.collect_garbage().len()
+            + self.textures.collect_garbage().len();
+        if dropped > 0 {
+            log::info!("Freed {} unused assets", dropped);
+        }
+    }
+}
+
+/// A grey checkerboard, for textures that haven't loaded.
+fn placeholder_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> Texture {
+    let image = image::RgbaImage::from_fn(8, 8, |x, y| {
+        if (x + y) % 2 == 0 {
+            image::Rgba([160, 160, 160, 255])
+        } else {
+            image::Rgba([96, 96, 96, 255])
+        }
+    });
+    Texture::from_image(device, queue, &image::DynamicImage::ImageRgba8(image), Some("placeholder"))
+        .expect("an 8x8 image always uploads")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str) -> Vec<AssetKey> {
+        vec![AssetKey::Name(name.to_string())]
+    }
+
+    fn add(storage: &mut AssetStorage<u32>, keys: Vec<AssetKey>, value: u32) -> Handle<u32> {
+        if let Some(handle) = storage.find(&keys) {
+            return handle;
+        }
+        let handle = storage.reserve("asset", keys);
+        storage.complete(&handle, Ok(value));
+        handle
+    }
+
+    #[test]
+    fn found_again_by_name_or_hash() {
+        let mut storage = AssetStorage::default();
+        let a = add(&mut storage, name("a"), 1);
+        assert_eq!(add(&mut storage, name("a"), 2), a);
+        assert_eq!(storage.get(&a), Some(&1));
+        assert_ne!(add(&mut storage, name("b"), 3), a);
+
+        let hashed = add(&mut storage, vec![hash_of("source")], 4);
+        assert_eq!(add(&mut storage, vec![hash_of("source")], 5), hashed);
+        assert_ne!(add(&mut storage, vec![hash_of("other source")], 6), hashed);
+
+        // Found by a new name with a known hash, then by that name alone.
+        let both = add(&mut storage, vec![AssetKey::Name("c".to_string()), hash_of("source")], 7);
+        assert_eq!(both, hashed);
+        assert_eq!(add(&mut storage, name("c"), 8), hashed);
+        assert_eq!(storage.iter().count(), 4);
+    }
+
+    #[test]
+    fn dropped_once_no_handle_is_left() {
+        let mut storage = AssetStorage::default();
+        let a = add(&mut storage, name("a"), 1);
+        let copy = a.clone();
+        let b = add(&mut storage, name("b"), 2);
+
+        drop(a);
+        assert!(storage.collect_garbage().is_empty(), "a copy is still held");
+        drop(copy);
+        assert_eq!(storage.collect_garbage(), vec![0]);
+        assert_eq!(storage.get(&b), Some(&2));
+        assert_eq!(storage.iter().count(), 1);
+
+        // Gone by its name too, so it's made again, in the freed slot.
+        let again = add(&mut storage, name("a"), 3);
+        assert_eq!(again, Handle::detached(0));
+        assert_eq!(storage.get(&again), Some(&3));
+        assert!(storage.collect_garbage().is_empty());
+    }
+
+    #[test]
+    fn pending_slots_wait_for_their_load() {
+        let mut storage: AssetStorage<u32> = AssetStorage::default();
+        let handle = storage.reserve("loading", name("loading"));
+        assert_eq!(storage.state(&handle), &LoadState::Pending);
+        let index = handle.index;
+        drop(handle);
+        assert!(storage.collect_garbage().is_empty());
+        assert_eq!(storage.find_path("loading"), Some(index));
+
+        storage.complete_index(index, Err(anyhow::anyhow!("no such file")));
+        assert_eq!(storage.collect_garbage(), vec![index]);
+        assert_eq!(storage.find_path("loading"), None);
+    }
+
+    #[test]
+    fn reloads_go_in_place_once_loading_is_done() {
+        let mut storage: AssetStorage<u32> = AssetStorage::default();
+        let handle = storage.reserve("a.png", name("a.png"));
+        assert!(!storage.reloadable("a.png"), "still loading");
+        assert!(!storage.reloadable("b.png"), "never loaded");
+
+        storage.complete(&handle, Ok(1));
+        assert!(storage.reloadable("a.png"));
+        let index = storage.find_path("a.png").unwrap();
+        storage.complete_index(index, Ok(2));
+        assert_eq!(storage.get(&handle), Some(&2));
+
+        // A broken file is reloadable too, to pick up the fixed one.
+        let broken = storage.reserve("c.png", name("c.png"));
+        storage.complete(&broken, Err(anyhow::anyhow!("half saved")));
+        assert!(storage.reloadable("c.png"));
+    }
+}
@@ -0,0 +1,227 @@
+use crate::post::{self, POST_INPUT_WGSL};
+use crate::texture::Texture;
+use crate::uniform::{UniformBuffer, WgslType};
+use crate::wgsl_layout;
+
+/// The chain stops once a level would be smaller than this on either side.
+const MIN_MIP_SIZE: u32 = 8;
+const MAX_MIPS: usize = 6;
+/// The starting threshold when the scene is rendered in the fallback
+/// format, where no pixel is brighter than 1.0.
+const LDR_THRESHOLD: f32 = 0.8;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniform {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    radius: f32,
+}
+
+wgsl_layout!(BloomUniform {
+    threshold: WgslType::F32,
+    knee: WgslType::F32,
+    intensity: WgslType::F32,
+    radius: WgslType::F32,
+});
+
+/// Bloom on the HDR scene target: a bright pass into half resolution,
+/// a downsample chain, then a tent-filtered upsample back up the chain
+/// that is finally added onto the scene.
+///
+/// Each level of the chain is its own texture rather than a mip of one
+/// texture, WebGL2 can't sample one mip of a texture while rendering into
+/// another.
+pub struct Bloom {
+    pub enabled: bool,
+    /// Brightness above which pixels start to bloom.
+    pub threshold: f32,
+    /// Width of the soft transition below the threshold, as a fraction of
+    /// the threshold.
+    pub knee: f32,
+    pub intensity: f32,
+    /// Upsample filter radius in UV units, larger spreads the glow further.
+    pub radius: f32,
+    format: wgpu::TextureFormat,
+    mips: Vec<(Texture, wgpu::BindGroup)>,
+    uniform: UniformBuffer<BloomUniform>,
+    prefilter_pipeline: wgpu::RenderPipeline,
+    downsample_pipeline: wgpu::RenderPipeline,
+    upsample_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+}
+
+impl Bloom {
+    /// `input_layout` is the layout of the scene's bind group, see
+    /// [`post::PostProcessChain::texture_bind_group_layout`].
+    pub fn new(
+        device: &wgpu::Device,
+        input_layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        // Without HDR nothing gets past 1.0, so the threshold comes down to
+        // let the brightest of what's left bloom.
+        let threshold = if format == crate::hdr::HDR_FORMAT {
+            1.0
+        } else {
+            log::info!("the scene isn't HDR, lowering the bloom threshold to {}", LDR_THRESHOLD);
+            LDR_THRESHOLD
+        };
+        let (knee, intensity, radius) = (0.5, 0.3, 0.005);
+        let uniform = UniformBuffer::new(
+            device,
+            &BloomUniform {
+                threshold,
+                knee: knee * threshold,
+                intensity,
+                radius,
+            },
+            wgpu::ShaderStages::FRAGMENT,
+            "bloom_uniform",
+        );
+
+        let source = format!("{}\n{}", POST_INPUT_WGSL, include_str!("bloom.wgsl"));
+        let layouts = [input_layout, &uniform.bind_group_layout];
+        let create_pipeline = |blend, entry_point, label| {
+            post::create_fullscreen_pipeline(device, &layouts, format, blend, &source, entry_point, label)
+        };
+        let prefilter_pipeline =
+            create_pipeline(wgpu::BlendState::REPLACE, "fs_prefilter", "Bloom Prefilter Pipeline");
+        let downsample_pipeline =
+            create_pipeline(wgpu::BlendState::REPLACE, "fs_downsample", "Bloom Downsample Pipeline");
+        let upsample_pipeline = create_pipeline(post::ADDITIVE, "fs_upsample", "Bloom Upsample Pipeline");
+        let composite_pipeline = create_pipeline(post::ADDITIVE, "fs_composite", "Bloom Composite Pipeline");
+
+        Self {
+            enabled: true,
+            threshold,
+            knee,
+            intensity,
+            radius,
+            format,
+            mips: create_mips(device, input_layout, width, height, format),
+            uniform,
+            prefilter_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            composite_pipeline,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, input_layout: &wgpu::BindGroupLayout, width: u32, height: u32) {
+        self.mips = create_mips(device, input_layout, width, height, self.format);
+    }
+
+    /// Blooms the scene behind `scene_bind_group` and adds the result back
+    /// onto `scene_view`.
+    pub fn run(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        scene_bind_group: &wgpu::BindGroup,
+        scene_view: &wgpu::TextureView,
+    ) {
+        if !self.enabled || self.mips.is_empty() {
+            return;
+        }
+
+        self.uniform.update(queue, &BloomUniform {
+            threshold: self.threshold,
+            knee: self.knee * self.threshold,
+            intensity: self.intensity,
+            radius: self.radius,
+        });
+
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+        self.draw(encoder, &self.prefilter_pipeline, scene_bind_group, &self.mips[0].0.view, clear, "Bloom Prefilter");
+
+        for window in self.mips.windows(2) {
+            let (source, target) = (&window[0], &window[1]);
+            self.draw(encoder, &self.downsample_pipeline, &source.1, &target.0.view, clear, "Bloom Downsample");
+        }
+
+        for window in self.mips.windows(2).rev() {
+            let (target, source) = (&window[0], &window[1]);
+            self.draw(
+                encoder,
+                &self.upsample_pipeline,
+                &source.1,
+                &target.0.view,
+                wgpu::LoadOp::Load,
+                "Bloom Upsample",
+            );
+        }
+
+        self.draw(
+            encoder,
+            &self.composite_pipeline,
+            &self.mips[0].1,
+            scene_view,
+            wgpu::LoadOp::Load,
+            "Bloom Composite",
+        );
+    }
+
+    fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        input: &wgpu::BindGroup,
+        target: &wgpu::TextureView,
+        load: wgpu::LoadOp<wgpu::Color>,
+        label: &str,
+    ) {
+        let mut pass = post::begin_fullscreen_pass(encoder, target, load, label);
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, input, &[]);
+        pass.set_bind_group(1, &self.uniform.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_mips(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> Vec<(Texture, wgpu::BindGroup)> {
+    mip_sizes(width, height)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (width, height))| {
+            post::create_target(device, layout, width, height, format, &format!("bloom_mip_{}", i))
+        })
+        .collect()
+}
+
+/// The size of each level of the chain, starting at half of `width` x
+/// `height`.
+fn mip_sizes(width: u32, height: u32) -> Vec<(u32, u32)> {
+    let mut sizes = Vec::new();
+    let (mut width, mut height) = (width / 2, height / 2);
+    while sizes.len() < MAX_MIPS && width >= MIN_MIP_SIZE && height >= MIN_MIP_SIZE {
+        sizes.push((width, height));
+        width /= 2;
+        height /= 2;
+    }
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_chain_halves_until_too_small() {
+        assert_eq!(mip_sizes(1920, 1080).len(), MAX_MIPS);
+        assert_eq!(mip_sizes(1920, 1080)[..3], [(960, 540), (480, 270), (240, 135)]);
+        // Stops on the shorter side.
+        assert_eq!(mip_sizes(200, 40), vec![(100, 20), (50, 10)]);
+        assert!(mip_sizes(15, 1000).is_empty());
+        assert!(mip_sizes(0, 0).is_empty());
+    }
+}
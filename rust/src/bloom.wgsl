@@ -0,0 +1,85 @@
+struct BloomUniform {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    radius: f32,
+}
+
+@group(1) @binding(0)
+var<uniform> bloom: BloomUniform;
+
+fn sample_offset(uv: vec2<f32>, texel: vec2<f32>, x: f32, y: f32) -> vec3<f32> {
+    return textureSample(t_input, s_input, uv + vec2<f32>(x, y) * texel).rgb;
+}
+
+// The 13 tap downsample from Call of Duty: Advanced Warfare. The
+// overlapping boxes keep the result stable as things move.
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_input));
+
+    let a = sample_offset(uv, texel, -2.0, 2.0);
+    let b = sample_offset(uv, texel, 0.0, 2.0);
+    let c = sample_offset(uv, texel, 2.0, 2.0);
+    let d = sample_offset(uv, texel, -2.0, 0.0);
+    let e = sample_offset(uv, texel, 0.0, 0.0);
+    let f = sample_offset(uv, texel, 2.0, 0.0);
+    let g = sample_offset(uv, texel, -2.0, -2.0);
+    let h = sample_offset(uv, texel, 0.0, -2.0);
+    let i = sample_offset(uv, texel, 2.0, -2.0);
+    let j = sample_offset(uv, texel, -1.0, 1.0);
+    let k = sample_offset(uv, texel, 1.0, 1.0);
+    let l = sample_offset(uv, texel, -1.0, -1.0);
+    let m = sample_offset(uv, texel, 1.0, -1.0);
+
+    return e * 0.125
+        + (a + c + g + i) * 0.03125
+        + (b + d + f + h) * 0.0625
+        + (j + k + l + m) * 0.125;
+}
+
+// A 3x3 tent filter whose footprint is set by the radius, in UV units.
+fn upsample(uv: vec2<f32>) -> vec3<f32> {
+    let r = vec2<f32>(bloom.radius);
+
+    let a = sample_offset(uv, r, -1.0, 1.0);
+    let b = sample_offset(uv, r, 0.0, 1.0);
+    let c = sample_offset(uv, r, 1.0, 1.0);
+    let d = sample_offset(uv, r, -1.0, 0.0);
+    let e = sample_offset(uv, r, 0.0, 0.0);
+    let f = sample_offset(uv, r, 1.0, 0.0);
+    let g = sample_offset(uv, r, -1.0, -1.0);
+    let h = sample_offset(uv, r, 0.0, -1.0);
+    let i = sample_offset(uv, r, 1.0, -1.0);
+
+    return (e * 4.0 + (b + d + f + h) * 2.0 + (a + c + g + i)) / 16.0;
+}
+
+// Keeps what is brighter than the threshold, with a quadratic knee so
+// the cut-off isn't visible.
+fn bright_pass(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    var soft = clamp(brightness - bloom.threshold + bloom.knee, 0.0, 2.0 * bloom.knee);
+    soft = soft * soft / (4.0 * bloom.knee + 1e-4);
+    let contribution = max(soft, brightness - bloom.threshold) / max(brightness, 1e-4);
+    return color * contribution;
+}
+
+@fragment
+fn fs_prefilter(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(bright_pass(downsample(in.uv)), 1.0);
+}
+
+@fragment
+fn fs_downsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample(in.uv), 1.0);
+}
+
+@fragment
+fn fs_upsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(upsample(in.uv), 1.0);
+}
+
+@fragment
+fn fs_composite(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(upsample(in.uv) * bloom.intensity, 0.0);
+}
@@ -0,0 +1,157 @@
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector3};
+use winit::keyboard::KeyCode;
+
+use crate::uniform::WgslType;
+use crate::wgsl_layout;
+
+/// The WGSL side of [`CameraUniform`], bound at group 0.
+pub const CAMERA_WGSL: &str = include_str!("camera.wgsl");
+
+/// cgmath builds OpenGL style projections with depth in -1..1, wgpu wants
+/// 0..1.
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+#[derive(Copy, Clone, Debug)]
+pub struct Camera {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    pub aspect: f32,
+    /// Vertical field of view in degrees.
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+impl Camera {
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        OPENGL_TO_WGPU_MATRIX * cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar)
+    }
+
+    pub fn build_view_projection_matrix(&self) -> Matrix4<f32> {
+        self.projection_matrix() * self.view_matrix()
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    view_position: [f32; 4],
+    view: [[f32; 4]; 4],
+    view_proj: [[f32; 4]; 4],
+    /// Takes clip space positions back to the world, for passes that start
+    /// from a depth buffer.
+    inv_view_proj: [[f32; 4]; 4],
+}
+
+wgsl_layout!(CameraUniform {
+    view_position: WgslType::Vec4,
+    view: WgslType::Mat4x4,
+    view_proj: WgslType::Mat4x4,
+    inv_view_proj: WgslType::Mat4x4,
+});
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self {
+            view_position: [0.0; 4],
+            view: Matrix4::identity().into(),
+            view_proj: Matrix4::identity().into(),
+            inv_view_proj: Matrix4::identity().into(),
+        }
+    }
+}
+
+impl CameraUniform {
+    pub fn update_view_proj(&mut self, camera: &Camera) {
+        self.view_position = camera.eye.to_homogeneous().into();
+        self.view = camera.view_matrix().into();
+        let view_proj = camera.build_view_projection_matrix();
+        self.view_proj = view_proj.into();
+        self.inv_view_proj = view_proj.invert().unwrap_or(Matrix4::identity()).into();
+    }
+}
+
+/// Orbits the camera around its target. W/S move in and out, A/D circle
+/// around, Space/Shift raise and lower the eye.
+pub struct CameraController {
+    speed: f32,
+    is_forward_pressed: bool,
+    is_backward_pressed: bool,
+    is_left_pressed: bool,
+    is_right_pressed: bool,
+    is_up_pressed: bool,
+    is_down_pressed: bool,
+}
+
+impl CameraController {
+    pub fn new(speed: f32) -> Self {
+        Self {
+            speed,
+            is_forward_pressed: false,
+            is_backward_pressed: false,
+            is_left_pressed: false,
+            is_right_pressed: false,
+            is_up_pressed: false,
+            is_down_pressed: false,
+        }
+    }
+
+    /// Returns whether the key was one of the camera's.
+    pub fn process_keyboard(&mut self, code: KeyCode, is_pressed: bool) -> bool {
+        match code {
+            KeyCode::KeyW => self.is_forward_pressed = is_pressed,
+            KeyCode::KeyS => self.is_backward_pressed = is_pressed,
+            KeyCode::KeyA => self.is_left_pressed = is_pressed,
+            KeyCode::KeyD => self.is_right_pressed = is_pressed,
+            KeyCode::Space => self.is_up_pressed = is_pressed,
+            KeyCode::ShiftLeft => self.is_down_pressed = is_pressed,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn update_camera(&self, camera: &mut Camera) {
+        let forward = camera.target - camera.eye;
+        let forward_norm = forward.normalize();
+        let forward_mag = forward.magnitude();
+
+        // Stop short of the target so the view direction stays defined.
+        if self.is_forward_pressed && forward_mag > self.speed {
+            camera.eye += forward_norm * self.speed;
+        }
+        if self.is_backward_pressed {
+            camera.eye -= forward_norm * self.speed;
+        }
+
+        let right = forward_norm.cross(camera.up);
+
+        // Redo the radius calculation in case forward/backward moved the eye.
+        let forward = camera.target - camera.eye;
+        let forward_mag = forward.magnitude();
+
+        if self.is_right_pressed {
+            camera.eye = camera.target - (forward - right * self.speed).normalize() * forward_mag;
+        }
+        if self.is_left_pressed {
+            camera.eye = camera.target - (forward + right * self.speed).normalize() * forward_mag;
+        }
+
+        if self.is_up_pressed {
+            camera.eye.y += self.speed;
+        }
+        if self.is_down_pressed {
+            camera.eye.y -= self.speed;
+        }
+    }
+}
@@ -0,0 +1,21 @@
+struct Camera {
+    view_pos: vec4<f32>,
+    view: mat4x4<f32>,
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+// The world position under `uv` at `depth`, `uv` being (0, 0) in the top
+// left corner.
+fn world_position_at(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = camera.inv_view_proj * ndc;
+    return world.xyz / world.w;
+}
+
+// Distance from the camera plane to `world_position`.
+fn view_depth_of(world_position: vec3<f32>) -> f32 {
+    return -(camera.view * vec4<f32>(world_position, 1.0)).z;
+}
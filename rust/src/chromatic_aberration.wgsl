@@ -0,0 +1,9 @@
+// params: x = strength in pixels at the screen edge
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let offset = (in.uv - vec2<f32>(0.5, 0.5)) * 2.0 * post.params.x * post.texel_size;
+    let r = textureSample(t_input, s_input, in.uv + offset).r;
+    let ga = textureSample(t_input, s_input, in.uv).ga;
+    let b = textureSample(t_input, s_input, in.uv - offset).b;
+    return vec4<f32>(r, ga.x, b, ga.y);
+}
@@ -0,0 +1,372 @@
+use cgmath::{InnerSpace, Matrix, Matrix4, MetricSpace, SquareMatrix, Vector4};
+use wgpu::util::DeviceExt;
+
+use crate::camera::Camera;
+use crate::light::{PointLight, PointLightRaw, POINT_LIGHT};
+use crate::uniform::{UniformBuffer, WgslType};
+use crate::wgsl_layout;
+
+// Must match the constants in point_lights.wgsl.
+const CLUSTER_X: u32 = 16;
+const CLUSTER_Y: u32 = 9;
+const CLUSTER_Z: u32 = 24;
+const CLUSTER_COUNT: u32 = CLUSTER_X * CLUSTER_Y * CLUSTER_Z;
+const MAX_LIGHTS_PER_CLUSTER: u32 = 64;
+const WORKGROUP_SIZE: [u32; 3] = [4, 3, 4];
+/// How many point lights the clustered path has room for.
+pub const MAX_POINT_LIGHTS: usize = 1024;
+/// How many point lights the WebGL2 fallback shades, the ones nearest the
+/// camera win.
+pub const MAX_UNIFORM_POINT_LIGHTS: usize = 128;
+
+const POINT_LIGHTS_WGSL: &str = include_str!("point_lights.wgsl");
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ClustersUniform {
+    inv_proj: [[f32; 4]; 4],
+    view: [[f32; 4]; 4],
+    screen_size: [f32; 2],
+    z_near: f32,
+    z_far: f32,
+    light_count: u32,
+}
+
+wgsl_layout!(ClustersUniform {
+    inv_proj: WgslType::Mat4x4,
+    view: WgslType::Mat4x4,
+    screen_size: WgslType::Vec2,
+    z_near: WgslType::F32,
+    z_far: WgslType::F32,
+    light_count: WgslType::U32,
+});
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointLightsUniform {
+    lights: [PointLightRaw; MAX_UNIFORM_POINT_LIGHTS],
+    count: u32,
+}
+
+wgsl_layout!(PointLightsUniform {
+    lights: WgslType::Array(&POINT_LIGHT, MAX_UNIFORM_POINT_LIGHTS),
+    count: WgslType::U32,
+});
+
+/// Finds the point lights that can touch each pixel.
+///
+/// Where there are compute shaders and storage buffers, the view frustum is
+/// cut into a grid of clusters and a compute pass lists the lights touching
+/// each one, so a fragment only walks the lights of its own cluster. Under
+/// WebGL2 the lights nearest the camera go into a uniform array that every
+/// fragment walks in full.
+pub struct LightClusters {
+    path: ClusterPath,
+}
+
+enum ClusterPath {
+    Clustered {
+        uniform: UniformBuffer<ClustersUniform>,
+        lights: wgpu::Buffer,
+        cluster_lights: wgpu::Buffer,
+        cull_pipeline: wgpu::ComputePipeline,
+        cull_bind_group: wgpu::BindGroup,
+    },
+    Uniform {
+        uniform: UniformBuffer<PointLightsUniform>,
+    },
+}
+
+impl LightClusters {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let limits = device.limits();
+        if limits.max_storage_buffers_per_shader_stage < 2 || limits.max_compute_workgroups_per_dimension == 0 {
+            log::info!("No compute shaders, shading at most {} point lights", MAX_UNIFORM_POINT_LIGHTS);
+            let uniform = UniformBuffer::new(
+                device,
+                &bytemuck::Zeroable::zeroed(),
+                wgpu::ShaderStages::FRAGMENT,
+                "point_lights",
+            );
+            return Self {
+                path: ClusterPath::Uniform { uniform },
+            };
+        }
+
+        let uniform = UniformBuffer::new(
+            device,
+            &bytemuck::Zeroable::zeroed(),
+            wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+            "clusters",
+        );
+        let lights = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("point_lights"),
+            size: (MAX_POINT_LIGHTS * std::mem::size_of::<PointLightRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // Zeroed, so every cluster reads as empty until the first cull.
+        let cluster_lights = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("cluster_lights"),
+            contents: bytemuck::cast_slice(&vec![0u32; (CLUSTER_COUNT * (MAX_LIGHTS_PER_CLUSTER + 1)) as usize]),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let cull_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[storage_entry(0, true), storage_entry(1, false)],
+            label: Some("cluster_cull_bind_group_layout"),
+        });
+        let cull_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &cull_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: lights.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: cluster_lights.as_entire_binding(),
+                },
+            ],
+            label: Some("cluster_cull_bind_group"),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("cluster_cull.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                format!("{}\n{}", POINT_LIGHTS_WGSL, include_str!("cluster_cull.wgsl")).into(),
+            ),
+        });
+        let cull_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cluster Cull Pipeline Layout"),
+            bind_group_layouts: &[&uniform.bind_group_layout, &cull_layout],
+            push_constant_ranges: &[],
+        });
+        let cull_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Cluster Cull Pipeline"),
+            layout: Some(&cull_pipeline_layout),
+            module: &shader,
+            entry_point: Some("cs_main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        Self {
+            path: ClusterPath::Clustered {
+                uniform,
+                lights,
+                cluster_lights,
+                cull_pipeline,
+                cull_bind_group,
+            },
+        }
+    }
+
+    /// The WGSL that defines `point_light_cluster`, `point_light_count` and
+    /// `point_light` for whichever path is in use, bound at group 1 from
+    /// binding 3 on.
+    pub fn wgsl(&self) -> String {
+        let lookup = match self.path {
+            ClusterPath::Clustered { .. } => include_str!("point_lights_clustered.wgsl"),
+            ClusterPath::Uniform { .. } => include_str!("point_lights_uniform.wgsl"),
+        };
+        format!("{}\n{}", POINT_LIGHTS_WGSL, lookup)
+    }
+
+    /// The bindings the lit shaders read the point lights from, starting at
+    /// `first_binding`.
+    pub fn sampling_resources(
+        &self,
+        first_binding: u32,
+    ) -> Vec<(wgpu::BindGroupLayoutEntry, wgpu::BindingResource<'_>)> {
+        match &self.path {
+            ClusterPath::Clustered {
+                uniform,
+                lights,
+                cluster_lights,
+                ..
+            } => {
+                let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+                    binding,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                };
+                vec![
+                    (
+                        UniformBuffer::<ClustersUniform>::layout_entry(first_binding, wgpu::ShaderStages::FRAGMENT),
+                        uniform.buffer.as_entire_binding(),
+                    ),
+                    (storage_entry(first_binding + 1), lights.as_entire_binding()),
+                    (storage_entry(first_binding + 2), cluster_lights.as_entire_binding()),
+                ]
+            }
+            ClusterPath::Uniform { uniform } => vec![(
+                UniformBuffer::<PointLightsUniform>::layout_entry(first_binding, wgpu::ShaderStages::FRAGMENT),
+                uniform.buffer.as_entire_binding(),
+            )],
+        }
+    }
+
+    /// Uploads `lights` as seen from `camera` onto a `width` by `height`
+    /// target.
+    pub fn update(&self, queue: &wgpu::Queue, lights: &[PointLight], camera: &Camera, width: u32, height: u32) {
+        match &self.path {
+            ClusterPath::Clustered { uniform, lights: buffer, .. } => {
+                if lights.len() > MAX_POINT_LIGHTS {
+                    log::warn!("{} point lights given, only the first {} are used", lights.len(), MAX_POINT_LIGHTS);
+                }
+                let raw: Vec<_> = lights.iter().take(MAX_POINT_LIGHTS).map(PointLight::to_raw).collect();
+                if !raw.is_empty() {
+                    queue.write_buffer(buffer, 0, bytemuck::cast_slice(&raw));
+                }
+                uniform.update(queue, &ClustersUniform {
+                    inv_proj: camera.projection_matrix().invert().unwrap_or(Matrix4::identity()).into(),
+                    view: camera.view_matrix().into(),
+                    screen_size: [width as f32, height as f32],
+                    z_near: camera.znear,
+                    z_far: camera.zfar,
+                    light_count: raw.len() as u32,
+                });
+            }
+            ClusterPath::Uniform { uniform } => {
+                let visible = nearest_visible(lights, camera);
+                let mut raw: PointLightsUniform = bytemuck::Zeroable::zeroed();
+                for (raw, light) in raw.lights.iter_mut().zip(visible.iter()) {
+                    *raw = light.to_raw();
+                }
+                raw.count = visible.len().min(MAX_UNIFORM_POINT_LIGHTS) as u32;
+                uniform.update(queue, &raw);
+            }
+        }
+    }
+
+    /// Sorts the lights into clusters for this frame's camera. Record it
+    /// before any pass that shades with them, it does nothing on the
+    /// fallback path.
+    pub fn cull(&self, encoder: &mut wgpu::CommandEncoder) {
+        let ClusterPath::Clustered {
+            uniform,
+            cull_pipeline,
+            cull_bind_group,
+            ..
+        } = &self.path
+        else {
+            return;
+        };
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Cluster Cull Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(cull_pipeline);
+        pass.set_bind_group(0, &uniform.bind_group, &[]);
+        pass.set_bind_group(1, cull_bind_group, &[]);
+        pass.dispatch_workgroups(
+            CLUSTER_X.div_ceil(WORKGROUP_SIZE[0]),
+            CLUSTER_Y.div_ceil(WORKGROUP_SIZE[1]),
+            CLUSTER_Z.div_ceil(WORKGROUP_SIZE[2]),
+        );
+    }
+}
+
+/// The lights `camera` can see any of, nearest first.
+fn nearest_visible<'a>(lights: &'a [PointLight], camera: &Camera) -> Vec<&'a PointLight> {
+    let view_proj = camera.build_view_projection_matrix();
+    let mut visible: Vec<_> = lights.iter().filter(|light| in_frustum(&view_proj, light)).collect();
+    visible.sort_by(|a, b| {
+        let a = a.position.distance2(camera.eye);
+        let b = b.position.distance2(camera.eye);
+        a.total_cmp(&b)
+    });
+    visible
+}
+
+/// Whether `light`'s sphere reaches into the frustum of `view_proj`, which
+/// has depth in 0..1.
+fn in_frustum(view_proj: &Matrix4<f32>, light: &PointLight) -> bool {
+    let rows: [Vector4<f32>; 4] = [0, 1, 2, 3].map(|i| view_proj.row(i));
+    let planes = [
+        rows[3] + rows[0],
+        rows[3] - rows[0],
+        rows[3] + rows[1],
+        rows[3] - rows[1],
+        rows[2],
+        rows[3] - rows[2],
+    ];
+    let center = light.position.to_homogeneous();
+    planes
+        .iter()
+        .all(|plane| plane.dot(center) >= -light.range * plane.truncate().magnitude())
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point3, Vector3};
+
+    use super::*;
+
+    fn light(x: f32, y: f32, z: f32, range: f32) -> PointLight {
+        PointLight {
+            position: Point3::new(x, y, z),
+            color: [1.0; 3],
+            intensity: 1.0,
+            range,
+        }
+    }
+
+    #[test]
+    fn constants_match_the_shader() {
+        for (name, value) in [
+            ("CLUSTER_X", CLUSTER_X),
+            ("CLUSTER_Y", CLUSTER_Y),
+            ("CLUSTER_Z", CLUSTER_Z),
+            ("MAX_LIGHTS_PER_CLUSTER", MAX_LIGHTS_PER_CLUSTER),
+            ("MAX_UNIFORM_POINT_LIGHTS", MAX_UNIFORM_POINT_LIGHTS as u32),
+        ] {
+            let declaration = format!("const {}: u32 = {}u;", name, value);
+            assert!(POINT_LIGHTS_WGSL.contains(&declaration), "{} isn't {} in point_lights.wgsl", name, value);
+        }
+        let workgroup_size = format!("@workgroup_size({}, {}, {})", WORKGROUP_SIZE[0], WORKGROUP_SIZE[1], WORKGROUP_SIZE[2]);
+        assert!(include_str!("cluster_cull.wgsl").contains(&workgroup_size));
+    }
+
+    #[test]
+    fn fallback_keeps_visible_lights_nearest_first() {
+        let camera = Camera {
+            eye: Point3::new(0.0, 0.0, 0.0),
+            target: Point3::new(0.0, 0.0, -1.0),
+            up: Vector3::unit_y(),
+            aspect: 1.0,
+            fovy: 90.0,
+            znear: 0.1,
+            zfar: 100.0,
+        };
+        let lights = [
+            light(0.0, 0.0, -20.0, 1.0),
+            // Behind the camera.
+            light(0.0, 0.0, 5.0, 1.0),
+            light(1.0, 0.0, -5.0, 1.0),
+            // Off to the side, but reaching into view.
+            light(12.0, 0.0, -10.0, 3.0),
+            light(30.0, 0.0, -10.0, 3.0),
+            // Past the far plane.
+            light(0.0, 0.0, -110.0, 5.0),
+        ];
+        let visible: Vec<_> = nearest_visible(&lights, &camera).iter().map(|light| light.position).collect();
+        assert_eq!(visible, [lights[2].position, lights[3].position, lights[0].position]);
+    }
+}
@@ -0,0 +1,51 @@
+@group(0) @binding(0)
+var<uniform> clusters: Clusters;
+@group(1) @binding(0)
+var<storage, read> point_lights: array<PointLight>;
+@group(1) @binding(1)
+var<storage, read_write> cluster_lights: array<u32>;
+
+fn view_from_ndc(ndc: vec2<f32>) -> vec3<f32> {
+    let view = clusters.inv_proj * vec4<f32>(ndc, 0.0, 1.0);
+    return view.xyz / view.w;
+}
+
+// One invocation per cluster: bound the cluster with a view space box and
+// keep every light whose sphere touches it.
+@compute @workgroup_size(4, 3, 4)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= CLUSTER_X || id.y >= CLUSTER_Y || id.z >= CLUSTER_Z) {
+        return;
+    }
+
+    // Tiles go left to right and top to bottom, like fragment coordinates.
+    let tile = vec2<f32>(2.0 / f32(CLUSTER_X), 2.0 / f32(CLUSTER_Y));
+    let ndc_min = vec2<f32>(-1.0 + f32(id.x) * tile.x, 1.0 - f32(id.y + 1u) * tile.y);
+    let ndc_max = vec2<f32>(-1.0 + f32(id.x + 1u) * tile.x, 1.0 - f32(id.y) * tile.y);
+    let corner_min = view_from_ndc(ndc_min);
+    let corner_max = view_from_ndc(ndc_max);
+    let near = cluster_slice_depth(clusters, id.z);
+    let far = cluster_slice_depth(clusters, id.z + 1u);
+    // The tile's corner rays, cut off at both ends of the slice. The view
+    // looks down -Z.
+    let a = corner_min * (near / -corner_min.z);
+    let b = corner_min * (far / -corner_min.z);
+    let c = corner_max * (near / -corner_max.z);
+    let d = corner_max * (far / -corner_max.z);
+    let box_min = min(min(a, b), min(c, d));
+    let box_max = max(max(a, b), max(c, d));
+
+    let cluster = id.x + id.y * CLUSTER_X + id.z * CLUSTER_X * CLUSTER_Y;
+    let base = cluster * (MAX_LIGHTS_PER_CLUSTER + 1u);
+    var count = 0u;
+    for (var i = 0u; i < clusters.light_count && count < MAX_LIGHTS_PER_CLUSTER; i += 1u) {
+        let light = point_lights[i];
+        let center = (clusters.view * vec4<f32>(light.position_range.xyz, 1.0)).xyz;
+        let offset = clamp(center, box_min, box_max) - center;
+        if (dot(offset, offset) <= light.position_range.w * light.position_range.w) {
+            cluster_lights[base + 1u + count] = i;
+            count += 1u;
+        }
+    }
+    cluster_lights[base] = count;
+}
@@ -0,0 +1,14 @@
+// The direction through `uv` on cube face `face`, with faces in +X, -X,
+// +Y, -Y, +Z, -Z order and `uv` = (0, 0) in the face's top left corner,
+// matching how the GPU picks a face and texel when sampling a cube.
+fn cube_face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let p = uv * 2.0 - vec2<f32>(1.0);
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -p.y, -p.x); }
+        case 1u: { return vec3<f32>(-1.0, -p.y, p.x); }
+        case 2u: { return vec3<f32>(p.x, 1.0, p.y); }
+        case 3u: { return vec3<f32>(p.x, -1.0, -p.y); }
+        case 4u: { return vec3<f32>(p.x, -p.y, 1.0); }
+        default: { return vec3<f32>(-p.x, -p.y, -1.0); }
+    }
+}
@@ -0,0 +1,337 @@
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+
+use crate::camera::CAMERA_WGSL;
+use crate::texture::Texture;
+
+/// Segments in each circle of [`DebugDraw::circle`] and
+/// [`DebugDraw::sphere`].
+const CIRCLE_SEGMENTS: usize = 32;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+impl LineVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+/// World space lines drawn over the scene for one frame. Shapes added
+/// during `update` are drawn by the next render and then forgotten, so they
+/// have to be added again every frame they should stay up.
+///
+/// While disabled every shape is ignored, callers don't need to check.
+pub struct DebugDraw {
+    pub enabled: bool,
+    /// Two vertices per line.
+    vertices: Vec<LineVertex>,
+    /// Made by the first [`DebugDraw::prepare`].
+    vertex_buffer: Option<wgpu::Buffer>,
+    vertex_count: u32,
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            vertices: Vec::new(),
+            vertex_buffer: None,
+            vertex_count: 0,
+        }
+    }
+
+    pub fn line(&mut self, from: Point3<f32>, to: Point3<f32>, color: [f32; 4]) {
+        if !self.enabled {
+            return;
+        }
+        self.vertices.extend([from, to].map(|position| LineVertex {
+            position: position.into(),
+            color,
+        }));
+    }
+
+    /// A line from `from` to `to` with a head at `to`.
+    pub fn arrow(&mut self, from: Point3<f32>, to: Point3<f32>, color: [f32; 4]) {
+        self.line(from, to, color);
+        let direction = to - from;
+        let length = direction.magnitude();
+        if length <= f32::EPSILON {
+            return;
+        }
+        let direction = direction / length;
+        let (side, up) = perpendicular_axes(direction);
+        let head = length.min(1.0) * 0.2;
+        let base = to - direction * head;
+        for offset in [side, -side, up, -up] {
+            self.line(to, base + offset * head * 0.5, color);
+        }
+    }
+
+    /// The box between corners `min` and `max`, aligned to the world axes.
+    pub fn aabb(&mut self, min: Point3<f32>, max: Point3<f32>, color: [f32; 4]) {
+        let transform = Matrix4::from_translation(Vector3::new(min.x, min.y, min.z))
+            * Matrix4::from_nonuniform_scale(max.x - min.x, max.y - min.y, max.z - min.z);
+        self.cuboid(transform, color);
+    }
+
+    /// The unit cube from (0, 0, 0) to (1, 1, 1) put through `transform`.
+    pub fn cuboid(&mut self, transform: Matrix4<f32>, color: [f32; 4]) {
+        let corners: [Point3<f32>; 8] = std::array::from_fn(|i| {
+            let corner = Point3::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32);
+            transform.transform_point(corner)
+        });
+        self.box_edges(&corners, color);
+    }
+
+    /// The twelve edges of a box, its corners indexed by their x, y and z
+    /// bits.
+    fn box_edges(&mut self, corners: &[Point3<f32>; 8], color: [f32; 4]) {
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+    }
+
+    pub fn circle(&mut self, center: Point3<f32>, normal: Vector3<f32>, radius: f32, color: [f32; 4]) {
+        let (u, v) = perpendicular_axes(normal.normalize());
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// Three circles around the axes.
+    pub fn sphere(&mut self, center: Point3<f32>, radius: f32, color: [f32; 4]) {
+        for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+            self.circle(center, axis, radius, color);
+        }
+    }
+
+    /// A grid on the XZ plane at `center`, `size` across with `divisions`
+    /// cells along each side.
+    pub fn grid(&mut self, center: Point3<f32>, size: f32, divisions: u32, color: [f32; 4]) {
+        let half = size * 0.5;
+        for i in 0..=divisions {
+            let offset = i as f32 / divisions.max(1) as f32 * size - half;
+            self.line(
+                center + Vector3::new(offset, 0.0, -half),
+                center + Vector3::new(offset, 0.0, half),
+                color,
+            );
+            self.line(
+                center + Vector3::new(-half, 0.0, offset),
+                center + Vector3::new(half, 0.0, offset),
+                color,
+            );
+        }
+    }
+
+    /// The x, y and z axes of `transform` in red, green and blue.
+    pub fn axes(&mut self, transform: Matrix4<f32>, length: f32) {
+        let origin = transform.transform_point(Point3::new(0.0, 0.0, 0.0));
+        let axes = [
+            (Vector3::unit_x(), [1.0, 0.0, 0.0, 1.0]),
+            (Vector3::unit_y(), [0.0, 1.0, 0.0, 1.0]),
+            (Vector3::unit_z(), [0.0, 0.0, 1.0, 1.0]),
+        ];
+        for (axis, color) in axes {
+            self.arrow(origin, transform.transform_point(Point3::new(0.0, 0.0, 0.0) + axis * length), color);
+        }
+    }
+
+    /// The volume `view_proj` projects onto the screen, such as a camera's
+    /// or a shadow map's.
+    pub fn frustum(&mut self, view_proj: Matrix4<f32>, color: [f32; 4]) {
+        let Some(inverse) = view_proj.invert() else {
+            return;
+        };
+        // wgpu's clip space runs from 0 to 1 in z.
+        let corners: [Point3<f32>; 8] = std::array::from_fn(|i| {
+            let ndc = Point3::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { 0.0 } else { 1.0 },
+            );
+            inverse.transform_point(ndc)
+        });
+        self.box_edges(&corners, color);
+    }
+
+    /// Uploads the lines added since the last call for drawing and starts
+    /// over with none.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let size = std::mem::size_of_val(self.vertices.as_slice()) as wgpu::BufferAddress;
+        if self.vertex_buffer.as_ref().is_none_or(|buffer| size > buffer.size()) {
+            self.vertex_buffer = Some(create_vertex_buffer(device, size.max(4096).next_power_of_two()));
+        }
+        let vertex_buffer = self.vertex_buffer.as_ref().unwrap();
+        queue.write_buffer(vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+        self.vertex_count = self.vertices.len() as u32;
+        self.vertices.clear();
+    }
+
+    /// Draws the prepared lines with the pipeline from [`create_pipeline`],
+    /// which has to be bound already along with the camera at group 0.
+    pub fn draw(&self, pass: &mut wgpu::RenderPass) {
+        let Some(vertex_buffer) = &self.vertex_buffer else {
+            return;
+        };
+        if self.vertex_count == 0 {
+            return;
+        }
+        pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        pass.draw(0..self.vertex_count, 0..1);
+    }
+}
+
+/// Two unit vectors perpendicular to `direction` and to each other.
+fn perpendicular_axes(direction: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let helper = if direction.y.abs() > 0.99 { Vector3::unit_x() } else { Vector3::unit_y() };
+    let u = direction.cross(helper).normalize();
+    (u, direction.cross(u))
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("debug_draw_vertex_buffer"),
+        size,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Lines drawn over everything, ignoring depth so nothing hides them.
+pub fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Debug Draw Shader"),
+        source: wgpu::ShaderSource::Wgsl(format!("{}\n{}", CAMERA_WGSL, include_str!("debug_draw.wgsl")).into()),
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Debug Draw Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[LineVertex::desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: color_format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::LineList,
+            ..Default::default()
+        },
+        // The pass has a depth attachment, so the pipeline needs a depth
+        // state, it just never fails.
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [f32; 4] = [1.0; 4];
+
+    fn enabled() -> DebugDraw {
+        DebugDraw {
+            enabled: true,
+            ..DebugDraw::new()
+        }
+    }
+
+    fn lines(draw: &DebugDraw) -> Vec<(Point3<f32>, Point3<f32>)> {
+        draw.vertices
+            .chunks(2)
+            .map(|line| (line[0].position.into(), line[1].position.into()))
+            .collect()
+    }
+
+    #[test]
+    fn aabb_draws_the_twelve_edges() {
+        let mut draw = enabled();
+        let (min, max) = (Point3::new(-1.0, 0.0, 2.0), Point3::new(1.0, 3.0, 4.0));
+        draw.aabb(min, max, WHITE);
+        let lines = lines(&draw);
+        assert_eq!(lines.len(), 12);
+        for (from, to) in lines {
+            // Along one axis, from corner to corner.
+            let differing = [from.x != to.x, from.y != to.y, from.z != to.z];
+            assert_eq!(differing.iter().filter(|&&d| d).count(), 1, "{:?} to {:?}", from, to);
+            for corner in [from, to] {
+                assert!([min.x, max.x].contains(&corner.x));
+                assert!([min.y, max.y].contains(&corner.y));
+                assert!([min.z, max.z].contains(&corner.z));
+            }
+        }
+    }
+
+    #[test]
+    fn circle_closes_on_its_radius() {
+        let mut draw = enabled();
+        let center = Point3::new(1.0, 2.0, 3.0);
+        draw.circle(center, Vector3::new(0.0, 0.0, 2.0), 0.5, WHITE);
+        let lines = lines(&draw);
+        assert_eq!(lines.len(), CIRCLE_SEGMENTS);
+        for (i, (from, to)) in lines.iter().enumerate() {
+            assert!(((from - center).magnitude() - 0.5).abs() < 1e-5);
+            assert!((from.z - center.z).abs() < 1e-5, "the circle lies across its normal");
+            let next = lines[(i + 1) % lines.len()].0;
+            assert!((to - next).magnitude() < 1e-5, "segment {} doesn't meet the next", i);
+        }
+
+        draw.vertices.clear();
+        draw.sphere(center, 1.0, WHITE);
+        assert_eq!(draw.vertices.len(), 2 * 3 * CIRCLE_SEGMENTS);
+    }
+
+    #[test]
+    fn nothing_is_added_while_disabled() {
+        let mut draw = DebugDraw::new();
+        draw.aabb(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0), WHITE);
+        draw.arrow(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0), WHITE);
+        assert!(draw.vertices.is_empty());
+    }
+}
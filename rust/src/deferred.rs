@@ -0,0 +1,390 @@
+use crate::light::Lights;
+use crate::material;
+use crate::mesh;
+use crate::post;
+use crate::ssao::Ssao;
+use crate::texture::Texture;
+use crate::uniform::{UniformBuffer, WgslType};
+use crate::wgsl_layout;
+
+/// The G-buffer channels the debug view can show, in the order
+/// gbuffer_debug.wgsl expects them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GBufferChannel {
+    Albedo,
+    Normal,
+    Metallic,
+    Roughness,
+    Occlusion,
+    AmbientOcclusion,
+    Depth,
+}
+
+impl GBufferChannel {
+    pub const ALL: [GBufferChannel; 7] = [
+        GBufferChannel::Albedo,
+        GBufferChannel::Normal,
+        GBufferChannel::Metallic,
+        GBufferChannel::Roughness,
+        GBufferChannel::Occlusion,
+        GBufferChannel::AmbientOcclusion,
+        GBufferChannel::Depth,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GBufferChannel::Albedo => "albedo",
+            GBufferChannel::Normal => "normal",
+            GBufferChannel::Metallic => "metallic",
+            GBufferChannel::Roughness => "roughness",
+            GBufferChannel::Occlusion => "occlusion",
+            GBufferChannel::AmbientOcclusion => "ssao",
+            GBufferChannel::Depth => "depth",
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GBufferDebugUniform {
+    channel: u32,
+    z_near: f32,
+    z_far: f32,
+}
+
+wgsl_layout!(GBufferDebugUniform {
+    channel: WgslType::U32,
+    z_near: WgslType::F32,
+    z_far: WgslType::F32,
+});
+
+/// The screen sized targets the geometry pass writes. Emissive light goes
+/// straight into the scene target instead.
+struct GBuffer {
+    albedo_occlusion: Texture,
+    normal: Texture,
+    metallic_roughness: Texture,
+    depth: Texture,
+}
+
+impl GBuffer {
+    /// The G-buffer followed by the ambient occlusion. Sampled with
+    /// `textureLoad` only, so every texture is bound as unfilterable.
+    fn layout_entries(first_binding: u32) -> [wgpu::BindGroupLayoutEntry; 5] {
+        [0, 1, 2, 3, 4].map(|i| wgpu::BindGroupLayoutEntry {
+            binding: first_binding + i,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        })
+    }
+
+    fn resources<'a>(&'a self, first_binding: u32, ssao: &'a Ssao) -> [(u32, wgpu::BindingResource<'a>); 5] {
+        [
+            (first_binding, wgpu::BindingResource::TextureView(&self.albedo_occlusion.view)),
+            (first_binding + 1, wgpu::BindingResource::TextureView(&self.normal.view)),
+            (first_binding + 2, wgpu::BindingResource::TextureView(&self.metallic_roughness.view)),
+            (first_binding + 3, wgpu::BindingResource::TextureView(&self.depth.view)),
+            (first_binding + 4, wgpu::BindingResource::TextureView(ssao.view())),
+        ]
+    }
+}
+
+/// Deferred shading: the meshes write their surfaces into a G-buffer, then
+/// one fullscreen pass lights every pixel with the same `shade` function the
+/// forward path uses.
+///
+/// There is no MSAA on this path, the G-buffer is a single sample per pixel.
+/// In exchange the ambient light gets screen space ambient occlusion.
+pub struct Deferred {
+    pub enabled: bool,
+    pub debug_channel: Option<GBufferChannel>,
+    pub ssao: Ssao,
+    gbuffer: GBuffer,
+    gbuffer_layout: wgpu::BindGroupLayout,
+    gbuffer_bind_group: wgpu::BindGroup,
+    geometry_pipeline: wgpu::RenderPipeline,
+    lighting_pipeline: wgpu::RenderPipeline,
+    debug_uniform: UniformBuffer<GBufferDebugUniform>,
+    debug_pipeline: wgpu::RenderPipeline,
+    scene_format: wgpu::TextureFormat,
+}
+
+impl Deferred {
+    /// `pbr_layout` is the layout the forward PBR pipeline is built with,
+    /// camera, lights, material and environment. The lighting pass is
+    /// drawn into a `scene_format` target and the debug view into an
+    /// `output_format` one.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &wgpu::Device,
+        pbr_layout: &wgpu::PipelineLayout,
+        camera_layout: &wgpu::BindGroupLayout,
+        lights: &Lights,
+        environment_layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+        scene_format: wgpu::TextureFormat,
+        output_format: wgpu::TextureFormat,
+    ) -> Self {
+        let gbuffer = create_gbuffer(device, width, height, scene_format);
+        let ssao = Ssao::new(device, camera_layout, width, height, &gbuffer.normal.view, &gbuffer.depth.view);
+        let gbuffer_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &GBuffer::layout_entries(0),
+            label: Some("gbuffer_bind_group_layout"),
+        });
+        let gbuffer_bind_group = create_gbuffer_bind_group(device, &gbuffer_layout, &gbuffer, &ssao);
+
+        let shader = device.create_shader_module(material::pbr_shader(&lights.wgsl()));
+        let target = |format| {
+            Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })
+        };
+        let geometry_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("G-Buffer Pipeline"),
+            layout: Some(pbr_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[mesh::MeshVertex::desc(), mesh::InstanceRaw::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_gbuffer"),
+                targets: &[
+                    target(gbuffer.albedo_occlusion.texture.format()),
+                    target(gbuffer.normal.texture.format()),
+                    target(gbuffer.metallic_roughness.texture.format()),
+                    target(scene_format),
+                ],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let lighting_pipeline = post::create_fullscreen_pipeline(
+            device,
+            &[camera_layout, lights.bind_group_layout(), &gbuffer_layout, environment_layout],
+            scene_format,
+            post::ADDITIVE,
+            &format!(
+                "{}\n{}",
+                material::lighting_wgsl(&lights.wgsl()),
+                include_str!("deferred_lighting.wgsl")
+            ),
+            "fs_main",
+            "Deferred Lighting Pipeline",
+        );
+
+        let debug_uniform = UniformBuffer::with_resources(
+            device,
+            &bytemuck::Zeroable::zeroed(),
+            wgpu::ShaderStages::FRAGMENT,
+            &GBuffer::layout_entries(1)
+                .into_iter()
+                .zip(gbuffer.resources(1, &ssao).map(|(_, resource)| resource))
+                .collect::<Vec<_>>(),
+            "gbuffer_debug",
+        );
+        let debug_pipeline = post::create_fullscreen_pipeline(
+            device,
+            &[&debug_uniform.bind_group_layout],
+            output_format,
+            wgpu::BlendState::REPLACE,
+            include_str!("gbuffer_debug.wgsl"),
+            "fs_main",
+            "G-Buffer Debug Pipeline",
+        );
+
+        Self {
+            enabled: false,
+            debug_channel: None,
+            ssao,
+            gbuffer,
+            gbuffer_layout,
+            gbuffer_bind_group,
+            geometry_pipeline,
+            lighting_pipeline,
+            debug_uniform,
+            debug_pipeline,
+            scene_format,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.gbuffer = create_gbuffer(device, width, height, self.scene_format);
+        self.ssao.resize(device, width, height, &self.gbuffer.normal.view, &self.gbuffer.depth.view);
+        self.gbuffer_bind_group = create_gbuffer_bind_group(device, &self.gbuffer_layout, &self.gbuffer, &self.ssao);
+        let debug_layout = self.debug_uniform.bind_group_layout.clone();
+        self.debug_uniform = UniformBuffer::with_layout(
+            device,
+            &bytemuck::Zeroable::zeroed(),
+            &debug_layout,
+            &self.gbuffer.resources(1, &self.ssao),
+            "gbuffer_debug",
+        );
+    }
+
+    /// The G-buffer's depth, which the forward passes drawn after the
+    /// lighting pass (sky, overlays) test against.
+    pub fn depth_view(&self) -> &wgpu::TextureView {
+        &self.gbuffer.depth.view
+    }
+
+    /// Starts the geometry pass with its pipeline bound, clearing the
+    /// G-buffer and `scene_view`, which receives the emissive light. Bind the
+    /// same groups as for the forward PBR pipeline and draw the meshes.
+    pub fn begin_geometry_pass<'a>(
+        &self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        scene_view: &wgpu::TextureView,
+        clear_color: wgpu::Color,
+    ) -> wgpu::RenderPass<'a> {
+        let attachment = |view, clear| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear),
+                    store: wgpu::StoreOp::Store,
+                },
+            })
+        };
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("G-Buffer Pass"),
+            color_attachments: &[
+                attachment(&self.gbuffer.albedo_occlusion.view, wgpu::Color::TRANSPARENT),
+                attachment(&self.gbuffer.normal.view, wgpu::Color::TRANSPARENT),
+                attachment(&self.gbuffer.metallic_roughness.view, wgpu::Color::TRANSPARENT),
+                attachment(scene_view, clear_color),
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.gbuffer.depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.geometry_pipeline);
+        pass
+    }
+
+    /// Adds the light reflected off every G-buffer pixel onto `scene_view`,
+    /// after working out the ambient occlusion.
+    pub fn light(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        scene_view: &wgpu::TextureView,
+        camera: &wgpu::BindGroup,
+        lights: &Lights,
+        environment: &wgpu::BindGroup,
+    ) {
+        self.ssao.run(queue, encoder, camera);
+        let mut pass = post::begin_fullscreen_pass(encoder, scene_view, wgpu::LoadOp::Load, "Deferred Lighting Pass");
+        pass.set_pipeline(&self.lighting_pipeline);
+        pass.set_bind_group(0, camera, &[]);
+        pass.set_bind_group(1, lights.bind_group(), &[]);
+        pass.set_bind_group(2, &self.gbuffer_bind_group, &[]);
+        pass.set_bind_group(3, environment, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    /// Shows the selected G-buffer channel over the whole of `output`.
+    pub fn draw_debug(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        z_near: f32,
+        z_far: f32,
+    ) {
+        let Some(channel) = self.debug_channel.filter(|_| self.enabled) else {
+            return;
+        };
+        self.debug_uniform.update(queue, &GBufferDebugUniform {
+            channel: channel as u32,
+            z_near,
+            z_far,
+        });
+        let mut pass = post::begin_fullscreen_pass(encoder, output, wgpu::LoadOp::Load, "G-Buffer Debug Pass");
+        pass.set_pipeline(&self.debug_pipeline);
+        pass.set_bind_group(0, &self.debug_uniform.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    /// Steps the debug view through off and then every channel.
+    pub fn cycle_debug_channel(&mut self) {
+        let channels = GBufferChannel::ALL;
+        self.debug_channel = match self.debug_channel {
+            None => Some(channels[0]),
+            Some(channel) => channels
+                .iter()
+                .position(|&c| c == channel)
+                .and_then(|i| channels.get(i + 1))
+                .copied(),
+        };
+    }
+}
+
+fn create_gbuffer(device: &wgpu::Device, width: u32, height: u32, scene_format: wgpu::TextureFormat) -> GBuffer {
+    GBuffer {
+        albedo_occlusion: Texture::create_render_target(
+            device,
+            width,
+            height,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            "gbuffer_albedo_occlusion",
+        ),
+        // Normals are stored as n * 0.5 + 0.5, in half floats where the
+        // scene has them.
+        normal: Texture::create_render_target(device, width, height, scene_format.remove_srgb_suffix(), "gbuffer_normal"),
+        metallic_roughness: Texture::create_render_target(
+            device,
+            width,
+            height,
+            wgpu::TextureFormat::Rgba8Unorm,
+            "gbuffer_metallic_roughness",
+        ),
+        depth: Texture::create_depth_texture(device, width, height, 1, "gbuffer_depth"),
+    }
+}
+
+fn create_gbuffer_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    gbuffer: &GBuffer,
+    ssao: &Ssao,
+) -> wgpu::BindGroup {
+    let entries = gbuffer.resources(0, ssao).map(|(binding, resource)| wgpu::BindGroupEntry { binding, resource });
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &entries,
+        label: Some("gbuffer_bind_group"),
+    })
+}
@@ -0,0 +1,36 @@
+@group(2) @binding(0)
+var t_albedo_occlusion: texture_2d<f32>;
+@group(2) @binding(1)
+var t_normal: texture_2d<f32>;
+@group(2) @binding(2)
+var t_metallic_roughness: texture_2d<f32>;
+@group(2) @binding(3)
+var t_depth: texture_2d<f32>;
+@group(2) @binding(4)
+var t_ambient_occlusion: texture_2d<f32>;
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(in.clip_position.xy);
+    let depth = textureLoad(t_depth, coords, 0).r;
+    // Nothing was drawn here, the sky fills it in later.
+    if (depth >= 1.0) {
+        discard;
+    }
+
+    let albedo_occlusion = textureLoad(t_albedo_occlusion, coords, 0);
+    let metallic_roughness = textureLoad(t_metallic_roughness, coords, 0);
+    var surface: Surface;
+    surface.albedo = albedo_occlusion.rgb;
+    surface.occlusion = albedo_occlusion.a * textureLoad(t_ambient_occlusion, coords, 0).r;
+    surface.normal = normalize(textureLoad(t_normal, coords, 0).xyz * 2.0 - vec3<f32>(1.0));
+    surface.metallic = metallic_roughness.r;
+    surface.roughness = metallic_roughness.g;
+
+    let world_position = world_position_at(in.uv, depth);
+    let view_depth = view_depth_of(world_position);
+    // The G-buffer only keeps the normal mapped normal, so shadow lookups
+    // are offset along that instead of the geometric one.
+    let color = shade(surface, world_position, surface.normal, view_depth, in.clip_position.xy);
+    return vec4<f32>(color, 0.0);
+}
@@ -0,0 +1,197 @@
+//! The 2D demo content drawn over the scene, one registered set at a time.
+
+use wgpu::util::DeviceExt;
+
+/// A pipeline added with [`DemoMeshes::add_pipeline`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PipelineId(usize);
+
+/// A bind group added with [`DemoMeshes::add_bind_group`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BindGroupId(usize);
+
+enum Draw {
+    Vertices(u32),
+    Indexed { buffer: wgpu::Buffer, count: u32 },
+}
+
+/// One draw call of a [`DemoMesh`], with the buffers it owns.
+pub struct DemoPart {
+    pipeline: PipelineId,
+    /// Set from group 0 up.
+    bind_groups: Vec<BindGroupId>,
+    vertex_buffer: Option<wgpu::Buffer>,
+    draw: Draw,
+}
+
+impl DemoPart {
+    /// `count` vertices the vertex shader makes up on its own, without
+    /// buffers.
+    pub fn procedural(pipeline: PipelineId, bind_groups: &[BindGroupId], count: u32) -> Self {
+        Self {
+            pipeline,
+            bind_groups: bind_groups.to_vec(),
+            vertex_buffer: None,
+            draw: Draw::Vertices(count),
+        }
+    }
+
+    /// Triangles from `vertices` and `indices`, uploaded to buffers of
+    /// their own.
+    pub fn indexed<V: bytemuck::Pod>(
+        device: &wgpu::Device,
+        label: &str,
+        pipeline: PipelineId,
+        bind_groups: &[BindGroupId],
+        vertices: &[V],
+        indices: &[u16],
+    ) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", label)),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Index Buffer", label)),
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        Self {
+            pipeline,
+            bind_groups: bind_groups.to_vec(),
+            vertex_buffer: Some(vertex_buffer),
+            draw: Draw::Indexed {
+                buffer: index_buffer,
+                count: indices.len() as u32,
+            },
+        }
+    }
+}
+
+pub struct DemoMesh {
+    pub name: String,
+    parts: Vec<DemoPart>,
+}
+
+/// Every piece of 2D demo content, of which one or none is drawn. Meshes
+/// refer to pipelines and bind groups by id, so those can be replaced for
+/// all of them at once, such as when the pipelines are rebuilt for another
+/// sample count.
+#[derive(Default)]
+pub struct DemoMeshes {
+    pipelines: Vec<wgpu::RenderPipeline>,
+    bind_groups: Vec<wgpu::BindGroup>,
+    meshes: Vec<DemoMesh>,
+    current: Option<usize>,
+}
+
+impl DemoMeshes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_pipeline(&mut self, pipeline: wgpu::RenderPipeline) -> PipelineId {
+        self.pipelines.push(pipeline);
+        PipelineId(self.pipelines.len() - 1)
+    }
+
+    pub fn set_pipeline(&mut self, id: PipelineId, pipeline: wgpu::RenderPipeline) {
+        self.pipelines[id.0] = pipeline;
+    }
+
+    pub fn add_bind_group(&mut self, bind_group: wgpu::BindGroup) -> BindGroupId {
+        self.bind_groups.push(bind_group);
+        BindGroupId(self.bind_groups.len() - 1)
+    }
+
+    pub fn set_bind_group(&mut self, id: BindGroupId, bind_group: wgpu::BindGroup) {
+        self.bind_groups[id.0] = bind_group;
+    }
+
+    /// Adds a mesh to the end of the ones [`DemoMeshes::cycle`] goes
+    /// through, drawn as `parts` in order.
+    pub fn register(&mut self, name: &str, parts: Vec<DemoPart>) {
+        self.meshes.push(DemoMesh {
+            name: name.to_string(),
+            parts,
+        });
+    }
+
+    pub fn meshes(&self) -> &[DemoMesh] {
+        &self.meshes
+    }
+
+    /// The index of the mesh being drawn.
+    pub fn current(&self) -> Option<usize> {
+        self.current
+    }
+
+    pub fn set_current(&mut self, current: Option<usize>) {
+        self.current = current.filter(|&i| i < self.meshes.len());
+    }
+
+    /// Moves on to the next mesh, or to none after the last.
+    pub fn cycle(&mut self) -> Option<&DemoMesh> {
+        self.current = match self.current {
+            None if !self.meshes.is_empty() => Some(0),
+            Some(i) if i + 1 < self.meshes.len() => Some(i + 1),
+            _ => None,
+        };
+        self.current.map(|i| &self.meshes[i])
+    }
+
+    pub fn draw(&self, pass: &mut wgpu::RenderPass) {
+        let Some(mesh) = self.current.map(|i| &self.meshes[i]) else {
+            return;
+        };
+        for part in &mesh.parts {
+            pass.set_pipeline(&self.pipelines[part.pipeline.0]);
+            for (group, bind_group) in part.bind_groups.iter().enumerate() {
+                pass.set_bind_group(group as u32, &self.bind_groups[bind_group.0], &[]);
+            }
+            if let Some(vertex_buffer) = &part.vertex_buffer {
+                pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            }
+            match &part.draw {
+                Draw::Vertices(count) => pass.draw(0..*count, 0..1),
+                Draw::Indexed { buffer, count } => {
+                    pass.set_index_buffer(buffer.slice(..), wgpu::IndexFormat::Uint16);
+                    pass.draw_indexed(0..*count, 0, 0..1);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(meshes: &mut DemoMeshes, steps: usize) -> Vec<Option<String>> {
+        (0..steps).map(|_| meshes.cycle().map(|mesh| mesh.name.clone())).collect()
+    }
+
+    #[test]
+    fn cycles_in_registration_order_then_to_none() {
+        let mut meshes = DemoMeshes::new();
+        assert!(meshes.cycle().is_none(), "nothing registered");
+        for name in ["pentagon", "letters", "triangle"] {
+            meshes.register(name, vec![DemoPart::procedural(PipelineId(0), &[], 3)]);
+        }
+        let expected = [Some("pentagon"), Some("letters"), Some("triangle"), None, Some("pentagon")];
+        assert_eq!(names(&mut meshes, 5), expected.map(|name| name.map(String::from)));
+    }
+
+    #[test]
+    fn current_stays_in_range() {
+        let mut meshes = DemoMeshes::new();
+        meshes.register("pentagon", Vec::new());
+        meshes.register("letters", Vec::new());
+        meshes.set_current(Some(1));
+        assert_eq!(meshes.current(), Some(1));
+        assert!(meshes.cycle().is_none());
+        meshes.set_current(Some(2));
+        assert_eq!(meshes.current(), None);
+        assert_eq!(meshes.cycle().map(|mesh| mesh.name.as_str()), Some("pentagon"));
+    }
+}
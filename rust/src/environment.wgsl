@@ -0,0 +1,42 @@
+const PREFILTERED_MIPS: u32 = 5u;
+
+struct Environment {
+    intensity: f32,
+}
+@group(3) @binding(0)
+var<uniform> environment: Environment;
+@group(3) @binding(1)
+var t_irradiance: texture_cube<f32>;
+@group(3) @binding(2)
+var t_prefiltered: texture_cube<f32>;
+@group(3) @binding(3)
+var t_brdf_lut: texture_2d<f32>;
+@group(3) @binding(4)
+var s_environment: sampler;
+
+// Diffuse irradiance plus split sum specular from the environment.
+// `n_dot_v` must be positive.
+fn environment_light(
+    albedo: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    n: vec3<f32>,
+    view_dir: vec3<f32>,
+) -> vec3<f32> {
+    let n_dot_v = max(dot(n, view_dir), 1e-4);
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+    // Rough surfaces can't reach the full grazing reflectance.
+    let f90 = max(vec3<f32>(1.0 - roughness), f0);
+    let f = f0 + (f90 - f0) * pow(1.0 - n_dot_v, 5.0);
+
+    let irradiance = textureSampleLevel(t_irradiance, s_environment, n, 0.0).rgb;
+    let diffuse = (vec3<f32>(1.0) - f) * (1.0 - metallic) * albedo * irradiance;
+
+    let r = reflect(-view_dir, n);
+    let lod = roughness * f32(PREFILTERED_MIPS - 1u);
+    let prefiltered = textureSampleLevel(t_prefiltered, s_environment, r, lod).rgb;
+    let brdf = textureSampleLevel(t_brdf_lut, s_environment, vec2<f32>(n_dot_v, roughness), 0.0).rg;
+    let specular = prefiltered * (f0 * brdf.x + brdf.y);
+
+    return (diffuse + specular) * environment.intensity;
+}
@@ -0,0 +1,23 @@
+const PI: f32 = 3.14159265359;
+
+struct CubeFace {
+    face: u32,
+}
+@group(0) @binding(0)
+var<uniform> cube_face: CubeFace;
+@group(0) @binding(1)
+var t_equirect: texture_2d<f32>;
+@group(0) @binding(2)
+var s_equirect: sampler;
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let dir = normalize(cube_face_direction(cube_face.face, in.uv));
+    // Longitude around +Y across, latitude from the zenith down.
+    let uv = vec2<f32>(
+        atan2(dir.z, dir.x) / (2.0 * PI) + 0.5,
+        acos(clamp(dir.y, -1.0, 1.0)) / PI,
+    );
+    // No mips, and the explicit level avoids derivatives across the seam.
+    return vec4<f32>(textureSampleLevel(t_equirect, s_equirect, uv, 0.0).rgb, 1.0);
+}
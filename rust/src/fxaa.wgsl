@@ -0,0 +1,46 @@
+// params: x = max span in pixels, y = reduce multiplier, z = minimum reduce
+fn luma(color: vec3<f32>) -> f32 {
+    // Inputs are linear HDR, squashing and taking the square root brings
+    // them close enough to perceptual space for edge detection.
+    let l = dot(color, vec3<f32>(0.299, 0.587, 0.114));
+    return sqrt(l / (1.0 + l));
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let texel = post.texel_size;
+    let span_max = post.params.x;
+    let reduce_mul = post.params.y;
+    let reduce_min = post.params.z;
+
+    let rgba_m = textureSample(t_input, s_input, in.uv);
+    let luma_nw = luma(textureSample(t_input, s_input, in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb);
+    let luma_ne = luma(textureSample(t_input, s_input, in.uv + vec2<f32>(1.0, -1.0) * texel).rgb);
+    let luma_sw = luma(textureSample(t_input, s_input, in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb);
+    let luma_se = luma(textureSample(t_input, s_input, in.uv + vec2<f32>(1.0, 1.0) * texel).rgb);
+    let luma_m = luma(rgba_m.rgb);
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    var dir = vec2<f32>(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * reduce_mul, reduce_min);
+    let rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2<f32>(-span_max), vec2<f32>(span_max)) * texel;
+
+    let rgb_a = 0.5 * (
+        textureSample(t_input, s_input, in.uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+        textureSample(t_input, s_input, in.uv + dir * (2.0 / 3.0 - 0.5)).rgb
+    );
+    let rgb_b = rgb_a * 0.5 + 0.25 * (
+        textureSample(t_input, s_input, in.uv + dir * -0.5).rgb +
+        textureSample(t_input, s_input, in.uv + dir * 0.5).rgb
+    );
+    let luma_b = luma(rgb_b);
+
+    let outside = luma_b < luma_min || luma_b > luma_max;
+    return vec4<f32>(select(rgb_b, rgb_a, outside), rgba_m.a);
+}
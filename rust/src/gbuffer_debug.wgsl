@@ -0,0 +1,39 @@
+struct GBufferDebug {
+    channel: u32,
+    z_near: f32,
+    z_far: f32,
+}
+@group(0) @binding(0)
+var<uniform> debug: GBufferDebug;
+@group(0) @binding(1)
+var t_albedo_occlusion: texture_2d<f32>;
+@group(0) @binding(2)
+var t_normal: texture_2d<f32>;
+@group(0) @binding(3)
+var t_metallic_roughness: texture_2d<f32>;
+@group(0) @binding(4)
+var t_depth: texture_2d<f32>;
+@group(0) @binding(5)
+var t_ambient_occlusion: texture_2d<f32>;
+
+// Channels in the order of `GBufferChannel`.
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(in.clip_position.xy);
+    var color: vec3<f32>;
+    switch debug.channel {
+        case 0u: { color = textureLoad(t_albedo_occlusion, coords, 0).rgb; }
+        case 1u: { color = textureLoad(t_normal, coords, 0).xyz; }
+        case 2u: { color = vec3<f32>(textureLoad(t_metallic_roughness, coords, 0).r); }
+        case 3u: { color = vec3<f32>(textureLoad(t_metallic_roughness, coords, 0).g); }
+        case 4u: { color = vec3<f32>(textureLoad(t_albedo_occlusion, coords, 0).a); }
+        case 5u: { color = vec3<f32>(textureLoad(t_ambient_occlusion, coords, 0).r); }
+        default: {
+            // Linear distance, so more than the last few metres are visible.
+            let depth = textureLoad(t_depth, coords, 0).r;
+            let distance = debug.z_near * debug.z_far / (debug.z_far - depth * (debug.z_far - debug.z_near));
+            color = vec3<f32>(1.0 - (distance - debug.z_near) / (debug.z_far - debug.z_near));
+        }
+    }
+    return vec4<f32>(color, 1.0);
+}
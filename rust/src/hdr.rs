@@ -0,0 +1,140 @@
+use crate::post::{self, POST_INPUT_WGSL};
+use crate::uniform::{UniformBuffer, WgslType};
+use crate::wgsl_layout;
+
+/// The format the scene is rendered in before tonemapping.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Picks [`HDR_FORMAT`] if the adapter can render to, filter and blend
+/// into it, which WebGL2 only does with both `EXT_color_buffer_float` and
+/// `EXT_float_blend`, otherwise falls back to `fallback` and loses
+/// everything above 1.0.
+pub fn scene_format(adapter: &wgpu::Adapter, fallback: wgpu::TextureFormat) -> wgpu::TextureFormat {
+    let features = adapter.get_texture_format_features(HDR_FORMAT);
+    let usages = wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING;
+    // Bloom, sprites, text and debug lines all blend into the scene.
+    let flags = wgpu::TextureFormatFeatureFlags::FILTERABLE | wgpu::TextureFormatFeatureFlags::BLENDABLE;
+    if features.allowed_usages.contains(usages) && features.flags.contains(flags) {
+        HDR_FORMAT
+    } else {
+        log::warn!("{:?} can't be rendered and blended into here, rendering the scene in {:?}", HDR_FORMAT, fallback);
+        fallback
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tonemapper {
+    Reinhard,
+    Aces,
+    AgX,
+}
+
+impl Tonemapper {
+    pub const ALL: [Tonemapper; 3] = [Tonemapper::Reinhard, Tonemapper::Aces, Tonemapper::AgX];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tonemapper::Reinhard => "Reinhard",
+            Tonemapper::Aces => "ACES",
+            Tonemapper::AgX => "AgX",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|t| t == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TonemapUniform {
+    exposure: f32,
+    tonemapper: u32,
+    /// 1 if the output isn't an sRGB format, so the shader has to encode.
+    encode_srgb: u32,
+}
+
+wgsl_layout!(TonemapUniform {
+    exposure: WgslType::F32,
+    tonemapper: WgslType::U32,
+    encode_srgb: WgslType::U32,
+});
+
+/// The final pass, mapping the linear HDR image into the surface's range.
+pub struct TonemapPass {
+    pub tonemapper: Tonemapper,
+    /// In stops, the image is scaled by `2^exposure` before tonemapping.
+    pub exposure: f32,
+    encode_srgb: bool,
+    pipeline: wgpu::RenderPipeline,
+    uniform: UniformBuffer<TonemapUniform>,
+}
+
+impl TonemapPass {
+    /// `input_layout` is the texture + sampler layout of whatever is being
+    /// tonemapped, see [`post::PostProcessChain::texture_bind_group_layout`].
+    pub fn new(
+        device: &wgpu::Device,
+        input_layout: &wgpu::BindGroupLayout,
+        output_format: wgpu::TextureFormat,
+    ) -> Self {
+        let tonemapper = Tonemapper::Aces;
+        let exposure = 0.0;
+        // An sRGB view encodes on write, anything else gets the shader's
+        // values as they are.
+        let encode_srgb = !output_format.is_srgb();
+        let uniform = UniformBuffer::new(
+            device,
+            &TonemapUniform {
+                exposure,
+                tonemapper: tonemapper as u32,
+                encode_srgb: encode_srgb as u32,
+            },
+            wgpu::ShaderStages::FRAGMENT,
+            "tonemap_uniform",
+        );
+        let pipeline = post::create_fullscreen_pipeline(
+            device,
+            &[input_layout, &uniform.bind_group_layout],
+            output_format,
+            wgpu::BlendState::REPLACE,
+            &format!("{}\n{}", POST_INPUT_WGSL, include_str!("tonemap.wgsl")),
+            "fs_main",
+            "Tonemap Pipeline",
+        );
+
+        Self {
+            tonemapper,
+            exposure,
+            encode_srgb,
+            pipeline,
+            uniform,
+        }
+    }
+
+    pub fn run(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::BindGroup,
+        output: &wgpu::TextureView,
+    ) {
+        self.uniform.update(queue, &TonemapUniform {
+            exposure: self.exposure,
+            tonemapper: self.tonemapper as u32,
+            encode_srgb: self.encode_srgb as u32,
+        });
+
+        let mut pass = post::begin_fullscreen_pass(
+            encoder,
+            output,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            "Tonemap Pass",
+        );
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, input, &[]);
+        pass.set_bind_group(1, &self.uniform.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,319 @@
+use crate::post;
+use crate::texture::Texture;
+use crate::uniform::{UniformBuffer, WgslType};
+use crate::wgsl_layout;
+
+/// The WGSL side of [`Environment`], bound at group 3, with the lookup that
+/// turns it into ambient light.
+pub const ENVIRONMENT_WGSL: &str = include_str!("environment.wgsl");
+
+/// Mip levels of the prefiltered specular map, from mirror-like at the top
+/// to fully rough at the bottom. Must match `PREFILTERED_MIPS` in
+/// environment.wgsl.
+const PREFILTERED_MIPS: u32 = 5;
+const PREFILTERED_SIZE: u32 = 128;
+const IRRADIANCE_SIZE: u32 = 32;
+const BRDF_LUT_SIZE: u32 = 128;
+/// The smallest level of the downsampled copies the bakes read from.
+const MIN_SOURCE_SIZE: u32 = 16;
+const IRRADIANCE_SAMPLES: u32 = 512;
+const PREFILTER_SAMPLES: u32 = 256;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BakeUniform {
+    face: u32,
+    roughness: f32,
+    sample_count: u32,
+}
+
+wgsl_layout!(BakeUniform {
+    face: WgslType::U32,
+    roughness: WgslType::F32,
+    sample_count: WgslType::U32,
+});
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct EnvironmentUniform {
+    intensity: f32,
+}
+
+wgsl_layout!(EnvironmentUniform {
+    intensity: WgslType::F32,
+});
+
+/// The bind group layout shared by every [`Environment`], along with the
+/// BRDF lookup table they all use and the pipelines that bake them.
+pub struct EnvironmentLayout {
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    brdf_lut: Texture,
+    bake_layout: wgpu::BindGroupLayout,
+    downsample_pipeline: wgpu::RenderPipeline,
+    irradiance_pipeline: wgpu::RenderPipeline,
+    prefilter_pipeline: wgpu::RenderPipeline,
+    format: wgpu::TextureFormat,
+}
+
+impl EnvironmentLayout {
+    /// Bakes the BRDF lookup table. Environments are baked into cube maps
+    /// of `format`, which should be the HDR scene format.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Self {
+        let cube_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::Cube,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                UniformBuffer::<EnvironmentUniform>::layout_entry(0, wgpu::ShaderStages::FRAGMENT),
+                cube_entry(1),
+                cube_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                sampler_entry(4),
+            ],
+            label: Some("environment_bind_group_layout"),
+        });
+        let bake_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                UniformBuffer::<BakeUniform>::layout_entry(0, wgpu::ShaderStages::FRAGMENT),
+                cube_entry(1),
+                sampler_entry(2),
+            ],
+            label: Some("environment_bake_bind_group_layout"),
+        });
+
+        let source = format!("{}\n{}", include_str!("cube.wgsl"), include_str!("ibl_bake.wgsl"));
+        let bake_pipeline = |entry_point, label| {
+            post::create_fullscreen_pipeline(
+                device,
+                &[&bake_layout],
+                format,
+                wgpu::BlendState::REPLACE,
+                &source,
+                entry_point,
+                label,
+            )
+        };
+        let downsample_pipeline = bake_pipeline("fs_downsample", "Environment Downsample Pipeline");
+        let irradiance_pipeline = bake_pipeline("fs_irradiance", "Irradiance Pipeline");
+        let prefilter_pipeline = bake_pipeline("fs_prefilter", "Prefilter Pipeline");
+
+        // Both terms lie in [0, 1], so a linear 8 bit target is enough when
+        // there is no float one.
+        let lut_format = format.remove_srgb_suffix();
+        let brdf_lut = Texture::create_render_target(device, BRDF_LUT_SIZE, BRDF_LUT_SIZE, lut_format, "brdf_lut");
+        let lut_pipeline = post::create_fullscreen_pipeline(
+            device,
+            &[],
+            lut_format,
+            wgpu::BlendState::REPLACE,
+            &source,
+            "fs_brdf_lut",
+            "BRDF LUT Pipeline",
+        );
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("BRDF LUT Encoder"),
+        });
+        {
+            let mut pass = post::begin_fullscreen_pass(
+                &mut encoder,
+                &brdf_lut.view,
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                "BRDF LUT Pass",
+            );
+            pass.set_pipeline(&lut_pipeline);
+            pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        Self {
+            bind_group_layout,
+            brdf_lut,
+            bake_layout,
+            downsample_pipeline,
+            irradiance_pipeline,
+            prefilter_pipeline,
+            format,
+        }
+    }
+
+    /// Renders every face of `target` at `mip_level` with `pipeline`,
+    /// reading from `source`.
+    #[allow(clippy::too_many_arguments)]
+    fn bake(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        source: &Texture,
+        target: &Texture,
+        mip_level: u32,
+        roughness: f32,
+        sample_count: u32,
+    ) {
+        let resources = [
+            (1, wgpu::BindingResource::TextureView(&source.view)),
+            (2, wgpu::BindingResource::Sampler(&source.sampler)),
+        ];
+        for face in 0..6 {
+            // One uniform per face and level, so the whole bake is one submit.
+            let uniform = UniformBuffer::with_layout(
+                device,
+                &BakeUniform {
+                    face,
+                    roughness,
+                    sample_count,
+                },
+                &self.bake_layout,
+                &resources,
+                "environment_bake",
+            );
+            let mut pass = post::begin_fullscreen_pass(
+                encoder,
+                &target.cube_face_view(face, mip_level),
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                "Environment Bake Pass",
+            );
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &uniform.bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+    }
+}
+
+/// Ambient light from an environment cube map: diffuse irradiance and
+/// specular reflections prefiltered for each roughness.
+pub struct Environment {
+    uniform: UniformBuffer<EnvironmentUniform>,
+}
+
+impl Environment {
+    /// Bakes the irradiance and prefiltered maps of `cubemap`, which is
+    /// left untouched and can still be drawn as the sky.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &EnvironmentLayout,
+        name: &str,
+        cubemap: &Texture,
+        intensity: f32,
+    ) -> Self {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Environment Bake Encoder"),
+        });
+
+        // Sampling a small bright spot, like the sun, with a few hundred rays
+        // misses it in some texels and hits it in others. Each bake reads a
+        // copy downsampled to about its own texel size instead, each level
+        // its own texture since WebGL2 can't read one mip while writing
+        // another.
+        let mut sources = vec![];
+        let mut size = cubemap.texture.width() / 2;
+        while size >= MIN_SOURCE_SIZE {
+            let level = Texture::create_cube_render_target(device, size, 1, layout.format, "environment_source");
+            let previous = sources.last().unwrap_or(cubemap);
+            layout.bake(device, &mut encoder, &layout.downsample_pipeline, previous, &level, 0, 0.0, 0);
+            sources.push(level);
+            size /= 2;
+        }
+        // The first of the cube map and its copies at most `size` across.
+        let source_for = |size: u32| {
+            std::iter::once(cubemap)
+                .chain(&sources)
+                .find(|source| source.texture.width() <= size)
+                .unwrap_or(sources.last().unwrap_or(cubemap))
+        };
+
+        let irradiance =
+            Texture::create_cube_render_target(device, IRRADIANCE_SIZE, 1, layout.format, &format!("{name}_irradiance"));
+        layout.bake(
+            device,
+            &mut encoder,
+            &layout.irradiance_pipeline,
+            source_for(MIN_SOURCE_SIZE),
+            &irradiance,
+            0,
+            0.0,
+            IRRADIANCE_SAMPLES,
+        );
+
+        let prefiltered = Texture::create_cube_render_target(
+            device,
+            PREFILTERED_SIZE,
+            PREFILTERED_MIPS,
+            layout.format,
+            &format!("{name}_prefiltered"),
+        );
+        for mip_level in 0..PREFILTERED_MIPS {
+            let roughness = mip_level as f32 / (PREFILTERED_MIPS - 1) as f32;
+            // A perfect mirror only needs the one sample along the normal.
+            let sample_count = if mip_level == 0 { 1 } else { PREFILTER_SAMPLES };
+            layout.bake(
+                device,
+                &mut encoder,
+                &layout.prefilter_pipeline,
+                source_for(2 * (PREFILTERED_SIZE >> mip_level)),
+                &prefiltered,
+                mip_level,
+                roughness,
+                sample_count,
+            );
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let uniform = UniformBuffer::with_layout(
+            device,
+            &EnvironmentUniform { intensity },
+            &layout.bind_group_layout,
+            &[
+                (1, wgpu::BindingResource::TextureView(&irradiance.view)),
+                (2, wgpu::BindingResource::TextureView(&prefiltered.view)),
+                (3, wgpu::BindingResource::TextureView(&layout.brdf_lut.view)),
+                (4, wgpu::BindingResource::Sampler(&prefiltered.sampler)),
+            ],
+            name,
+        );
+
+        Self { uniform }
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.uniform.bind_group
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_count_matches_the_shader() {
+        let declaration = format!("const PREFILTERED_MIPS: u32 = {}u;", PREFILTERED_MIPS);
+        assert!(
+            ENVIRONMENT_WGSL.contains(&declaration),
+            "PREFILTERED_MIPS isn't {} in environment.wgsl",
+            PREFILTERED_MIPS
+        );
+    }
+}
@@ -0,0 +1,120 @@
+const PI: f32 = 3.14159265359;
+
+struct Bake {
+    face: u32,
+    roughness: f32,
+    sample_count: u32,
+}
+@group(0) @binding(0)
+var<uniform> bake: Bake;
+@group(0) @binding(1)
+var t_source: texture_cube<f32>;
+@group(0) @binding(2)
+var s_source: sampler;
+
+// The i-th of n points of the Hammersley set. The radical inverse is
+// spelled out because WebGL2 has no bitfieldReverse.
+fn hammersley(i: u32, n: u32) -> vec2<f32> {
+    var bits = i;
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return vec2<f32>(f32(i) / f32(n), f32(bits) * 2.3283064365386963e-10);
+}
+
+// Turns a direction around +Z into one around `n`.
+fn to_world(v: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(n.z) < 0.999);
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return tangent * v.x + bitangent * v.y + n * v.z;
+}
+
+// A half vector around `n` distributed like the GGX normal distribution.
+fn importance_sample_ggx(xi: vec2<f32>, n: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return to_world(vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta), n);
+}
+
+// Averages the 2x2 texels of the source under each texel of a cube half
+// its size.
+@fragment
+fn fs_downsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let dir = normalize(cube_face_direction(bake.face, in.uv));
+    return vec4<f32>(textureSampleLevel(t_source, s_source, dir, 0.0).rgb, 1.0);
+}
+
+// Cosine weighted average of the incoming light over the hemisphere, so
+// that diffuse lighting is just albedo times a lookup along the normal.
+@fragment
+fn fs_irradiance(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let n = normalize(cube_face_direction(bake.face, in.uv));
+    var sum = vec3<f32>(0.0);
+    for (var i = 0u; i < bake.sample_count; i += 1u) {
+        let xi = hammersley(i, bake.sample_count);
+        let r = sqrt(xi.y);
+        let phi = 2.0 * PI * xi.x;
+        let l = to_world(vec3<f32>(r * cos(phi), r * sin(phi), sqrt(1.0 - xi.y)), n);
+        sum += textureSampleLevel(t_source, s_source, l, 0.0).rgb;
+    }
+    return vec4<f32>(sum / f32(bake.sample_count), 1.0);
+}
+
+// The environment convolved with the GGX lobe of `bake.roughness`, under
+// the usual assumption that the view direction equals the normal.
+@fragment
+fn fs_prefilter(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let n = normalize(cube_face_direction(bake.face, in.uv));
+    var sum = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < bake.sample_count; i += 1u) {
+        let h = importance_sample_ggx(hammersley(i, bake.sample_count), n, bake.roughness);
+        let l = normalize(2.0 * dot(n, h) * h - n);
+        let n_dot_l = dot(n, l);
+        if (n_dot_l > 0.0) {
+            sum += textureSampleLevel(t_source, s_source, l, 0.0).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    return vec4<f32>(sum / max(weight, 1e-4), 1.0);
+}
+
+fn geometry_schlick_ggx_ibl(n_dot_x: f32, roughness: f32) -> f32 {
+    // Image based lighting remaps k differently from analytic lights.
+    let k = roughness * roughness / 2.0;
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+// The scale (red) and bias (green) applied to F0 by the split sum
+// approximation, for n.v across and roughness down.
+@fragment
+fn fs_brdf_lut(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let n_dot_v = max(in.uv.x, 1e-3);
+    let roughness = in.uv.y;
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let n = vec3<f32>(0.0, 0.0, 1.0);
+
+    let sample_count = 512u;
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < sample_count; i += 1u) {
+        let h = importance_sample_ggx(hammersley(i, sample_count), n, roughness);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = saturate(l.z);
+        let n_dot_h = saturate(h.z);
+        let v_dot_h = saturate(dot(v, h));
+        if (n_dot_l > 0.0) {
+            let g = geometry_schlick_ggx_ibl(n_dot_v, roughness) * geometry_schlick_ggx_ibl(n_dot_l, roughness);
+            let g_vis = g * v_dot_h / (n_dot_h * n_dot_v);
+            let fc = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fc) * g_vis;
+            bias += fc * g_vis;
+        }
+    }
+    return vec4<f32>(scale / f32(sample_count), bias / f32(sample_count), 0.0, 1.0);
+}
@@ -1,4 +1,5 @@
-use std::{fs, io::stdout, sync::Arc};
+use std::sync::Arc;
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, Quaternion, Rotation3, Vector3};
 use wgpu::util::DeviceExt;
 use winit::{
     application::ApplicationHandler,
@@ -7,7 +8,34 @@ use winit::{
     keyboard::{KeyCode, PhysicalKey},
     window::Window,
 };
+mod assets;
+mod bloom;
+mod camera;
+mod cluster;
+mod debug_draw;
+mod deferred;
+mod demo_mesh;
+mod hdr;
+mod ibl;
+mod light;
+mod loader;
+mod material;
+mod mesh;
+mod overlay;
+mod post;
+mod primitives;
+mod scene;
+mod scene_file;
+mod shadow;
+mod skybox;
+mod sprite;
+mod ssao;
+mod svg;
+mod text;
 mod texture;
+mod triangulate;
+pub mod uniform;
+mod vector;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
@@ -40,80 +68,65 @@ impl Vertex {
     }
 }
 
-const VERTICES: &[Vertex] = &[
-    Vertex { position: [-0.0868241, 0.49240386, 0.0], tex_coords: [0.4131759, 0.00759614], }, // A
-    Vertex { position: [-0.49513406, 0.06958647, 0.0], tex_coords: [0.0048659444, 0.43041354], }, // B
-    Vertex { position: [-0.21918549, -0.44939706, 0.0], tex_coords: [0.28081453, 0.949397], }, // C
-    Vertex { position: [0.35966998, -0.3473291, 0.0], tex_coords: [0.85967, 0.84732914], }, // D
-    Vertex { position: [0.44147372, 0.2347359, 0.0], tex_coords: [0.9414737, 0.2652641], }, // E
+/// The tutorial's pentagon, triangulated at startup.
+const PENTAGON: &[[f32; 2]] = &[
+    [-0.0868241, 0.49240386], // A
+    [-0.49513406, 0.06958647], // B
+    [-0.21918549, -0.44939706], // C
+    [0.35966998, -0.3473291], // D
+    [0.44147372, 0.2347359], // E
 ];
 
-
-const INDICES: &[u16] = &[
-    0, 1, 4,
-    1, 2, 4,
-    2, 3, 4,
+/// The L of "LOL", with the second L shifted over. Triangulated at
+/// startup.
+const LETTER_L: &[[f32; 2]] = &[
+    [0.05, -0.10],
+    [0.15, -0.10],
+    [0.15, -0.75],
+    [0.25, -0.75],
+    [0.25, -0.85],
+    [0.05, -0.85],
+];
+const LETTER_O: &[[f32; 2]] = &[
+    [0.40, -0.20],
+    [0.60, -0.20],
+    [0.70, -0.30],
+    [0.70, -0.70],
+    [0.60, -0.80],
+    [0.40, -0.80],
+    [0.30, -0.70],
+    [0.30, -0.30],
+];
+const LETTER_O_HOLE: &[[f32; 2]] = &[
+    [0.40, -0.30],
+    [0.60, -0.30],
+    [0.60, -0.70],
+    [0.40, -0.70],
 ];
 
-
-//const FUNNY_VERTICES: &[Vertex] = &[
-//
-//    //L
-//    Vertex { position: [0.05, -0.10, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.15, -0.10, 0.0], color: [1.0, 1.0, 0.5] },
-//    Vertex { position: [0.15, -0.75, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.25, -0.75, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.25, -0.85, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.05, -0.85, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.05, -0.75, 0.0], color: [1.0, 0.0, 0.0] },
-//
-//    //O
-//    Vertex { position: [0.40, -0.30, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.40, -0.20, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.60, -0.20, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.60, -0.30, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.70, -0.30, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.70, -0.70, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.60, -0.70, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.60, -0.80, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.40, -0.80, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.40, -0.70, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.30, -0.70, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.30, -0.30, 0.0], color: [1.0, 0.0, 0.0] },
-//
-//    //L2
-//    Vertex { position: [0.05 + 0.7, -0.10, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.15 + 0.7, -0.10, 0.0], color: [1.0, 1.0, 0.5] },
-//    Vertex { position: [0.15 + 0.7, -0.75, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.25 + 0.7, -0.75, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.25 + 0.7, -0.85, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.05 + 0.7, -0.85, 0.0], color: [1.0, 0.0, 0.0] },
-//    Vertex { position: [0.05 + 0.7, -0.75, 0.0], color: [1.0, 0.0, 0.0] },
-//
-//];
-
-const FUNNY_INDICES: &[u16] = &[
-    //L
-    6, 1, 0,
-    6, 2, 1,
-    3, 6, 5,
-    5, 4, 3,
-
-    9,8,7,
-    7,10,9,
-    12,11,10,
-    10,13,12,
-    13,15,14,
-    13,16,15,
-    16,18,17,
-    16,7,18,
-
-    //L2
-    19,21,20,
-    19,25,21,
-    25,23,22,
-    25,24,23,
+/// The "LOL" letters in one set of vertices and indices, each textured
+/// across its own bounds.
+fn lol_letters() -> anyhow::Result<(Vec<Vertex>, Vec<u16>)> {
+    let second_l: Vec<[f32; 2]> = LETTER_L.iter().map(|p| [p[0] + 0.7, p[1]]).collect();
+    let letters = [
+        triangulate::polygon(LETTER_L, &[])?,
+        triangulate::polygon(LETTER_O, &[LETTER_O_HOLE.to_vec()])?,
+        triangulate::polygon(&second_l, &[])?,
     ];
+    let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+    for (letter_vertices, letter_indices) in letters {
+        let first = vertices.len() as u16;
+        indices.extend(letter_indices.into_iter().map(|i| first + i));
+        vertices.extend(letter_vertices);
+    }
+    Ok((vertices, indices))
+}
+
+/// The scene loaded at startup, relative to the asset root natively and
+/// to the page on the web. The inspector saves over it.
+const SCENE_PATH: &str = "scenes/demo.ron";
+/// One of the 2D demo meshes.
+const DRAWING_PATH: &str = "drawings/badge.svg";
 
 pub struct State {
     surface: wgpu::Surface<'static>,
@@ -121,27 +134,65 @@ pub struct State {
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     is_surface_configured: bool,
+    present_modes: Vec<wgpu::PresentMode>,
+    scene_format: wgpu::TextureFormat,
     window: Arc<Window>, 
 
-    render_pipeline: wgpu::RenderPipeline,
-    color_render_pipeline: wgpu::RenderPipeline,
-    use_color: bool,
+    pipeline_layouts: ScenePipelineLayouts,
+    pipelines: ScenePipelines,
+    show_sprites: bool,
+    clear_color: [f32; 3],
 
-    vertex_buffer: wgpu::Buffer,
-    num_vertices: u32,
+    supported_sample_counts: Vec<u32>,
+    sample_count: u32,
+    msaa_texture: Option<texture::Texture>,
+    depth_texture: texture::Texture,
 
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
+    camera: camera::Camera,
+    camera_controller: camera::CameraController,
+    camera_uniform: camera::CameraUniform,
+    camera_buffer: uniform::UniformBuffer<camera::CameraUniform>,
 
-    //funny_vertex_buffer: wgpu::Buffer,
-    //funny_num_vertices: u32,
+    lights: light::Lights,
+    shadow_maps: shadow::ShadowMaps,
+    skybox: skybox::Skybox,
+    environments: Vec<ibl::Environment>,
+    environment: usize,
+    deferred: deferred::Deferred,
+    text: text::TextRenderer,
+    sprites: sprite::SpriteBatch,
+    debug_draw: debug_draw::DebugDraw,
+    happy_tree_sprite: sprite::SpriteTexture,
+    overlay: overlay::Overlay,
+    texture_preview: egui::TextureId,
 
-    //funny_index_buffer: wgpu::Buffer,
-    //funny_num_indices: u32,
-    //use_funny: bool,
+    material_layout: material::MaterialLayout,
+    assets: assets::Assets,
+    instance_buffer: wgpu::Buffer,
+    draw_calls: Vec<scene::DrawCall>,
+    scene: scene::SceneGraph,
+    /// What the scene was loaded from, to save it with.
+    #[cfg(not(target_arch = "wasm32"))]
+    scene_source: scene_file::SceneSource,
+    /// Turned a little every frame, carrying whatever is on it around.
+    turntable: Option<scene::NodeId>,
+    /// The scene node whose camera the view is from, instead of the free
+    /// camera.
+    view_node: Option<scene::NodeId>,
 
-    diffuse_bind_group: wgpu::BindGroup,
-    diffuse_texture: texture::Texture,
+    post: post::PostProcessChain,
+    bloom: bloom::Bloom,
+    tonemap: hdr::TonemapPass,
+
+    /// Drawn over the scene, one at a time.
+    demo_meshes: demo_mesh::DemoMeshes,
+    textured_pipeline: demo_mesh::PipelineId,
+    color_pipeline: demo_mesh::PipelineId,
+    /// The happy tree, until it's loaded the placeholder.
+    diffuse_binding: demo_mesh::BindGroupId,
+
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    diffuse_texture: assets::Handle<texture::Texture>,
 }
 
 impl State {
@@ -169,7 +220,9 @@ impl State {
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: None,
-                required_features: wgpu::Features::empty(),
+                // Without it wgpu only allows the sample counts every
+                // adapter guarantees, whatever this one reports.
+                required_features: adapter.features() & wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
                 // WebGL doesn't sport all of wgpu's features, so if
                 // we're building for the web we'll have to disable some.
                 required_limits: if cfg!(target_arch = "wasm32") {
@@ -200,12 +253,11 @@ impl State {
             desired_maximum_frame_latency: 2,
         };
 
-        let diffuse_texture = texture::Texture::from_bytes(
-            &device,
-            &queue,
-            include_bytes!("../happy-tree.png"),
-             "happy-tree.png")
-        .unwrap();
+        let mut assets = assets::Assets::new(&device, &queue);
+        // A placeholder stands in until it has loaded, see
+        // `bind_diffuse_texture`.
+        let diffuse_texture = assets.load_texture("happy-tree.png");
+        let diffuse = assets.texture_or_placeholder(&diffuse_texture);
 
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -233,145 +285,214 @@ impl State {
             }
         );
 
-        let diffuse_bind_group = device.create_bind_group(
-        &wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                }
-            ],
-            label: Some("diffuse_bind_group"),
-        }
-    );
+        let diffuse_bind_group = create_diffuse_bind_group(&device, &texture_bind_group_layout, diffuse);
 
+        let mut sprites = sprite::SpriteBatch::new(&device, &texture_bind_group_layout, config.format);
+        let happy_tree_sprite = sprites.add_texture(&device, diffuse, "happy_tree_sprite");
 
+        let mut overlay = overlay::Overlay::new(&device, &window, config.format);
+        let texture_preview = overlay.register_texture(&device, &diffuse.view);
 
-        let vertex_buffer = device.create_buffer_init(
-           &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(VERTICES),
-                usage: wgpu::BufferUsages::VERTEX,
-            } 
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[&texture_bind_group_layout],
+                push_constant_ranges: &[],
+            }
         );
 
-        let index_buffer = device.create_buffer_init(
-           &wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(INDICES),
-                usage: wgpu::BufferUsages::INDEX,
-            } 
+        let scene_format = hdr::scene_format(&adapter, config.format);
+
+        let supported_sample_counts = supported_sample_counts(&adapter, &device, scene_format);
+        let sample_count = if supported_sample_counts.contains(&4) { 4 } else { 1 };
+
+        let material_layout = material::MaterialLayout::new(&device, &queue);
+        let scene = scene_file::load(SCENE_PATH)
+            .await?
+            .instantiate(&device, &queue, &mut assets, &material_layout);
+        let mut scene_graph = scene.graph;
+        scene_graph.update_world_matrices();
+        let (instance_data, draw_calls) = scene_graph.draw_list();
+        let instance_buffer = create_instance_buffer(&device, &instance_data);
+        let (point_lights, spot_lights) = scene_graph.lights();
+
+        let camera = camera::Camera {
+            aspect: config.width as f32 / config.height.max(1) as f32,
+            ..scene.camera
+        };
+        let camera_controller = camera::CameraController::new(0.2);
+        let mut camera_uniform = camera::CameraUniform::default();
+        camera_uniform.update_view_proj(&camera);
+        let camera_buffer = uniform::UniformBuffer::new(
+            &device,
+            &camera_uniform,
+            wgpu::ShaderStages::VERTEX_FRAGMENT,
+            "camera",
         );
 
-        
-        //let funny_vertex_buffer = device.create_buffer_init(
-        //   &wgpu::util::BufferInitDescriptor {
-        //        label: Some("Vertex Buffer"),
-        //        contents: bytemuck::cast_slice(FUNNY_VERTICES),
-        //        usage: wgpu::BufferUsages::VERTEX,
-        //    } 
-        //);
-
-        let funny_index_buffer = device.create_buffer_init(
-           &wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(FUNNY_INDICES),
-                usage: wgpu::BufferUsages::INDEX,
-            } 
+        let shadow_maps = shadow::ShadowMaps::new(&device, config.format);
+        let lights = light::Lights::new(
+            &device,
+            &shadow_maps,
+            scene.sun,
+            spot_lights,
+            point_lights,
+        );
+
+        let mut skybox = skybox::Skybox::new(&device);
+        let sky = texture::Texture::cube_from_equirectangular(
+            &device,
+            &queue,
+            include_bytes!("../sky.hdr"),
+            512,
+            scene_format,
+            "sky.hdr",
+        )?;
+        skybox.add_cubemap(&device, "sky", &sky);
+        let grid = texture::Texture::cube_from_images(&device, &queue, &debug_cube_faces(64), "debug_grid")?;
+        skybox.add_cubemap(&device, "debug grid", &grid);
+
+        let environment_layout = ibl::EnvironmentLayout::new(&device, &queue, scene_format);
+        let environments = vec![
+            ibl::Environment::new(&device, &queue, &environment_layout, "sky", &sky, 1.0),
+            ibl::Environment::new(&device, &queue, &environment_layout, "debug grid", &grid, 1.0),
+        ];
+
+        let pbr_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("PBR Pipeline Layout"),
+                bind_group_layouts: &[
+                    &camera_buffer.bind_group_layout,
+                    lights.bind_group_layout(),
+                    &material_layout.bind_group_layout,
+                    &environment_layout.bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            }
         );
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
+        let skybox_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Skybox Pipeline Layout"),
+                bind_group_layouts: &skybox.bind_group_layouts(),
+                push_constant_ranges: &[],
+            }
+        );
 
-        let render_pipeline_layout =
+        let text = text::TextRenderer::new(&device, include_bytes!("../fonts/DejaVuSans.ttf").to_vec(), config.format)?;
+        let text_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&texture_bind_group_layout],
+                label: Some("Text Pipeline Layout"),
+                bind_group_layouts: &text.world_bind_group_layouts(&camera_buffer.bind_group_layout),
                 push_constant_ranges: &[],
             }
         );
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[Vertex::desc()],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false, 
-            },
-            multiview: None,
-            cache: None,
-        });
+        let debug_draw = debug_draw::DebugDraw::new();
+        let debug_draw_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Debug Draw Pipeline Layout"),
+                bind_group_layouts: &[&camera_buffer.bind_group_layout],
+                push_constant_ranges: &[],
+            }
+        );
 
-        let color_shader = device.create_shader_module(wgpu::include_wgsl!("color_shader.wgsl"));
+        let pipeline_layouts = ScenePipelineLayouts {
+            textured: render_pipeline_layout,
+            pbr: pbr_pipeline_layout,
+            skybox: skybox_pipeline_layout,
+            text: text_pipeline_layout,
+            debug_draw: debug_draw_pipeline_layout,
+        };
+        let pipelines = ScenePipelines::new(&device, &mut assets, &pipeline_layouts, &lights, scene_format, sample_count);
 
-        let color_render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &color_shader,
-                entry_point: Some("vs_main"),
-                buffers: &[],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &color_shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false, 
-            },
-            multiview: None,
-            cache: None,
+        let mut demo_meshes = demo_mesh::DemoMeshes::new();
+        let textured_pipeline = demo_meshes.add_pipeline(pipelines.textured.clone());
+        let color_pipeline = demo_meshes.add_pipeline(pipelines.color.clone());
+        let diffuse_binding = demo_meshes.add_bind_group(diffuse_bind_group);
+
+        let (vertices, indices) = triangulate::polygon(PENTAGON, &[])?;
+        let pentagon = demo_mesh::DemoPart::indexed(&device, "Pentagon", textured_pipeline, &[diffuse_binding], &vertices, &indices);
+        demo_meshes.register("Pentagon", vec![pentagon]);
+
+        let (vertices, indices) = lol_letters()?;
+        let letters = demo_mesh::DemoPart::indexed(&device, "LOL", textured_pipeline, &[diffuse_binding], &vertices, &indices);
+        demo_meshes.register("LOL", vec![letters]);
+
+        // The shader doesn't sample the texture, it's only bound because the
+        // layout has it.
+        let triangle = demo_mesh::DemoPart::procedural(color_pipeline, &[diffuse_binding], 3);
+        demo_meshes.register("Colour triangle", vec![triangle]);
+
+        let drawing = svg::load(DRAWING_PATH).await?;
+        let drawing_palette = texture::Texture::from_image(
+            &device,
+            &queue,
+            &image::DynamicImage::ImageRgba8(drawing.palette()),
+            Some("drawing_palette"),
+        )?;
+        let palette_binding =
+            demo_meshes.add_bind_group(create_diffuse_bind_group(&device, &texture_bind_group_layout, &drawing_palette));
+        for (name, paint, binding) in [
+            ("SVG drawing", svg::Paint::Colours, palette_binding),
+            ("SVG drawing, textured", svg::Paint::Texture, diffuse_binding),
+        ] {
+            let geometry = drawing.geometry([-0.6, -0.6], [0.6, 0.6], paint)?;
+            let part = demo_mesh::DemoPart::indexed(&device, name, textured_pipeline, &[binding], &geometry.vertices, &geometry.indices);
+            demo_meshes.register(name, vec![part]);
+        }
+        // The pentagon shows from the start, as the 2D shapes did before.
+        demo_meshes.set_current(Some(0));
+
+        let deferred = deferred::Deferred::new(
+            &device,
+            &pipeline_layouts.pbr,
+            &camera_buffer.bind_group_layout,
+            &lights,
+            &environment_layout.bind_group_layout,
+            config.width,
+            config.height,
+            scene_format,
+            config.format,
+        );
+
+        let msaa_texture = (sample_count > 1).then(|| {
+            texture::Texture::create_msaa_texture(
+                &device,
+                config.width,
+                config.height,
+                scene_format,
+                sample_count,
+                "msaa_texture",
+            )
         });
+        let depth_texture = texture::Texture::create_depth_texture(
+            &device,
+            config.width,
+            config.height,
+            sample_count,
+            "depth_texture",
+        );
+
+        let mut post = post::PostProcessChain::new(
+            &device,
+            config.width,
+            config.height,
+            scene_format,
+        );
+        post.add_builtin(&device, post::BuiltinEffect::Fxaa, true);
+        post.add_builtin(&device, post::BuiltinEffect::ChromaticAberration, false);
+        post.add_builtin(&device, post::BuiltinEffect::Grayscale, false);
+        post.add_builtin(&device, post::BuiltinEffect::Vignette, true);
+
+        let bloom = bloom::Bloom::new(
+            &device,
+            post.texture_bind_group_layout(),
+            config.width,
+            config.height,
+            scene_format,
+        );
+        let tonemap = hdr::TonemapPass::new(&device, post.texture_bind_group_layout(), config.format);
 
         Ok(Self {
             surface,
@@ -379,20 +500,50 @@ impl State {
             queue,
             config,
             is_surface_configured: false,
-            render_pipeline,
+            present_modes: surface_caps.present_modes,
+            scene_format,
+            pipeline_layouts,
+            pipelines,
             window,
-            color_render_pipeline,
-            use_color: false,
-            vertex_buffer,
-            num_vertices: VERTICES.len() as u32,
-            index_buffer,
-            num_indices: INDICES.len() as u32,
-            //funny_vertex_buffer,
-            //funny_num_vertices: FUNNY_VERTICES.len() as u32,
-            //funny_index_buffer,
-            //funny_num_indices: FUNNY_INDICES.len() as u32,
-            //use_funny: false,
-            diffuse_bind_group,
+            supported_sample_counts,
+            sample_count,
+            msaa_texture,
+            depth_texture,
+            camera,
+            camera_controller,
+            camera_uniform,
+            camera_buffer,
+            lights,
+            shadow_maps,
+            skybox,
+            environments,
+            environment: 0,
+            deferred,
+            text,
+            sprites,
+            debug_draw,
+            happy_tree_sprite,
+            overlay,
+            texture_preview,
+            material_layout,
+            assets,
+            instance_buffer,
+            draw_calls,
+            turntable: scene_graph.find("turntable"),
+            scene: scene_graph,
+            #[cfg(not(target_arch = "wasm32"))]
+            scene_source: scene.source,
+            view_node: None,
+            post,
+            bloom,
+            tonemap,
+            show_sprites: false,
+            clear_color: scene.clear_color,
+            demo_meshes,
+            textured_pipeline,
+            color_pipeline,
+            diffuse_binding,
+            texture_bind_group_layout,
             diffuse_texture
         })
     }
@@ -403,24 +554,487 @@ impl State {
             self.config.height = height;
             self.surface.configure(&self.device, &self.config);
             self.is_surface_configured = true;
+            self.msaa_texture = self.create_msaa_texture(self.sample_count);
+            self.depth_texture = self.create_depth_texture(self.sample_count);
+            self.camera.aspect = width as f32 / height as f32;
+            self.post.resize(&self.device, width, height);
+            self.bloom.resize(&self.device, self.post.texture_bind_group_layout(), width, height);
+            self.deferred.resize(&self.device, width, height);
+        }
+    }
+
+    /// Switches to `sample_count`x MSAA, rebuilding the pipelines, the
+    /// multisampled colour target and the depth buffer. Fails if the scene
+    /// or depth format can't be multisampled at that count.
+    pub fn set_sample_count(&mut self, sample_count: u32) -> anyhow::Result<()> {
+        if !self.supported_sample_counts.contains(&sample_count) {
+            anyhow::bail!(
+                "{}x MSAA is not supported for {:?} (supported: {:?})",
+                sample_count,
+                self.scene_format,
+                self.supported_sample_counts,
+            );
+        }
+
+        self.pipelines = ScenePipelines::new(
+            &self.device,
+            &mut self.assets,
+            &self.pipeline_layouts,
+            &self.lights,
+            self.scene_format,
+            sample_count,
+        );
+        self.demo_meshes.set_pipeline(self.textured_pipeline, self.pipelines.textured.clone());
+        self.demo_meshes.set_pipeline(self.color_pipeline, self.pipelines.color.clone());
+        self.sample_count = sample_count;
+        self.msaa_texture = self.create_msaa_texture(sample_count);
+        self.depth_texture = self.create_depth_texture(sample_count);
+        Ok(())
+    }
+
+    fn create_depth_texture(&self, sample_count: u32) -> texture::Texture {
+        texture::Texture::create_depth_texture(
+            &self.device,
+            self.config.width,
+            self.config.height,
+            sample_count,
+            "depth_texture",
+        )
+    }
+
+    fn create_msaa_texture(&self, sample_count: u32) -> Option<texture::Texture> {
+        (sample_count > 1).then(|| {
+            texture::Texture::create_msaa_texture(
+                &self.device,
+                self.config.width,
+                self.config.height,
+                self.scene_format,
+                sample_count,
+                "msaa_texture",
+            )
+        })
+    }
+
+    fn cycle_sample_count(&mut self) {
+        if self.deferred.enabled {
+            log::warn!("MSAA is not available with deferred shading");
+            return;
+        }
+        let current = self.supported_sample_counts
+            .iter()
+            .position(|&count| count == self.sample_count)
+            .unwrap_or(0);
+        let next = self.supported_sample_counts[(current + 1) % self.supported_sample_counts.len()];
+        match self.set_sample_count(next) {
+            Ok(()) => log::info!("MSAA: {}x", next),
+            Err(e) => log::error!("{}", e),
         }
     }
 
+    /// Switches between forward and deferred shading. The G-buffer has one
+    /// sample per pixel, so MSAA is turned off for deferred.
+    fn toggle_deferred(&mut self) {
+        if !self.deferred.enabled && self.sample_count != 1 {
+            if let Err(e) = self.set_sample_count(1) {
+                log::error!("{}", e);
+                return;
+            }
+            log::info!("MSAA: off for deferred shading");
+        }
+        self.deferred.enabled = !self.deferred.enabled;
+        log::info!("Shading: {}", if self.deferred.enabled { "deferred" } else { "forward" });
+    }
+
     fn handle_key(&mut self, event_loop: &ActiveEventLoop, code: KeyCode, is_pressed: bool) {
+        if self.camera_controller.process_keyboard(code, is_pressed) {
+            return;
+        }
+
         match (code, is_pressed) {
-            (KeyCode::KeyC, pressed) => {
-                self.use_color = pressed;
+            (KeyCode::KeyO, true) => match self.demo_meshes.cycle() {
+                Some(mesh) => log::info!("2D demo: {}", mesh.name),
+                None => log::info!("2D demo: off"),
+            },
+            (KeyCode::KeyI, true) => self.overlay.visible = !self.overlay.visible,
+            (KeyCode::KeyM, true) => self.cycle_sample_count(),
+            (KeyCode::KeyG, true) => self.toggle_deferred(),
+            (KeyCode::KeyL, true) => {
+                self.debug_draw.enabled = !self.debug_draw.enabled;
+                log::info!("Debug draw: {}", if self.debug_draw.enabled { "on" } else { "off" });
+            },
+            (KeyCode::KeyX, true) => {
+                let ssao = &mut self.deferred.ssao;
+                ssao.enabled = !ssao.enabled;
+                log::info!("SSAO: {}", if ssao.enabled { "on" } else { "off" });
+                if ssao.enabled && !self.deferred.enabled {
+                    log::info!("SSAO only applies to deferred shading (G)");
+                }
+            },
+            (KeyCode::KeyN, true) => {
+                self.deferred.cycle_debug_channel();
+                match self.deferred.debug_channel {
+                    Some(channel) => log::info!("G-buffer debug view: {}", channel.name()),
+                    None => log::info!("G-buffer debug view: off"),
+                }
+            },
+            (KeyCode::Digit1, true) => self.post.toggle(0),
+            (KeyCode::Digit2, true) => self.post.toggle(1),
+            (KeyCode::Digit3, true) => self.post.toggle(2),
+            (KeyCode::Digit4, true) => self.post.toggle(3),
+            (KeyCode::KeyB, true) => {
+                self.bloom.enabled = !self.bloom.enabled;
+                log::info!("Bloom: {}", if self.bloom.enabled { "on" } else { "off" });
+            },
+            (KeyCode::KeyT, true) => {
+                self.tonemap.tonemapper = self.tonemap.tonemapper.next();
+                log::info!("Tonemapper: {}", self.tonemap.tonemapper.name());
+            },
+            (KeyCode::Equal, true) => {
+                self.tonemap.exposure += 0.5;
+                log::info!("Exposure: {:+.1} EV", self.tonemap.exposure);
+            },
+            (KeyCode::Minus, true) => {
+                self.tonemap.exposure -= 0.5;
+                log::info!("Exposure: {:+.1} EV", self.tonemap.exposure);
+            },
+            (KeyCode::KeyV, true) => {
+                self.shadow_maps.cycle_debug_layer();
+                match self.shadow_maps.debug_layer {
+                    Some(layer) => log::info!("Shadow map debug view: layer {}", layer),
+                    None => log::info!("Shadow map debug view: off"),
+                }
+            },
+            (KeyCode::KeyK, true) => {
+                // The environments were baked from the skybox's cube maps, in
+                // the same order.
+                if let Some(name) = self.skybox.next_cubemap() {
+                    self.environment = (self.environment + 1) % self.environments.len();
+                    log::info!("Skybox: {}", name);
+                }
+            },
+            (KeyCode::KeyP, true) => {
+                self.shadow_maps.pcf_radius = (self.shadow_maps.pcf_radius + 1) % 4;
+                log::info!("PCF kernel: {0}x{0}", 2 * self.shadow_maps.pcf_radius + 1);
             },
-            //(KeyCode::KeyF, pressed) => {
-            //    self.use_funny = pressed;
-            //},
             (KeyCode::Escape, true) => event_loop.exit(),
             _ => {}
         }
     }
+
+    /// The camera the frame is drawn from: the one at `view_node` if it
+    /// has one, otherwise the free camera.
+    fn active_camera(&self) -> camera::Camera {
+        self.view_node
+            .and_then(|node| self.scene.camera(node, self.camera.aspect))
+            .unwrap_or(self.camera)
+    }
     
     fn update(&mut self) {
-        // remove `todo!()`
+        if let Some(turntable) = self.turntable {
+            let mut transform = *self.scene.node(turntable).transform();
+            transform.rotation = Quaternion::from_angle_y(Deg(0.5)) * transform.rotation;
+            self.scene.set_transform(turntable, transform);
+        }
+        if self.scene.update_world_matrices() {
+            self.upload_scene();
+        }
+        let loaded = self.assets.poll(&self.device, &self.queue, &self.material_layout);
+        if loaded.contains(&self.diffuse_texture) {
+            self.bind_diffuse_texture();
+        }
+        self.assets.collect_garbage();
+
+        self.camera_controller.update_camera(&mut self.camera);
+        let camera = self.active_camera();
+        self.camera_uniform.update_view_proj(&camera);
+        self.camera_buffer.update(&self.queue, &self.camera_uniform);
+        self.skybox.update(&self.queue, &camera);
+
+        self.shadow_maps.update(&self.queue, &camera, &self.lights.sun, &self.lights.spot_lights);
+        self.lights.update(&self.queue, &self.shadow_maps, &camera, self.config.width, self.config.height);
+
+        let status = format!(
+            "{} shading, {}x MSAA{}",
+            if self.deferred.enabled { "Deferred" } else { "Forward" },
+            self.sample_count,
+            if self.deferred.enabled && self.deferred.ssao.enabled { ", SSAO" } else { "" },
+        );
+        self.text.queue(text::TextSection {
+            text: &status,
+            size: 18.0,
+            color: [1.0; 4],
+            align: text::TextAlign::Left,
+            max_width: None,
+            placement: text::TextPlacement::Screen { x: 12.0, y: 10.0 },
+        });
+        self.text.queue(text::TextSection {
+            text: "WASD, Space and Shift to move. G for deferred shading, X for SSAO, N for the G-buffer views, M for MSAA, L for debug lines, I for the inspector",
+            size: 14.0,
+            color: [0.8, 0.8, 0.8, 1.0],
+            align: text::TextAlign::Right,
+            max_width: Some(300.0),
+            placement: text::TextPlacement::Screen {
+                x: self.config.width as f32 - 12.0,
+                y: 10.0,
+            },
+        });
+        self.text.queue(text::TextSection {
+            text: "Copper",
+            size: 0.4,
+            color: [1.0, 0.85, 0.7, 1.0],
+            align: text::TextAlign::Center,
+            max_width: None,
+            placement: text::TextPlacement::World(Matrix4::from_translation(Vector3::new(3.0, 2.1, -2.0))),
+        });
+        self.text.prepare(&self.device, &self.queue, self.config.width, self.config.height);
+
+        if self.show_sprites {
+            self.queue_demo_sprites();
+        }
+        self.sprites.prepare(&self.device, &self.queue, self.config.width, self.config.height);
+
+        self.draw_light_gizmos();
+        self.debug_draw.prepare(&self.device, &self.queue);
+
+        let input = self.overlay.take_input(&self.window);
+        let output = self.overlay.context().clone().run(input, |ctx| {
+            if self.overlay.visible {
+                self.inspector(ctx);
+            }
+        });
+        self.overlay.finish(&self.window, output);
+    }
+
+    /// A window for looking at and changing the state while running.
+    fn inspector(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Inspector")
+            .default_pos([12.0, 40.0])
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.collapsing("Frame", |ui| {
+                    let average = self.overlay.stats.average();
+                    ui.label(format!(
+                        "{:.0} fps, {:.2} ms (worst {:.2} ms)",
+                        if average > 0.0 { 1.0 / average } else { 0.0 },
+                        average * 1000.0,
+                        self.overlay.stats.worst() * 1000.0,
+                    ));
+                    ui.label(format!("{}x{}, {}x MSAA", self.config.width, self.config.height, self.sample_count));
+                    ui.label(format!(
+                        "{} draw calls, {} point lights, {} spot lights",
+                        self.draw_calls.len(),
+                        self.lights.point_lights.len(),
+                        self.lights.spot_lights.len(),
+                    ));
+                });
+
+                ui.collapsing("Pipeline", |ui| {
+                    let mut deferred = self.deferred.enabled;
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut deferred, false, "Forward");
+                        ui.radio_value(&mut deferred, true, "Deferred");
+                    });
+                    if deferred != self.deferred.enabled {
+                        self.toggle_deferred();
+                    }
+                    ui.checkbox(&mut self.deferred.ssao.enabled, "SSAO");
+                    let meshes = self.demo_meshes.meshes();
+                    let name = |current: Option<usize>| current.map_or("None", |i| meshes[i].name.as_str());
+                    let mut current = self.demo_meshes.current();
+                    egui::ComboBox::from_label("2D demo")
+                        .selected_text(name(current))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut current, None, name(None));
+                            for i in 0..meshes.len() {
+                                ui.selectable_value(&mut current, Some(i), name(Some(i)));
+                            }
+                        });
+                    self.demo_meshes.set_current(current);
+                    ui.checkbox(&mut self.show_sprites, "Sprites");
+                    ui.checkbox(&mut self.debug_draw.enabled, "Debug lines");
+                });
+
+                ui.collapsing("Surface", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Clear colour");
+                        ui.color_edit_button_rgb(&mut self.clear_color);
+                    });
+                    let mut present_mode = self.config.present_mode;
+                    egui::ComboBox::from_label("Present mode")
+                        .selected_text(format!("{:?}", present_mode))
+                        .show_ui(ui, |ui| {
+                            for &mode in &self.present_modes {
+                                ui.selectable_value(&mut present_mode, mode, format!("{:?}", mode));
+                            }
+                        });
+                    if present_mode != self.config.present_mode {
+                        self.config.present_mode = present_mode;
+                        if self.is_surface_configured {
+                            self.surface.configure(&self.device, &self.config);
+                        }
+                        log::info!("Present mode: {:?}", present_mode);
+                    }
+                });
+
+                ui.collapsing("Scene", |ui| {
+                    let name = |node: Option<scene::NodeId>| match node {
+                        Some(node) => self.scene.node(node).name.clone(),
+                        None => "free camera".to_string(),
+                    };
+                    let mut view_node = self.view_node;
+                    egui::ComboBox::from_label("View from")
+                        .selected_text(name(view_node))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut view_node, None, name(None));
+                            for (id, node) in self.scene.nodes().filter(|(_, node)| node.camera.is_some()) {
+                                ui.selectable_value(&mut view_node, Some(id), &node.name);
+                            }
+                        });
+                    self.view_node = view_node;
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if ui.button(format!("Save to {}", SCENE_PATH)).clicked() {
+                        self.save_scene();
+                    }
+                    for &root in self.scene.roots() {
+                        scene_tree(ui, &self.scene, root);
+                    }
+                });
+
+                ui.collapsing("Assets", |ui| {
+                    let storages = [
+                        ("Textures", self.assets.textures.iter().collect::<Vec<_>>()),
+                        ("Meshes", self.assets.meshes.iter().collect()),
+                        ("Shaders", self.assets.shaders.iter().collect()),
+                        ("Materials", self.assets.materials.iter().collect()),
+                    ];
+                    for (kind, assets) in storages {
+                        ui.collapsing(format!("{} ({})", kind, assets.len()), |ui| {
+                            for (name, state) in assets {
+                                ui.label(format!("{}: {:?}", name, state));
+                            }
+                        });
+                    }
+                });
+
+                ui.collapsing("Texture", |ui| {
+                    ui.label(format!(
+                        "happy-tree.png: {:?}",
+                        self.assets.textures.state(&self.diffuse_texture),
+                    ));
+                    ui.image((self.texture_preview, egui::vec2(128.0, 128.0)));
+                });
+            });
+    }
+
+    /// Writes the scene as it is now, seen from the free camera, over the
+    /// file it was loaded from.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_scene(&self) {
+        let file = self.scene_source.to_file(&self.scene, &self.camera, &self.lights.sun, self.clear_color);
+        match scene_file::save(SCENE_PATH, &file) {
+            Ok(()) => log::info!("Saved the scene to {}", SCENE_PATH),
+            Err(e) => log::error!("Failed to save the scene: {:#}", e),
+        }
+    }
+
+    /// Points everything showing the happy tree at the loaded texture in
+    /// place of the placeholder.
+    fn bind_diffuse_texture(&mut self) {
+        let diffuse = self.assets.texture_or_placeholder(&self.diffuse_texture);
+        let bind_group = create_diffuse_bind_group(&self.device, &self.texture_bind_group_layout, diffuse);
+        self.demo_meshes.set_bind_group(self.diffuse_binding, bind_group);
+        self.sprites.set_texture(&self.device, self.happy_tree_sprite, diffuse, "happy_tree_sprite");
+        self.overlay.update_texture(&self.device, self.texture_preview, &diffuse.view);
+    }
+
+    /// Rebuilds the instances, draw calls and lights from the scene graph.
+    fn upload_scene(&mut self) {
+        let (instances, draw_calls) = self.scene.draw_list();
+        let size = std::mem::size_of_val(instances.as_slice()) as wgpu::BufferAddress;
+        if size > self.instance_buffer.size() {
+            self.instance_buffer = create_instance_buffer(&self.device, &instances);
+        } else {
+            self.queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+        }
+        self.draw_calls = draw_calls;
+        (self.lights.point_lights, self.lights.spot_lights) = self.scene.lights();
+    }
+
+    /// Where the lights are and which way they point, with the world axes
+    /// and a grid on the ground.
+    fn draw_light_gizmos(&mut self) {
+        let draw = &mut self.debug_draw;
+        draw.grid(Point3::new(0.0, 0.01, 0.0), 20.0, 20, [1.0, 1.0, 1.0, 0.25]);
+        draw.axes(Matrix4::from_translation(Vector3::new(0.0, 0.02, 0.0)), 1.0);
+
+        let sun = self.lights.sun.direction.normalize();
+        let sun_color = [1.0, 0.9, 0.3, 1.0];
+        draw.arrow(Point3::new(0.0, 6.0, 0.0) - sun * 3.0, Point3::new(0.0, 6.0, 0.0), sun_color);
+
+        for spot in &self.lights.spot_lights {
+            let [r, g, b] = spot.color;
+            let color = [r, g, b, 1.0];
+            let marker = Vector3::new(0.1, 0.1, 0.1);
+            draw.aabb(spot.position - marker, spot.position + marker, color);
+            draw.frustum(spot.view_proj(), [r, g, b, 0.5]);
+        }
+        for point in &self.lights.point_lights {
+            let [r, g, b] = point.color;
+            draw.sphere(point.position, 0.1, [r, g, b, 1.0]);
+        }
+    }
+
+    /// A few happy trees in the bottom left corner, overlapping to show off
+    /// the z-order.
+    fn queue_demo_sprites(&mut self) {
+        let bottom = self.config.height as f32;
+        let tree = self.happy_tree_sprite;
+        self.sprites.queue(sprite::Sprite {
+            scale: [0.4, 0.4],
+            ..sprite::Sprite::new(tree, [80.0, bottom - 80.0])
+        });
+        self.sprites.queue(sprite::Sprite {
+            rotation: Deg(20.0),
+            scale: [0.4, 0.4],
+            tint: [1.0, 0.6, 0.6, 1.0],
+            z_order: 1,
+            ..sprite::Sprite::new(tree, [150.0, bottom - 90.0])
+        });
+        // Only the crown, mirrored and behind the others.
+        self.sprites.queue(sprite::Sprite {
+            scale: [-0.4, 0.4],
+            tint: [0.6, 0.8, 1.0, 0.8],
+            uv_rect: sprite::UvRect {
+                min: [0.0, 0.0],
+                max: [1.0, 0.5],
+            },
+            z_order: -1,
+            ..sprite::Sprite::new(tree, [230.0, bottom - 70.0])
+        });
+    }
+
+    /// Issues every draw call with whatever pipeline is bound, shared by the
+    /// shadow passes and the scene pass. Materials are bound at
+    /// `material_group` if there is one, the shadow pipeline has none.
+    fn draw_meshes(&self, pass: &mut wgpu::RenderPass, material_group: Option<u32>) {
+        // Each draw gets its instances by offsetting into the instance buffer
+        // rather than with a first instance, which WebGL2 can't do.
+        let stride = std::mem::size_of::<mesh::InstanceRaw>() as wgpu::BufferAddress;
+        for call in &self.draw_calls {
+            let Some(mesh) = self.assets.meshes.get(&call.mesh) else {
+                continue;
+            };
+            if let Some(group) = material_group {
+                let Some(material) = self.assets.materials.get(&call.material) else {
+                    continue;
+                };
+                pass.set_bind_group(group, material.bind_group(), &[]);
+            }
+            pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            pass.set_vertex_buffer(1, self.instance_buffer.slice(call.instances.start as u64 * stride..));
+            pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            pass.draw_indexed(0..mesh.num_elements, 0, 0..call.instances.len() as u32);
+        }
     }
 
 
@@ -437,54 +1051,345 @@ impl State {
             label: Some("Render Encoder"),
         });
 
-        
+
+        self.shadow_maps.render(&mut encoder, |pass| self.draw_meshes(pass, None));
+        self.lights.cull(&mut encoder);
+
+        // The scene goes into the post-processing chain's scene target. With
+        // MSAA on we draw into the multisampled target and let the pass
+        // resolve it into the scene target instead.
+        let scene_view = self.post.scene_view();
+        let [r, g, b] = self.clear_color.map(f64::from);
+        let clear_color = wgpu::Color { r, g, b, a: 1.0 };
+        let environment = self.environments[self.environment].bind_group();
+
+        // Deferred shading lights the scene target on its own, the pass below
+        // then only adds the sky and the 2D shapes, against the G-buffer's
+        // depth.
+        if self.deferred.enabled {
+            let mut geometry_pass = self.deferred.begin_geometry_pass(&mut encoder, scene_view, clear_color);
+            geometry_pass.set_bind_group(0, &self.camera_buffer.bind_group, &[]);
+            geometry_pass.set_bind_group(1, self.lights.bind_group(), &[]);
+            geometry_pass.set_bind_group(3, environment, &[]);
+            self.draw_meshes(&mut geometry_pass, Some(2));
+            drop(geometry_pass);
+            self.deferred.light(&self.queue, &mut encoder, scene_view, &self.camera_buffer.bind_group, &self.lights, environment);
+        }
+        let (color_load, depth_view, depth_load) = if self.deferred.enabled {
+            (wgpu::LoadOp::Load, self.deferred.depth_view(), wgpu::LoadOp::Load)
+        } else {
+            (wgpu::LoadOp::Clear(clear_color), &self.depth_texture.view, wgpu::LoadOp::Clear(1.0))
+        };
+        let (color_view, resolve_target) = match &self.msaa_texture {
+            Some(msaa) => (&msaa.view, Some(scene_view)),
+            None => (scene_view, None),
+        };
+
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
+                view: color_view,
+                resolve_target,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.1,
-                        g: 0.2,
-                        b: 0.3,
-                        a: 1.0,
-                    }),
+                    load: color_load,
                     store: wgpu::StoreOp::Store,
                 },
             })],
-            depth_stencil_attachment: None,
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: depth_load,
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: None,
+            }),
             occlusion_query_set: None,
             timestamp_writes: None,
         });
-        if self.use_color {
-            render_pass.set_pipeline(&self.color_render_pipeline);
-            render_pass.draw(0..3, 0..1);
-        //} else if self.use_funny {
-            //render_pass.set_pipeline(&self.render_pipeline);
-            //render_pass.set_vertex_buffer(0, self.funny_vertex_buffer.slice(..));
-            //render_pass.set_index_buffer(self.funny_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            //render_pass.draw_indexed(0..self.funny_num_indices, 0, 0..1);
-        }
-         else {
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+
+        if !self.deferred.enabled {
+            render_pass.set_pipeline(&self.pipelines.pbr);
+            render_pass.set_bind_group(0, &self.camera_buffer.bind_group, &[]);
+            render_pass.set_bind_group(1, self.lights.bind_group(), &[]);
+            render_pass.set_bind_group(3, environment, &[]);
+            self.draw_meshes(&mut render_pass, Some(2));
         }
 
+        render_pass.set_pipeline(&self.pipelines.skybox);
+        self.skybox.draw(&mut render_pass);
+
+        render_pass.set_pipeline(&self.pipelines.text);
+        render_pass.set_bind_group(0, &self.camera_buffer.bind_group, &[]);
+        self.text.draw_world(&mut render_pass);
+
+        // The 2D shapes sit at depth 0 and so always end up on top.
+        self.demo_meshes.draw(&mut render_pass);
+
+        render_pass.set_pipeline(&self.pipelines.debug_draw);
+        render_pass.set_bind_group(0, &self.camera_buffer.bind_group, &[]);
+        self.debug_draw.draw(&mut render_pass);
+
         // drop for encoder borrow to end,
         // the render pass did not panic if we reach this code,
         // so we just ignore the result... for now
         drop(render_pass);
-        
-        self.queue.submit(std::iter::once(encoder.finish()));
+
+        self.bloom.run(&self.queue, &mut encoder, self.post.scene_bind_group(), self.post.scene_view());
+        let post_output = self.post.run(&self.queue, &mut encoder);
+        self.tonemap.run(&self.queue, &mut encoder, post_output, &view);
+        self.sprites.draw(&mut encoder, &view);
+        let camera = self.active_camera();
+        self.deferred.draw_debug(&self.queue, &mut encoder, &view, camera.znear, camera.zfar);
+        self.shadow_maps.draw_debug(&self.queue, &mut encoder, &view, self.config.width, self.config.height);
+        self.text.draw_screen(&mut encoder, &view);
+        let overlay_commands = self.overlay.draw(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            &view,
+            self.config.width,
+            self.config.height,
+        );
+
+        self.queue.submit(overlay_commands.into_iter().chain(std::iter::once(encoder.finish())));
         output.present();
         Ok(())
     }
 }
 
+/// `id` and everything below it in the inspector, with what each node
+/// carries.
+fn scene_tree(ui: &mut egui::Ui, scene: &scene::SceneGraph, id: scene::NodeId) {
+    let node = scene.node(id);
+    let position = node.world_matrix().w;
+    let mut label = format!("{} ({:.1}, {:.1}, {:.1})", node.name, position.x, position.y, position.z);
+    for (attached, tag) in [
+        (node.mesh.is_some(), "mesh"),
+        (node.light.is_some(), "light"),
+        (node.camera.is_some(), "camera"),
+    ] {
+        if attached {
+            label = format!("{} [{}]", label, tag);
+        }
+    }
+    if node.children().is_empty() {
+        ui.label(label);
+        return;
+    }
+    egui::CollapsingHeader::new(label)
+        .id_salt(id)
+        .show(ui, |ui| {
+            for &child in node.children() {
+                scene_tree(ui, scene, child);
+            }
+        });
+}
+
+/// The MSAA sample counts both `scene_format` and the depth buffer can be
+/// multisampled at. Only 1 and 4 unless the device was given
+/// `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES`.
+fn supported_sample_counts(adapter: &wgpu::Adapter, device: &wgpu::Device, scene_format: wgpu::TextureFormat) -> Vec<u32> {
+    let adapter_specific = device.features().contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+    let color = adapter.get_texture_format_features(scene_format).flags;
+    let depth = adapter.get_texture_format_features(texture::Texture::DEPTH_FORMAT).flags;
+    [1, 2, 4, 8]
+        .into_iter()
+        .filter(|&count| adapter_specific || matches!(count, 1 | 4))
+        .filter(|&count| color.sample_count_supported(count) && depth.sample_count_supported(count))
+        .collect()
+}
+
+fn create_diffuse_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    diffuse: &texture::Texture,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&diffuse.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&diffuse.sampler),
+            },
+        ],
+        label: Some("diffuse_bind_group"),
+    })
+}
+
+fn create_instance_buffer(device: &wgpu::Device, instances: &[mesh::InstanceRaw]) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Instance Buffer"),
+        contents: bytemuck::cast_slice(instances),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    })
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+    vertex_layouts: &[wgpu::VertexBufferLayout],
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: vertex_layouts,
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: color_format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// The layouts [`ScenePipelines`] are built from, they don't depend on the
+/// sample count and outlive any one set of pipelines.
+struct ScenePipelineLayouts {
+    textured: wgpu::PipelineLayout,
+    pbr: wgpu::PipelineLayout,
+    skybox: wgpu::PipelineLayout,
+    text: wgpu::PipelineLayout,
+    debug_draw: wgpu::PipelineLayout,
+}
+
+/// Every pipeline that draws into the scene target, rebuilt whenever the
+/// MSAA sample count changes.
+struct ScenePipelines {
+    textured: wgpu::RenderPipeline,
+    color: wgpu::RenderPipeline,
+    pbr: wgpu::RenderPipeline,
+    skybox: wgpu::RenderPipeline,
+    text: wgpu::RenderPipeline,
+    debug_draw: wgpu::RenderPipeline,
+    /// Only held, so rebuilding for another sample count finds the shaders
+    /// already compiled.
+    _shaders: [assets::Handle<wgpu::ShaderModule>; 3],
+}
+
+impl ScenePipelines {
+    /// The PBR shader depends on how `lights` finds its point lights.
+    fn new(
+        device: &wgpu::Device,
+        assets: &mut assets::Assets,
+        layouts: &ScenePipelineLayouts,
+        lights: &light::Lights,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let shaders = [
+            assets.shader(device, wgpu::include_wgsl!("shader.wgsl")),
+            assets.shader(device, wgpu::include_wgsl!("color_shader.wgsl")),
+            assets.shader(device, material::pbr_shader(&lights.wgsl())),
+        ];
+        let [textured_shader, color_shader, pbr_shader] = shaders.each_ref().map(|shader| {
+            assets.shaders.get(shader).expect("shader modules are ready as soon as they're made")
+        });
+        let textured = create_render_pipeline(
+            device,
+            &layouts.textured,
+            color_format,
+            sample_count,
+            &[Vertex::desc()],
+            textured_shader,
+        );
+        let color = create_render_pipeline(
+            device,
+            &layouts.textured,
+            color_format,
+            sample_count,
+            &[],
+            color_shader,
+        );
+        let pbr = create_render_pipeline(
+            device,
+            &layouts.pbr,
+            color_format,
+            sample_count,
+            &[mesh::MeshVertex::desc(), mesh::InstanceRaw::desc()],
+            pbr_shader,
+        );
+        let skybox = skybox::create_pipeline(device, &layouts.skybox, color_format, sample_count);
+        let text = text::create_world_pipeline(device, &layouts.text, color_format, sample_count);
+        let debug_draw = debug_draw::create_pipeline(device, &layouts.debug_draw, color_format, sample_count);
+        Self {
+            textured,
+            color,
+            pbr,
+            skybox,
+            text,
+            debug_draw,
+            _shaders: shaders,
+        }
+    }
+}
+
+/// Six flat-coloured faces with a grid on them, for checking cube map
+/// orientation: +X red, -X cyan, +Y green, -Y magenta, +Z blue, -Z yellow.
+fn debug_cube_faces(size: u32) -> Vec<image::DynamicImage> {
+    let colors = [
+        [255, 64, 64],
+        [64, 255, 255],
+        [64, 255, 64],
+        [255, 64, 255],
+        [64, 64, 255],
+        [255, 255, 64],
+    ];
+    colors
+        .iter()
+        .map(|&[r, g, b]| {
+            let cell = (size / 8).max(1);
+            let face = image::RgbaImage::from_fn(size, size, |x, y| {
+                if x % cell == 0 || y % cell == 0 {
+                    image::Rgba([255, 255, 255, 255])
+                } else {
+                    image::Rgba([r / 2, g / 2, b / 2, 255])
+                }
+            });
+            image::DynamicImage::ImageRgba8(face)
+        })
+        .collect()
+}
+
 pub struct App {
     #[cfg(target_arch = "wasm32")]
     proxy: Option<winit::event_loop::EventLoopProxy<State>>,
@@ -492,6 +1397,7 @@ pub struct App {
 }
 
 impl App {
+    #[allow(clippy::new_without_default)]
     pub fn new(#[cfg(target_arch = "wasm32")] event_loop: &EventLoop<State>) -> Self {
         #[cfg(target_arch = "wasm32")]
         let proxy = Some(event_loop.create_proxy());
@@ -570,11 +1476,18 @@ impl ApplicationHandler<State> for App {
             Some(canvas) => canvas,
             None => return,
         };
+        // Releases go through regardless, a key held down when egui took
+        // focus would otherwise stay down for the camera.
+        let released = matches!(&event, WindowEvent::KeyboardInput { event, .. } if !event.state.is_pressed());
+        if state.overlay.handle_event(&state.window, &event) && !released {
+            return;
+        }
 
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
             WindowEvent::Resized(size) => state.resize(size.width, size.height),
             WindowEvent::RedrawRequested => {
+                state.update();
                 match state.render() {
                     Ok(_) => {}
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
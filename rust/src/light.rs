@@ -0,0 +1,238 @@
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, Vector3};
+
+use crate::camera::{Camera, OPENGL_TO_WGPU_MATRIX};
+use crate::cluster::LightClusters;
+use crate::shadow::{ShadowMaps, CASCADE_COUNT};
+use crate::uniform::{UniformBuffer, WgslType};
+use crate::wgsl_layout;
+
+pub const MAX_SPOT_LIGHTS: usize = 4;
+
+const LIGHTS_WGSL: &str = include_str!("lights.wgsl");
+
+/// A light infinitely far away, like the sun.
+pub struct DirectionalLight {
+    /// The direction the light travels in, from the light towards the scene.
+    pub direction: Vector3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct SpotLight {
+    pub position: Point3<f32>,
+    pub direction: Vector3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Distance at which the light has faded out completely.
+    pub range: f32,
+    /// Half angle of the fully lit cone.
+    pub inner_angle: Deg<f32>,
+    /// Half angle past which the light is gone.
+    pub outer_angle: Deg<f32>,
+}
+
+impl SpotLight {
+    /// The perspective the light's shadow map is rendered with.
+    pub fn view_proj(&self) -> Matrix4<f32> {
+        let direction = self.direction.normalize();
+        let up = if direction.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() };
+        let view = Matrix4::look_to_rh(self.position, direction, up);
+        let proj = cgmath::perspective(self.outer_angle * 2.0, 1.0, 0.1, self.range);
+        OPENGL_TO_WGPU_MATRIX * proj * view
+    }
+
+    fn to_raw(&self) -> SpotLightRaw {
+        let direction = self.direction.normalize();
+        let color = self.color.map(|c| c * self.intensity);
+        SpotLightRaw {
+            view_proj: self.view_proj().into(),
+            position_range: [self.position.x, self.position.y, self.position.z, self.range],
+            direction_outer: [direction.x, direction.y, direction.z, cgmath::Angle::cos(self.outer_angle)],
+            color_inner: [color[0], color[1], color[2], cgmath::Angle::cos(self.inner_angle)],
+        }
+    }
+}
+
+/// A small light shining in every direction, shaded through
+/// [`LightClusters`] so there can be hundreds of them. Point lights cast no
+/// shadows.
+#[derive(Clone, Debug)]
+pub struct PointLight {
+    pub position: Point3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Distance at which the light has faded out completely.
+    pub range: f32,
+}
+
+impl PointLight {
+    pub fn to_raw(&self) -> PointLightRaw {
+        let color = self.color.map(|c| c * self.intensity);
+        PointLightRaw {
+            position_range: [self.position.x, self.position.y, self.position.z, self.range],
+            color: [color[0], color[1], color[2], 0.0],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointLightRaw {
+    position_range: [f32; 4],
+    color: [f32; 4],
+}
+
+wgsl_layout!(PointLightRaw {
+    position_range: WgslType::Vec4,
+    color: WgslType::Vec4,
+});
+
+pub const POINT_LIGHT: WgslType = WgslType::of::<PointLightRaw>();
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SpotLightRaw {
+    view_proj: [[f32; 4]; 4],
+    position_range: [f32; 4],
+    /// `w` is the cosine of the outer angle.
+    direction_outer: [f32; 4],
+    /// `w` is the cosine of the inner angle.
+    color_inner: [f32; 4],
+}
+
+wgsl_layout!(SpotLightRaw {
+    view_proj: WgslType::Mat4x4,
+    position_range: WgslType::Vec4,
+    direction_outer: WgslType::Vec4,
+    color_inner: WgslType::Vec4,
+});
+
+const SPOT_LIGHT: WgslType = WgslType::of::<SpotLightRaw>();
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightsUniform {
+    cascade_view_proj: [[[f32; 4]; 4]; CASCADE_COUNT],
+    cascade_splits: [f32; 4],
+    sun_direction: [f32; 4],
+    sun_color: [f32; 4],
+    ambient: [f32; 4],
+    spot_lights: [SpotLightRaw; MAX_SPOT_LIGHTS],
+    spot_count: u32,
+    pcf_radius: u32,
+    normal_bias: f32,
+}
+
+wgsl_layout!(LightsUniform {
+    cascade_view_proj: WgslType::Array(&WgslType::Mat4x4, CASCADE_COUNT),
+    cascade_splits: WgslType::Vec4,
+    sun_direction: WgslType::Vec4,
+    sun_color: WgslType::Vec4,
+    ambient: WgslType::Vec4,
+    spot_lights: WgslType::Array(&SPOT_LIGHT, MAX_SPOT_LIGHTS),
+    spot_count: WgslType::U32,
+    pcf_radius: WgslType::U32,
+    normal_bias: WgslType::F32,
+});
+
+/// The lights in the scene and the bind group the lit shaders read them
+/// from, together with the shadow maps they cast.
+pub struct Lights {
+    pub sun: DirectionalLight,
+    pub spot_lights: Vec<SpotLight>,
+    pub point_lights: Vec<PointLight>,
+    pub ambient: [f32; 3],
+    uniform: UniformBuffer<LightsUniform>,
+    clusters: LightClusters,
+}
+
+impl Lights {
+    pub fn new(
+        device: &wgpu::Device,
+        shadow_maps: &ShadowMaps,
+        sun: DirectionalLight,
+        spot_lights: Vec<SpotLight>,
+        point_lights: Vec<PointLight>,
+    ) -> Self {
+        if spot_lights.len() > MAX_SPOT_LIGHTS {
+            log::warn!(
+                "{} spot lights given, only the first {} are used",
+                spot_lights.len(),
+                MAX_SPOT_LIGHTS
+            );
+        }
+
+        let clusters = LightClusters::new(device);
+        let resources: Vec<_> = shadow_maps
+            .sampling_resources(1)
+            .into_iter()
+            .chain(clusters.sampling_resources(3))
+            .collect();
+        let uniform = UniformBuffer::with_resources(
+            device,
+            &bytemuck::Zeroable::zeroed(),
+            wgpu::ShaderStages::VERTEX_FRAGMENT,
+            &resources,
+            "lights",
+        );
+
+        Self {
+            sun,
+            spot_lights,
+            point_lights,
+            ambient: [0.03, 0.03, 0.04],
+            uniform,
+            clusters,
+        }
+    }
+
+    /// The WGSL side of the lights, bound at group 1, with the shadow and
+    /// point light lookups. Which point light path it takes depends on the
+    /// device.
+    pub fn wgsl(&self) -> String {
+        format!("{}\n{}", LIGHTS_WGSL, self.clusters.wgsl())
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.uniform.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.uniform.bind_group
+    }
+
+    /// Uploads the lights along with the shadow projections `shadow_maps`
+    /// was last updated with. The point lights are sorted for `camera`
+    /// looking at a `width` by `height` target.
+    pub fn update(&self, queue: &wgpu::Queue, shadow_maps: &ShadowMaps, camera: &Camera, width: u32, height: u32) {
+        let mut raw = LightsUniform {
+            cascade_view_proj: [[[0.0; 4]; 4]; CASCADE_COUNT],
+            cascade_splits: [0.0; 4],
+            sun_direction: self.sun.direction.normalize().extend(0.0).into(),
+            sun_color: {
+                let c = self.sun.color.map(|c| c * self.sun.intensity);
+                [c[0], c[1], c[2], 0.0]
+            },
+            ambient: [self.ambient[0], self.ambient[1], self.ambient[2], 0.0],
+            spot_lights: bytemuck::Zeroable::zeroed(),
+            spot_count: self.spot_lights.len().min(MAX_SPOT_LIGHTS) as u32,
+            pcf_radius: shadow_maps.pcf_radius,
+            normal_bias: shadow_maps.normal_bias,
+        };
+        for (cascade, split) in shadow_maps.cascade_splits().into_iter().enumerate() {
+            raw.cascade_view_proj[cascade] = shadow_maps.view_proj(cascade).into();
+            raw.cascade_splits[cascade] = split;
+        }
+        for (raw, spot) in raw.spot_lights.iter_mut().zip(self.spot_lights.iter()) {
+            *raw = spot.to_raw();
+        }
+        self.uniform.update(queue, &raw);
+        self.clusters.update(queue, &self.point_lights, camera, width, height);
+    }
+
+    /// Assigns the point lights to clusters, see [`LightClusters::cull`].
+    pub fn cull(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.clusters.cull(encoder);
+    }
+}
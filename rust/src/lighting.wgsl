@@ -0,0 +1,91 @@
+const PI: f32 = 3.14159265359;
+
+struct Surface {
+    albedo: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    normal: vec3<f32>,
+    occlusion: f32,
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+fn geometry_schlick_ggx(n_dot_x: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (vec3<f32>(1.0) - f0) * pow(saturate(1.0 - cos_theta), 5.0);
+}
+
+// Cook-Torrance specular plus Lambert diffuse for one light, already
+// multiplied by n.l. `light_dir` and `view_dir` point away from the surface.
+fn brdf(surface: Surface, light_dir: vec3<f32>, view_dir: vec3<f32>) -> vec3<f32> {
+    let n = surface.normal;
+    let h = normalize(view_dir + light_dir);
+    let n_dot_l = max(dot(n, light_dir), 0.0);
+    let n_dot_v = max(dot(n, view_dir), 1e-4);
+    let n_dot_h = max(dot(n, h), 0.0);
+
+    let f0 = mix(vec3<f32>(0.04), surface.albedo, surface.metallic);
+    let f = fresnel_schlick(max(dot(h, view_dir), 0.0), f0);
+    let d = distribution_ggx(n_dot_h, surface.roughness);
+    let g = geometry_schlick_ggx(n_dot_v, surface.roughness) * geometry_schlick_ggx(n_dot_l, surface.roughness);
+    let specular = d * g * f / (4.0 * n_dot_v * max(n_dot_l, 1e-4));
+
+    // Metals have no diffuse, and what is reflected can't also diffuse.
+    let k_d = (vec3<f32>(1.0) - f) * (1.0 - surface.metallic);
+    return (k_d * surface.albedo / PI + specular) * n_dot_l;
+}
+
+// Everything that lights `surface` at `world_position`: the environment,
+// the sun and spot lights with their shadows, and the point lights of the
+// cluster at `frag_coord`. Shadow lookups are pushed out along
+// `geometric_normal`.
+fn shade(
+    surface: Surface,
+    world_position: vec3<f32>,
+    geometric_normal: vec3<f32>,
+    view_depth: f32,
+    frag_coord: vec2<f32>,
+) -> vec3<f32> {
+    let view_dir = normalize(camera.view_pos.xyz - world_position);
+
+    var color = (environment_light(surface.albedo, surface.metallic, surface.roughness, surface.normal, view_dir)
+        + lights.ambient.rgb * surface.albedo) * surface.occlusion;
+
+    let sun = sun_shadow(view_depth, world_position, geometric_normal);
+    color += brdf(surface, -lights.sun_direction.xyz, view_dir) * lights.sun_color.rgb * sun;
+
+    for (var i = 0u; i < lights.spot_count; i += 1u) {
+        let spot = lights.spot_lights[i];
+        let light_dir = normalize(spot.position_range.xyz - world_position);
+        let attenuation = spot_attenuation(spot, world_position, light_dir);
+        if (attenuation <= 0.0) {
+            continue;
+        }
+        let shadow = shadow_factor(CASCADE_COUNT + i, spot.view_proj, world_position, geometric_normal);
+        color += brdf(surface, light_dir, view_dir) * spot.color_inner.rgb * attenuation * shadow;
+    }
+
+    let cluster = point_light_cluster(frag_coord, view_depth);
+    let point_count = point_light_count(cluster);
+    for (var i = 0u; i < point_count; i += 1u) {
+        let light = point_light(cluster, i);
+        let attenuation = point_attenuation(light, world_position);
+        if (attenuation <= 0.0) {
+            continue;
+        }
+        let light_dir = normalize(light.position_range.xyz - world_position);
+        color += brdf(surface, light_dir, view_dir) * light.color.rgb * attenuation;
+    }
+
+    return color;
+}
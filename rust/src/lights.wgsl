@@ -0,0 +1,72 @@
+const CASCADE_COUNT: u32 = 3u;
+const MAX_SPOT_LIGHTS: u32 = 4u;
+
+struct SpotLight {
+    view_proj: mat4x4<f32>,
+    position_range: vec4<f32>,
+    direction_outer: vec4<f32>,
+    color_inner: vec4<f32>,
+}
+
+struct Lights {
+    cascade_view_proj: array<mat4x4<f32>, CASCADE_COUNT>,
+    cascade_splits: vec4<f32>,
+    sun_direction: vec4<f32>,
+    sun_color: vec4<f32>,
+    ambient: vec4<f32>,
+    spot_lights: array<SpotLight, MAX_SPOT_LIGHTS>,
+    spot_count: u32,
+    pcf_radius: u32,
+    normal_bias: f32,
+}
+@group(1) @binding(0)
+var<uniform> lights: Lights;
+@group(1) @binding(1)
+var t_shadow: texture_depth_2d_array;
+@group(1) @binding(2)
+var s_shadow: sampler_comparison;
+
+// Fraction of light reaching `world_position` from the shadow map in
+// `layer`, averaged over a (2 * pcf_radius + 1)^2 texel kernel.
+fn shadow_factor(layer: u32, view_proj: mat4x4<f32>, world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    // Pushing the lookup out along the normal keeps surfaces from shadowing
+    // themselves at grazing angles.
+    let clip = view_proj * vec4<f32>(world_position + normal * lights.normal_bias, 1.0);
+    let ndc = clip.xyz / clip.w;
+    if (any(abs(ndc.xy) > vec2<f32>(1.0)) || ndc.z > 1.0 || ndc.z < 0.0) {
+        return 1.0;
+    }
+
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5);
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_shadow));
+    let radius = i32(lights.pcf_radius);
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y += 1) {
+        for (var x = -radius; x <= radius; x += 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, layer, ndc.z);
+        }
+    }
+    let taps = f32((2 * radius + 1) * (2 * radius + 1));
+    return lit / taps;
+}
+
+fn sun_shadow(view_depth: f32, world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    for (var cascade = 0u; cascade < CASCADE_COUNT; cascade += 1u) {
+        if (view_depth < lights.cascade_splits[cascade]) {
+            return shadow_factor(cascade, lights.cascade_view_proj[cascade], world_position, normal);
+        }
+    }
+    // Past the last cascade nothing is in shadow.
+    return 1.0;
+}
+
+// How much of `spot` reaches `world_position`, cone and distance
+// falloff only. `light_dir` points from the surface to the light.
+fn spot_attenuation(spot: SpotLight, world_position: vec3<f32>, light_dir: vec3<f32>) -> f32 {
+    let distance = length(spot.position_range.xyz - world_position);
+    let cos_angle = dot(-light_dir, spot.direction_outer.xyz);
+    let cone = smoothstep(spot.direction_outer.w, spot.color_inner.w, cos_angle);
+    let falloff = saturate(1.0 - pow(distance / spot.position_range.w, 4.0));
+    return cone * falloff * falloff / (distance * distance + 1.0);
+}
@@ -0,0 +1,394 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::OnceLock;
+
+use anyhow::Context;
+
+use crate::mesh::MeshData;
+
+/// A decoded image: the pixels natively, and on the web an `ImageBitmap`
+/// the browser decoded.
+#[cfg(not(target_arch = "wasm32"))]
+pub type Image = image::DynamicImage;
+#[cfg(target_arch = "wasm32")]
+pub type Image = wgpu::web_sys::ImageBitmap;
+
+/// A file [`Loader`] was asked for, read and decoded, or not. Either way
+/// it's tagged with the path it was asked for by.
+pub enum Loaded {
+    Image {
+        path: String,
+        image: anyhow::Result<Image>,
+    },
+    Model {
+        path: String,
+        model: anyhow::Result<MeshData>,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Image,
+    Model,
+}
+
+/// Reads and decodes images and models: on a worker thread from
+/// [`asset_root`] natively, and with `fetch` relative to the page on the web.
+/// Finished files are picked up with [`Loader::finished`], uploading them is
+/// left to the caller. On the web images are decoded by the browser with
+/// `createImageBitmap`, off the main thread, and models are parsed as they
+/// arrive.
+///
+/// Natively the directories of loaded files are watched too, and
+/// [`Loader::changed_files`] lists the ones that changed on disk since so
+/// they can be loaded again.
+pub struct Loader {
+    #[cfg(not(target_arch = "wasm32"))]
+    requests: mpsc::Sender<(Kind, String)>,
+    #[cfg(not(target_arch = "wasm32"))]
+    watcher: Option<watch::Watcher>,
+    #[cfg(target_arch = "wasm32")]
+    results_sender: mpsc::Sender<Loaded>,
+    results: mpsc::Receiver<Loaded>,
+}
+
+impl Loader {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        let (results_sender, results) = mpsc::channel();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let (requests, request_receiver) = mpsc::channel::<(Kind, String)>();
+            std::thread::Builder::new()
+                .name("asset loader".to_string())
+                .spawn(move || {
+                    for (kind, path) in request_receiver {
+                        let bytes = read_file(&path);
+                        let loaded = match kind {
+                            Kind::Image => Loaded::Image { image: decode_image(&path, bytes), path },
+                            Kind::Model => Loaded::Model { model: parse_model(&path, bytes), path },
+                        };
+                        if results_sender.send(loaded).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .expect("failed to start the asset loader thread");
+            // Loading works without it, there's just no reloading.
+            let watcher = watch::Watcher::new()
+                .inspect_err(|e| log::warn!("Not watching assets for changes: {:#}", e))
+                .ok();
+            Self { requests, watcher, results }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        Self { results_sender, results }
+    }
+
+    /// Starts loading the image at `path`, to come back from
+    /// [`Loader::finished`].
+    pub fn load_image(&mut self, path: &str) {
+        self.load(Kind::Image, path);
+    }
+
+    /// Starts loading the OBJ model at `path`, to come back from
+    /// [`Loader::finished`].
+    pub fn load_model(&mut self, path: &str) {
+        self.load(Kind::Model, path);
+    }
+
+    fn load(&mut self, kind: Kind, path: &str) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some(watcher) = &mut self.watcher {
+                watcher.watch(path);
+            }
+            self.requests
+                .send((kind, path.to_string()))
+                .expect("the asset loader thread runs as long as the loader");
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            let sender = self.results_sender.clone();
+            let path = path.to_string();
+            wasm_bindgen_futures::spawn_local(async move {
+                let loaded = match kind {
+                    Kind::Image => Loaded::Image { image: fetch_image(&path).await, path },
+                    Kind::Model => Loaded::Model { model: parse_model(&path, fetch_bytes(&path).await), path },
+                };
+                // The loader may be gone by the time the fetch comes back.
+                let _ = sender.send(loaded);
+            });
+        }
+    }
+
+    /// The files that finished loading since the last call.
+    pub fn finished(&self) -> impl Iterator<Item = Loaded> + '_ {
+        self.results.try_iter()
+    }
+
+    /// Paths of loaded files that changed on disk since the last call, as
+    /// they were asked for. Always empty on the web.
+    pub fn changed_files(&self) -> Vec<String> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(watcher) = &self.watcher {
+            return watcher.changed_files();
+        }
+        Vec::new()
+    }
+}
+
+/// Reads the file at `path` in one go, for things the app can't start
+/// without.
+pub async fn read_bytes(path: &str) -> anyhow::Result<Vec<u8>> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        read_file(path)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        fetch_bytes(path).await
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn decode_image(path: &str, bytes: anyhow::Result<Vec<u8>>) -> anyhow::Result<Image> {
+    bytes.and_then(|bytes| image::load_from_memory(&bytes).with_context(|| format!("decoding {}", path)))
+}
+
+fn parse_model(path: &str, bytes: anyhow::Result<Vec<u8>>) -> anyhow::Result<MeshData> {
+    bytes.and_then(|bytes| MeshData::from_obj(&bytes).with_context(|| format!("parsing {}", path)))
+}
+
+/// A directory with this in it is taken for the asset root.
+#[cfg(not(target_arch = "wasm32"))]
+const ASSET_MARKER: &str = "scenes";
+
+/// The directory files are read from natively, worked out the first time
+/// it's needed: `$ASSET_ROOT` if that's set, otherwise the first of the
+/// current directory and those the executable is in that has
+/// [`ASSET_MARKER`] in it, or the current directory if none has.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn asset_root() -> &'static Path {
+    static ROOT: OnceLock<PathBuf> = OnceLock::new();
+    ROOT.get_or_init(|| {
+        let root = find_asset_root(
+            std::env::var_os("ASSET_ROOT").map(PathBuf::from),
+            std::env::current_dir().ok(),
+            std::env::current_exe().ok(),
+        );
+        log::info!("Reading assets from {}", root.display());
+        root
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn find_asset_root(configured: Option<PathBuf>, current: Option<PathBuf>, executable: Option<PathBuf>) -> PathBuf {
+    if let Some(root) = configured {
+        return root;
+    }
+    let found = current
+        .iter()
+        .map(PathBuf::as_path)
+        .chain(executable.iter().flat_map(|executable| executable.ancestors().skip(1)))
+        .find(|directory| directory.join(ASSET_MARKER).is_dir())
+        .map(Path::to_path_buf);
+    found.or(current).unwrap_or_default()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_file(path: &str) -> anyhow::Result<Vec<u8>> {
+    let full_path = asset_root().join(path);
+    std::fs::read(&full_path).with_context(|| format!("reading {}", full_path.display()))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod watch {
+    use std::collections::HashSet;
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+    use notify_debouncer_mini::{DebounceEventResult, Debouncer, new_debouncer};
+
+    /// Editors tend to save in a few steps, this waits for them to finish.
+    const DEBOUNCE: Duration = Duration::from_millis(200);
+
+    /// Watches the directories of loaded files rather than the files
+    /// themselves, since saving by writing a new file and renaming it over
+    /// the old one would stop a watch on the file.
+    pub struct Watcher {
+        debouncer: Debouncer<RecommendedWatcher>,
+        events: mpsc::Receiver<DebounceEventResult>,
+        root: PathBuf,
+        directories: HashSet<PathBuf>,
+    }
+
+    impl Watcher {
+        pub fn new() -> anyhow::Result<Self> {
+            let (sender, events) = mpsc::channel();
+            let debouncer = new_debouncer(DEBOUNCE, sender)?;
+            let root = super::asset_root();
+            // Events come with the real path on some platforms.
+            let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+            Ok(Self {
+                debouncer,
+                events,
+                root,
+                directories: HashSet::new(),
+            })
+        }
+
+        /// Starts watching `path` relative to the asset root.
+        pub fn watch(&mut self, path: &str) {
+            let Some(directory) = self.root.join(path).parent().map(Path::to_path_buf) else {
+                return;
+            };
+            if self.directories.contains(&directory) {
+                return;
+            }
+            match self.debouncer.watcher().watch(&directory, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    self.directories.insert(directory);
+                }
+                Err(e) => log::warn!("Not watching {} for changes: {}", directory.display(), e),
+            }
+        }
+
+        /// Every file in a watched directory that changed, relative to the
+        /// asset root with `/` separators. Files that weren't loaded
+        /// are in here too, it's up to the caller to skip them.
+        pub fn changed_files(&self) -> Vec<String> {
+            let mut changed = Vec::new();
+            for result in self.events.try_iter() {
+                let events = match result {
+                    Ok(events) => events,
+                    Err(e) => {
+                        log::warn!("Watching assets failed: {}", e);
+                        continue;
+                    }
+                };
+                for event in events {
+                    let Some(path) = relative_path(&self.root, &event.path) else {
+                        continue;
+                    };
+                    if !changed.contains(&path) {
+                        changed.push(path);
+                    }
+                }
+            }
+            changed
+        }
+    }
+
+    /// `path` relative to `root` with `/` separators, the way files are
+    /// asked for, or nothing if it's outside `root`.
+    pub(super) fn relative_path(root: &Path, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(root).ok()?;
+        let path = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        Some(path)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn fetch(url: &str) -> anyhow::Result<wgpu::web_sys::Response> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    let js_error = |e: wasm_bindgen::JsValue| anyhow::anyhow!("fetching {}: {:?}", url, e);
+    let window = wgpu::web_sys::window().context("no window to fetch from")?;
+    let response: wgpu::web_sys::Response = JsFuture::from(window.fetch_with_str(url))
+        .await
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(js_error)?;
+    if !response.ok() {
+        anyhow::bail!("fetching {} failed with status {}", url, response.status());
+    }
+    Ok(response)
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn fetch_bytes(url: &str) -> anyhow::Result<Vec<u8>> {
+    use wasm_bindgen_futures::JsFuture;
+
+    let response = fetch(url).await?;
+    let buffer = JsFuture::from(response.array_buffer().map_err(|e| anyhow::anyhow!("fetching {}: {:?}", url, e))?)
+        .await
+        .map_err(|e| anyhow::anyhow!("fetching {}: {:?}", url, e))?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+/// Fetches the image at `url` and has the browser decode it, which it does
+/// off the main thread.
+#[cfg(target_arch = "wasm32")]
+async fn fetch_image(url: &str) -> anyhow::Result<Image> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+    use wgpu::web_sys::{Blob, ColorSpaceConversion, ImageBitmapOptions, PremultiplyAlpha};
+
+    let response = fetch(url).await?;
+    let blob: Blob = JsFuture::from(response.blob().map_err(|e| anyhow::anyhow!("fetching {}: {:?}", url, e))?)
+        .await
+        .and_then(|blob| blob.dyn_into())
+        .map_err(|e| anyhow::anyhow!("fetching {}: {:?}", url, e))?;
+    // The pixels as they are in the file, as the native decoder gives them.
+    let options = ImageBitmapOptions::new();
+    options.set_color_space_conversion(ColorSpaceConversion::None);
+    options.set_premultiply_alpha(PremultiplyAlpha::None);
+    let window = wgpu::web_sys::window().context("no window to decode with")?;
+    let decode_error = |e: wasm_bindgen::JsValue| anyhow::anyhow!("decoding {}: {:?}", url, e);
+    let promise = window
+        .create_image_bitmap_with_blob_and_image_bitmap_options(&blob, &options)
+        .map_err(decode_error)?;
+    JsFuture::from(promise)
+        .await
+        .and_then(|bitmap| bitmap.dyn_into())
+        .map_err(decode_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn asset_root_is_found_above_the_executable() {
+        let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let executable = crate_dir.join("target").join("debug").join("app");
+        let elsewhere = crate_dir.join("src");
+
+        let root = find_asset_root(None, Some(elsewhere.clone()), Some(executable));
+        assert_eq!(root, crate_dir);
+        let root = find_asset_root(None, Some(crate_dir.clone()), None);
+        assert_eq!(root, crate_dir);
+        let root = find_asset_root(None, Some(elsewhere.clone()), None);
+        assert_eq!(root, elsewhere);
+    }
+
+    #[test]
+    fn changed_files_are_named_as_they_were_asked_for() {
+        let root = Path::new("/game");
+        let changed = root.join("models").join("cube.obj");
+        assert_eq!(watch::relative_path(root, &changed).as_deref(), Some("models/cube.obj"));
+        assert_eq!(watch::relative_path(root, &root.join("happy-tree.png")).as_deref(), Some("happy-tree.png"));
+        assert_eq!(watch::relative_path(root, Path::new("/elsewhere/cube.obj")), None);
+    }
+
+    #[test]
+    fn configured_asset_root_wins() {
+        let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let configured = PathBuf::from("/somewhere/else");
+        let root = find_asset_root(Some(configured.clone()), Some(crate_dir.clone()), Some(crate_dir.join("app")));
+        assert_eq!(root, configured);
+    }
+}
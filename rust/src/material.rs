@@ -0,0 +1,248 @@
+use crate::camera::CAMERA_WGSL;
+use crate::ibl::ENVIRONMENT_WGSL;
+use crate::texture::Texture;
+use crate::uniform::{UniformBuffer, WgslType};
+use crate::wgsl_layout;
+
+/// Camera, lights and environment declarations along with the BRDF and the
+/// `shade` function that sums up every light, shared by forward and
+/// deferred shading. `lights_wgsl` comes from
+/// [`Lights::wgsl`](crate::light::Lights::wgsl).
+pub fn lighting_wgsl(lights_wgsl: &str) -> String {
+    format!(
+        "{}\n{}\n{}\n{}",
+        CAMERA_WGSL,
+        lights_wgsl,
+        ENVIRONMENT_WGSL,
+        include_str!("lighting.wgsl")
+    )
+}
+
+/// The Cook-Torrance shader every PBR mesh is drawn with: camera at group
+/// 0, lights at group 1, the material at group 2 and the environment at
+/// group 3. `fs_main` shades forward, `fs_gbuffer` fills the deferred
+/// path's G-buffer.
+pub fn pbr_shader(lights_wgsl: &str) -> wgpu::ShaderModuleDescriptor<'static> {
+    wgpu::ShaderModuleDescriptor {
+        label: Some("pbr.wgsl"),
+        source: wgpu::ShaderSource::Wgsl(
+            format!("{}\n{}", lighting_wgsl(lights_wgsl), include_str!("pbr.wgsl")).into(),
+        ),
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialUniform {
+    base_color_factor: [f32; 4],
+    emissive_factor: [f32; 3],
+    metallic_factor: f32,
+    roughness_factor: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+}
+
+wgsl_layout!(MaterialUniform {
+    base_color_factor: WgslType::Vec4,
+    emissive_factor: WgslType::Vec3,
+    metallic_factor: WgslType::F32,
+    roughness_factor: WgslType::F32,
+    normal_scale: WgslType::F32,
+    occlusion_strength: WgslType::F32,
+});
+
+impl MaterialUniform {
+    fn new(desc: &MaterialDescriptor) -> Self {
+        Self {
+            base_color_factor: desc.base_color_factor,
+            emissive_factor: desc.emissive_factor,
+            metallic_factor: desc.metallic_factor,
+            roughness_factor: desc.roughness_factor,
+            normal_scale: desc.normal_scale,
+            occlusion_strength: desc.occlusion_strength,
+        }
+    }
+}
+
+/// Describes a metallic-roughness material the way glTF does: every map is
+/// optional and multiplied by its factor.
+#[derive(Clone, Copy)]
+pub struct MaterialDescriptor<'a> {
+    pub name: &'a str,
+    pub base_color_factor: [f32; 4],
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub emissive_factor: [f32; 3],
+    /// Scales the X and Y of the sampled tangent space normal.
+    pub normal_scale: f32,
+    /// 0 ignores the occlusion map, 1 applies it fully.
+    pub occlusion_strength: f32,
+    /// sRGB.
+    pub base_color_texture: Option<&'a Texture>,
+    /// Linear, roughness in green and metallic in blue.
+    pub metallic_roughness_texture: Option<&'a Texture>,
+    /// Linear, tangent space.
+    pub normal_texture: Option<&'a Texture>,
+    /// Linear, occlusion in red.
+    pub occlusion_texture: Option<&'a Texture>,
+    /// sRGB.
+    pub emissive_texture: Option<&'a Texture>,
+}
+
+impl Default for MaterialDescriptor<'_> {
+    /// A plain white dielectric. glTF defaults metallic and roughness to 1,
+    /// which looks black until there is an environment to reflect.
+    fn default() -> Self {
+        Self {
+            name: "material",
+            base_color_factor: [1.0; 4],
+            metallic_factor: 0.0,
+            roughness_factor: 0.5,
+            emissive_factor: [0.0; 3],
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+        }
+    }
+}
+
+/// The bind group layout shared by every PBR material, with the textures
+/// that stand in for the maps a material leaves out.
+pub struct MaterialLayout {
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    white: Texture,
+    flat_normal: Texture,
+    sampler: wgpu::Sampler,
+}
+
+impl MaterialLayout {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                UniformBuffer::<MaterialUniform>::layout_entry(0, wgpu::ShaderStages::FRAGMENT),
+                texture_entry(1),
+                texture_entry(2),
+                texture_entry(3),
+                texture_entry(4),
+                texture_entry(5),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("material_bind_group_layout"),
+        });
+
+        let white = Texture::from_color(device, queue, [255; 4], wgpu::TextureFormat::Rgba8Unorm, "white");
+        let flat_normal = Texture::from_color(
+            device,
+            queue,
+            [128, 128, 255, 255],
+            wgpu::TextureFormat::Rgba8Unorm,
+            "flat_normal",
+        );
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("material_sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            bind_group_layout,
+            white,
+            flat_normal,
+            sampler,
+        }
+    }
+}
+
+pub struct Material {
+    uniform: UniformBuffer<MaterialUniform>,
+}
+
+impl Material {
+    pub fn new(device: &wgpu::Device, layout: &MaterialLayout, desc: &MaterialDescriptor) -> Self {
+        fn view<'a>(texture: Option<&'a Texture>, fallback: &'a Texture) -> wgpu::BindingResource<'a> {
+            wgpu::BindingResource::TextureView(&texture.unwrap_or(fallback).view)
+        }
+        // White base colour and emissive maps are fine as linear, 1.0 is 1.0
+        // either way.
+        let resources = [
+            (1, view(desc.base_color_texture, &layout.white)),
+            (2, view(desc.metallic_roughness_texture, &layout.white)),
+            (3, view(desc.normal_texture, &layout.flat_normal)),
+            (4, view(desc.occlusion_texture, &layout.white)),
+            (5, view(desc.emissive_texture, &layout.white)),
+            (6, wgpu::BindingResource::Sampler(&layout.sampler)),
+        ];
+        let uniform = UniformBuffer::with_layout(
+            device,
+            &MaterialUniform::new(desc),
+            &layout.bind_group_layout,
+            &resources,
+            desc.name,
+        );
+
+        Self { uniform }
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.uniform.bind_group
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_a_plain_white_dielectric() {
+        let desc = MaterialDescriptor::default();
+        assert!(desc.base_color_texture.is_none() && desc.metallic_roughness_texture.is_none());
+        assert!(desc.normal_texture.is_none() && desc.occlusion_texture.is_none() && desc.emissive_texture.is_none());
+
+        let uniform = MaterialUniform::new(&desc);
+        assert_eq!(uniform.base_color_factor, [1.0; 4]);
+        assert_eq!((uniform.metallic_factor, uniform.roughness_factor), (0.0, 0.5));
+        // The white stand-in for a missing emissive map is multiplied away.
+        assert_eq!(uniform.emissive_factor, [0.0; 3]);
+        assert_eq!((uniform.normal_scale, uniform.occlusion_strength), (1.0, 1.0));
+    }
+
+    #[test]
+    fn factors_are_copied_as_given() {
+        let uniform = MaterialUniform::new(&MaterialDescriptor {
+            base_color_factor: [0.8, 0.3, 0.3, 0.5],
+            metallic_factor: 1.0,
+            roughness_factor: 0.2,
+            emissive_factor: [2.0, 1.0, 0.0],
+            normal_scale: 0.5,
+            occlusion_strength: 0.0,
+            ..Default::default()
+        });
+        // The emissive vec3 shares its 16 bytes with the metallic factor.
+        let floats: &[f32] = bytemuck::cast_slice(std::slice::from_ref(&uniform));
+        assert_eq!(floats, [0.8, 0.3, 0.3, 0.5, 2.0, 1.0, 0.0, 1.0, 0.2, 0.5, 0.0]);
+    }
+}
@@ -0,0 +1,226 @@
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, SquareMatrix, Vector2, Vector3, Zero};
+use wgpu::util::DeviceExt;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    /// Points along +u, `w` is the handedness: the bitangent is
+    /// `cross(normal, tangent.xyz) * w` and points along -v, up in the image.
+    pub tangent: [f32; 4],
+}
+
+impl MeshVertex {
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 4] =
+            wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2, 2 => Float32x3, 3 => Float32x4];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<MeshVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+/// Per-object data, fed to the lit pipelines as a second, per-instance
+/// vertex buffer.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceRaw {
+    model: [[f32; 4]; 4],
+    normal: [[f32; 3]; 3],
+    color: [f32; 4],
+}
+
+impl InstanceRaw {
+    /// An instance placed by `model`, its vertex colours multiplied by
+    /// `color`.
+    pub fn new(model: Matrix4<f32>, color: [f32; 4]) -> Self {
+        // The inverse transpose keeps normals perpendicular under
+        // non-uniform scaling, the renormalize in the shader takes care of
+        // the length.
+        let linear = Matrix3::from_cols(model.x.truncate(), model.y.truncate(), model.z.truncate());
+        let normal = linear.invert().map_or(linear, |inverse| inverse.transpose());
+        Self {
+            model: model.into(),
+            normal: normal.into(),
+            color,
+        }
+    }
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        // Locations start at 5 to leave room for more per-vertex attributes.
+        const ATTRIBUTES: [wgpu::VertexAttribute; 8] = wgpu::vertex_attr_array![
+            5 => Float32x4, 6 => Float32x4, 7 => Float32x4, 8 => Float32x4,
+            9 => Float32x3, 10 => Float32x3, 11 => Float32x3,
+            12 => Float32x4,
+        ];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+pub struct Mesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub num_elements: u32,
+}
+
+impl Mesh {
+    pub fn new(device: &wgpu::Device, name: &str, data: &MeshData) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", name)),
+            contents: bytemuck::cast_slice(&data.vertices),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Index Buffer", name)),
+            contents: bytemuck::cast_slice(&data.indices),
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            num_elements: data.indices.len() as u32,
+        }
+    }
+
+    /// Overwrites the mesh with `data` in its existing buffers, so whatever
+    /// refers to them keeps working. Returns false, leaving the mesh as it
+    /// was, if `data` doesn't fit.
+    pub fn write(&mut self, queue: &wgpu::Queue, data: &MeshData) -> bool {
+        let vertices: &[u8] = bytemuck::cast_slice(&data.vertices);
+        let indices: &[u8] = bytemuck::cast_slice(&data.indices);
+        if vertices.len() as u64 > self.vertex_buffer.size() || indices.len() as u64 > self.index_buffer.size() {
+            return false;
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, vertices);
+        queue.write_buffer(&self.index_buffer, 0, indices);
+        self.num_elements = data.indices.len() as u32;
+        true
+    }
+}
+
+/// Vertices and indices for a [`Mesh`], before they're on the GPU.
+pub struct MeshData {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    /// Every object in a Wavefront OBJ file, merged into one mesh. Material
+    /// libraries are ignored, materials come from wherever the mesh is
+    /// used. Objects without normals get smooth ones.
+    pub fn from_obj(bytes: &[u8]) -> anyhow::Result<Self> {
+        let (models, _) = tobj::load_obj_buf(&mut std::io::Cursor::new(bytes), &tobj::GPU_LOAD_OPTIONS, |_| {
+            Ok(Default::default())
+        })?;
+
+        let mut data = Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        };
+        for model in models {
+            let mesh = model.mesh;
+            let base = data.vertices.len() as u32;
+            let first_index = data.indices.len();
+            for i in 0..mesh.positions.len() / 3 {
+                let tex_coords = match mesh.texcoords.get(i * 2..i * 2 + 2) {
+                    // OBJ has v going up the image, ours goes down it.
+                    Some(uv) => [uv[0], 1.0 - uv[1]],
+                    None => [0.0, 0.0],
+                };
+                let normal = match mesh.normals.get(i * 3..i * 3 + 3) {
+                    Some(n) => [n[0], n[1], n[2]],
+                    None => [0.0; 3],
+                };
+                data.vertices.push(MeshVertex {
+                    position: [mesh.positions[i * 3], mesh.positions[i * 3 + 1], mesh.positions[i * 3 + 2]],
+                    tex_coords,
+                    normal,
+                    tangent: [0.0; 4],
+                });
+            }
+            data.indices.extend(mesh.indices.iter().map(|i| base + i));
+            if mesh.normals.is_empty() {
+                smooth_normals(&mut data.vertices[base as usize..], &data.indices[first_index..], base);
+            }
+        }
+        if data.indices.is_empty() {
+            anyhow::bail!("no triangles");
+        }
+        compute_tangents(&mut data.vertices, &data.indices);
+        Ok(data)
+    }
+}
+
+/// Gives each of `vertices` the average normal of the triangles around it,
+/// weighted by their area. `indices` count from `base`.
+fn smooth_normals(vertices: &mut [MeshVertex], indices: &[u32], base: u32) {
+    let mut normals = vec![Vector3::zero(); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| (i - base) as usize);
+        let p0 = Vector3::from(vertices[a].position);
+        // Twice the area long, which is the weighting.
+        let normal = (Vector3::from(vertices[b].position) - p0).cross(Vector3::from(vertices[c].position) - p0);
+        for i in [a, b, c] {
+            normals[i] += normal;
+        }
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        if normal.magnitude2() > 0.0 {
+            vertex.normal = normal.normalize().into();
+        }
+    }
+}
+
+/// Fills in every vertex's tangent from the UV layout of the triangles
+/// around it. Vertices with degenerate UVs get a tangent perpendicular to
+/// their normal so normal mapping still has a frame to work in.
+pub fn compute_tangents(vertices: &mut [MeshVertex], indices: &[u32]) {
+    let mut tangents = vec![Vector3::zero(); vertices.len()];
+    let mut bitangents = vec![Vector3::zero(); vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| i as usize);
+        let p0 = Vector3::from(vertices[a].position);
+        let e1 = Vector3::from(vertices[b].position) - p0;
+        let e2 = Vector3::from(vertices[c].position) - p0;
+        let uv0 = Vector2::from(vertices[a].tex_coords);
+        let duv1 = Vector2::from(vertices[b].tex_coords) - uv0;
+        let duv2 = Vector2::from(vertices[c].tex_coords) - uv0;
+
+        let det = duv1.x * duv2.y - duv2.x * duv1.y;
+        if det.abs() < f32::EPSILON {
+            continue;
+        }
+        let r = 1.0 / det;
+        // Weighted by UV area, which is as good as any other weighting here.
+        let tangent = (e1 * duv2.y - e2 * duv1.y) * r;
+        let bitangent = (e2 * duv1.x - e1 * duv2.x) * r;
+        for i in [a, b, c] {
+            tangents[i] += tangent;
+            bitangents[i] += bitangent;
+        }
+    }
+
+    for (vertex, (tangent, bitangent)) in vertices.iter_mut().zip(tangents.into_iter().zip(bitangents)) {
+        let normal = Vector3::from(vertex.normal);
+        // Gram-Schmidt against the normal.
+        let mut t = tangent - normal * normal.dot(tangent);
+        if t.magnitude2() < 1e-12 {
+            let axis = if normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+            t = axis - normal * normal.dot(axis);
+        }
+        let t = t.normalize();
+        // `bitangent` follows +v, the stored frame's bitangent follows -v.
+        let w = if normal.cross(t).dot(bitangent) > 0.0 { -1.0 } else { 1.0 };
+        vertex.tangent = [t.x, t.y, t.z, w];
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+/// Frames [`FrameStats`] averages over.
+const FRAME_HISTORY: usize = 120;
+
+/// Frame times over the last [`FRAME_HISTORY`] frames.
+pub struct FrameStats {
+    frame_times: VecDeque<f32>,
+    last_time: Option<f64>,
+}
+
+impl FrameStats {
+    fn new() -> Self {
+        Self {
+            frame_times: VecDeque::with_capacity(FRAME_HISTORY),
+            last_time: None,
+        }
+    }
+
+    /// Records a frame starting at `time` seconds.
+    fn record(&mut self, time: f64) {
+        if let Some(last_time) = self.last_time {
+            if self.frame_times.len() == FRAME_HISTORY {
+                self.frame_times.pop_front();
+            }
+            self.frame_times.push_back((time - last_time) as f32);
+        }
+        self.last_time = Some(time);
+    }
+
+    /// Mean frame time in seconds, 0 before the second frame.
+    pub fn average(&self) -> f32 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+        self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32
+    }
+
+    /// Longest frame time in seconds.
+    pub fn worst(&self) -> f32 {
+        self.frame_times.iter().copied().fold(0.0, f32::max)
+    }
+}
+
+/// An egui UI drawn over everything else. Window events go through
+/// [`Overlay::handle_event`] first, and only reach the rest of the app when
+/// egui doesn't want them, except key releases, which always do.
+///
+/// Each frame runs [`Overlay::take_input`], then the UI on
+/// [`Overlay::context`], then [`Overlay::finish`] with its output, and
+/// finally [`Overlay::draw`].
+pub struct Overlay {
+    /// Off until toggled with I, so the scene shows unobstructed at first.
+    pub visible: bool,
+    pub stats: FrameStats,
+    context: egui::Context,
+    input: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    primitives: Vec<egui::ClippedPrimitive>,
+    textures: egui::TexturesDelta,
+    pixels_per_point: f32,
+}
+
+impl Overlay {
+    /// The overlay is drawn into `output_format` targets the size of
+    /// `window`.
+    pub fn new(device: &wgpu::Device, window: &Window, output_format: wgpu::TextureFormat) -> Self {
+        let context = egui::Context::default();
+        let input = egui_winit::State::new(
+            context.clone(),
+            egui::ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            window.theme(),
+            Some(device.limits().max_texture_dimension_2d as usize),
+        );
+        let renderer = egui_wgpu::Renderer::new(device, output_format, None, 1, false);
+        Self {
+            visible: false,
+            stats: FrameStats::new(),
+            context,
+            input,
+            renderer,
+            primitives: Vec::new(),
+            textures: egui::TexturesDelta::default(),
+            pixels_per_point: window.scale_factor() as f32,
+        }
+    }
+
+    /// Passes `event` to egui, returning whether egui used it up, like a
+    /// click on one of its windows or typing into a text field.
+    pub fn handle_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        self.input.on_window_event(window, event).consumed
+    }
+
+    /// Makes `view` available to [`egui::Image`]s, until
+    /// [`Overlay::update_texture`] points it elsewhere.
+    pub fn register_texture(&mut self, device: &wgpu::Device, view: &wgpu::TextureView) -> egui::TextureId {
+        self.renderer.register_native_texture(device, view, wgpu::FilterMode::Linear)
+    }
+
+    /// Points `id` from [`Overlay::register_texture`] at `view` instead.
+    pub fn update_texture(&mut self, device: &wgpu::Device, id: egui::TextureId, view: &wgpu::TextureView) {
+        self.renderer
+            .update_egui_texture_from_wgpu_texture(device, view, wgpu::FilterMode::Linear, id);
+    }
+
+    pub fn context(&self) -> &egui::Context {
+        &self.context
+    }
+
+    /// The input gathered since the last frame, to run the UI with.
+    pub fn take_input(&mut self, window: &Window) -> egui::RawInput {
+        let input = self.input.take_egui_input(window);
+        if let Some(time) = input.time {
+            self.stats.record(time);
+        }
+        input
+    }
+
+    /// Hands the output of running the UI back to the window and readies
+    /// it for [`Overlay::draw`].
+    pub fn finish(&mut self, window: &Window, output: egui::FullOutput) {
+        self.input.handle_platform_output(window, output.platform_output);
+        self.primitives = self.context.tessellate(output.shapes, output.pixels_per_point);
+        self.textures.append(output.textures_delta);
+        self.pixels_per_point = output.pixels_per_point;
+    }
+
+    /// Draws the UI over `output`, which is `width` by `height` pixels.
+    /// Returns command buffers egui needs submitted before `encoder`.
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        width: u32,
+        height: u32,
+    ) -> Vec<wgpu::CommandBuffer> {
+        let textures = std::mem::take(&mut self.textures);
+        for (id, delta) in &textures.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+
+        let screen = egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [width, height],
+            pixels_per_point: self.pixels_per_point,
+        };
+        let command_buffers = self.renderer.update_buffers(device, queue, encoder, &self.primitives, &screen);
+        if !self.primitives.is_empty() {
+            let mut pass = encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Overlay Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: output,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                })
+                .forget_lifetime();
+            self.renderer.render(&mut pass, &self.primitives, &screen);
+        }
+
+        for id in &textures.free {
+            self.renderer.free_texture(id);
+        }
+        command_buffers
+    }
+}
@@ -0,0 +1,141 @@
+struct Material {
+    base_color_factor: vec4<f32>,
+    emissive_factor: vec3<f32>,
+    metallic_factor: f32,
+    roughness_factor: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+}
+@group(2) @binding(0)
+var<uniform> material: Material;
+@group(2) @binding(1)
+var t_base_color: texture_2d<f32>;
+@group(2) @binding(2)
+var t_metallic_roughness: texture_2d<f32>;
+@group(2) @binding(3)
+var t_normal: texture_2d<f32>;
+@group(2) @binding(4)
+var t_occlusion: texture_2d<f32>;
+@group(2) @binding(5)
+var t_emissive: texture_2d<f32>;
+@group(2) @binding(6)
+var s_material: sampler;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec4<f32>,
+}
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+    @location(9) normal_matrix_0: vec3<f32>,
+    @location(10) normal_matrix_1: vec3<f32>,
+    @location(11) normal_matrix_2: vec3<f32>,
+    @location(12) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) world_tangent: vec4<f32>,
+    @location(3) tex_coords: vec2<f32>,
+    @location(4) color: vec4<f32>,
+    @location(5) view_depth: f32,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+    // Tangents lie in the surface, so they follow the model matrix itself.
+    let tangent = mat3x3<f32>(model_matrix[0].xyz, model_matrix[1].xyz, model_matrix[2].xyz) * model.tangent.xyz;
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * world_position;
+    out.world_position = world_position.xyz;
+    out.world_normal = normal_matrix * model.normal;
+    out.world_tangent = vec4<f32>(tangent, model.tangent.w);
+    out.tex_coords = model.tex_coords;
+    out.color = instance.color;
+    out.view_depth = -(camera.view * world_position).z;
+    return out;
+}
+
+struct SampledMaterial {
+    surface: Surface,
+    geometric_normal: vec3<f32>,
+    emissive: vec3<f32>,
+    alpha: f32,
+}
+
+fn sample_material(in: VertexOutput) -> SampledMaterial {
+    let base_color = textureSample(t_base_color, s_material, in.tex_coords) * material.base_color_factor * in.color;
+    // glTF packs roughness in green and metallic in blue.
+    let metallic_roughness = textureSample(t_metallic_roughness, s_material, in.tex_coords);
+    let tangent_normal = textureSample(t_normal, s_material, in.tex_coords).xyz * 2.0 - vec3<f32>(1.0);
+    let occlusion = textureSample(t_occlusion, s_material, in.tex_coords).r;
+
+    let geometric_normal = normalize(in.world_normal);
+    let t = normalize(in.world_tangent.xyz - geometric_normal * dot(geometric_normal, in.world_tangent.xyz));
+    let b = cross(geometric_normal, t) * in.world_tangent.w;
+    let scaled = tangent_normal * vec3<f32>(material.normal_scale, material.normal_scale, 1.0);
+
+    var out: SampledMaterial;
+    out.surface.albedo = base_color.rgb;
+    out.surface.metallic = saturate(material.metallic_factor * metallic_roughness.b);
+    // Very low roughness turns highlights from analytic lights into aliasing.
+    out.surface.roughness = clamp(material.roughness_factor * metallic_roughness.g, 0.045, 1.0);
+    out.surface.normal = normalize(mat3x3<f32>(t, b, geometric_normal) * scaled);
+    out.surface.occlusion = mix(1.0, occlusion, material.occlusion_strength);
+    out.geometric_normal = geometric_normal;
+    out.emissive = textureSample(t_emissive, s_material, in.tex_coords).rgb * material.emissive_factor;
+    out.alpha = base_color.a;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let sampled = sample_material(in);
+    let color = sampled.emissive
+        + shade(sampled.surface, in.world_position, sampled.geometric_normal, in.view_depth, in.clip_position.xy);
+    return vec4<f32>(color, sampled.alpha);
+}
+
+// The deferred path's G-buffer. Emissive goes straight into the scene
+// target, which the lighting pass then adds onto.
+struct GBufferOutput {
+    @location(0) albedo_occlusion: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+    @location(2) metallic_roughness: vec4<f32>,
+    @location(3) emissive: vec4<f32>,
+}
+
+@fragment
+fn fs_gbuffer(in: VertexOutput) -> GBufferOutput {
+    let sampled = sample_material(in);
+    var out: GBufferOutput;
+    out.albedo_occlusion = vec4<f32>(sampled.surface.albedo, sampled.surface.occlusion);
+    out.normal = vec4<f32>(sampled.surface.normal * 0.5 + vec3<f32>(0.5), 1.0);
+    out.metallic_roughness = vec4<f32>(sampled.surface.metallic, sampled.surface.roughness, 0.0, 1.0);
+    out.emissive = vec4<f32>(sampled.emissive, 1.0);
+    return out;
+}
@@ -0,0 +1,34 @@
+// Must match the constants in cluster.rs.
+const CLUSTER_X: u32 = 16u;
+const CLUSTER_Y: u32 = 9u;
+const CLUSTER_Z: u32 = 24u;
+const MAX_LIGHTS_PER_CLUSTER: u32 = 64u;
+const MAX_UNIFORM_POINT_LIGHTS: u32 = 128u;
+
+struct PointLight {
+    position_range: vec4<f32>,
+    color: vec4<f32>,
+}
+
+struct Clusters {
+    inv_proj: mat4x4<f32>,
+    view: mat4x4<f32>,
+    screen_size: vec2<f32>,
+    z_near: f32,
+    z_far: f32,
+    light_count: u32,
+}
+
+// The depth slices grow exponentially, so that clusters stay roughly as
+// deep as they are wide all the way out.
+fn cluster_slice_depth(clusters: Clusters, slice: u32) -> f32 {
+    return clusters.z_near * pow(clusters.z_far / clusters.z_near, f32(slice) / f32(CLUSTER_Z));
+}
+
+// How much of `light` reaches `world_position`, the same falloff spot
+// lights use.
+fn point_attenuation(light: PointLight, world_position: vec3<f32>) -> f32 {
+    let distance = length(light.position_range.xyz - world_position);
+    let falloff = saturate(1.0 - pow(distance / light.position_range.w, 4.0));
+    return falloff * falloff / (distance * distance + 1.0);
+}
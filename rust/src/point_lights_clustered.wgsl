@@ -0,0 +1,24 @@
+@group(1) @binding(3)
+var<uniform> clusters: Clusters;
+@group(1) @binding(4)
+var<storage, read> point_lights: array<PointLight>;
+// Per cluster, a count followed by MAX_LIGHTS_PER_CLUSTER light indices.
+@group(1) @binding(5)
+var<storage, read> cluster_lights: array<u32>;
+
+fn point_light_cluster(frag_coord: vec2<f32>, view_depth: f32) -> u32 {
+    let tile = vec2<u32>(frag_coord / clusters.screen_size * vec2<f32>(f32(CLUSTER_X), f32(CLUSTER_Y)));
+    let slice = log(max(view_depth, clusters.z_near) / clusters.z_near) / log(clusters.z_far / clusters.z_near);
+    let z = min(u32(slice * f32(CLUSTER_Z)), CLUSTER_Z - 1u);
+    let x = min(tile.x, CLUSTER_X - 1u);
+    let y = min(tile.y, CLUSTER_Y - 1u);
+    return x + y * CLUSTER_X + z * CLUSTER_X * CLUSTER_Y;
+}
+
+fn point_light_count(cluster: u32) -> u32 {
+    return cluster_lights[cluster * (MAX_LIGHTS_PER_CLUSTER + 1u)];
+}
+
+fn point_light(cluster: u32, i: u32) -> PointLight {
+    return point_lights[cluster_lights[cluster * (MAX_LIGHTS_PER_CLUSTER + 1u) + 1u + i]];
+}
@@ -0,0 +1,20 @@
+// WebGL2 has neither storage buffers nor compute shaders, so every
+// fragment walks the lights picked on the CPU instead.
+struct PointLights {
+    lights: array<PointLight, MAX_UNIFORM_POINT_LIGHTS>,
+    count: u32,
+}
+@group(1) @binding(3)
+var<uniform> point_lights: PointLights;
+
+fn point_light_cluster(frag_coord: vec2<f32>, view_depth: f32) -> u32 {
+    return 0u;
+}
+
+fn point_light_count(cluster: u32) -> u32 {
+    return point_lights.count;
+}
+
+fn point_light(cluster: u32, i: u32) -> PointLight {
+    return point_lights.lights[i];
+}
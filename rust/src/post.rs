@@ -0,0 +1,365 @@
+use crate::texture::Texture;
+use crate::uniform::{UniformBuffer, WgslType};
+use crate::wgsl_layout;
+
+const FULLSCREEN_WGSL: &str = include_str!("fullscreen.wgsl");
+/// Declares `t_input`/`s_input`, the texture a fullscreen pass reads.
+pub const POST_INPUT_WGSL: &str = include_str!("post_input.wgsl");
+const POST_WGSL: &str = include_str!("post.wgsl");
+
+/// Adds the pass's colour onto what the target already holds, leaving its
+/// alpha alone.
+pub const ADDITIVE: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Zero,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostUniform {
+    texel_size: [f32; 2],
+    _padding: [f32; 2],
+    params: [f32; 4],
+}
+
+wgsl_layout!(PostUniform {
+    texel_size: WgslType::Vec2,
+    params: WgslType::Vec4,
+});
+
+/// The effects that ship with the renderer. Anything else can be added to
+/// the chain with [`PostProcessChain::add_effect`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BuiltinEffect {
+    Fxaa,
+    ChromaticAberration,
+    Grayscale,
+    Vignette,
+}
+
+impl BuiltinEffect {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuiltinEffect::Fxaa => "FXAA",
+            BuiltinEffect::ChromaticAberration => "Chromatic aberration",
+            BuiltinEffect::Grayscale => "Grayscale",
+            BuiltinEffect::Vignette => "Vignette",
+        }
+    }
+
+    fn source(&self) -> &'static str {
+        match self {
+            BuiltinEffect::Fxaa => include_str!("fxaa.wgsl"),
+            BuiltinEffect::ChromaticAberration => include_str!("chromatic_aberration.wgsl"),
+            BuiltinEffect::Grayscale => include_str!("grayscale.wgsl"),
+            BuiltinEffect::Vignette => include_str!("vignette.wgsl"),
+        }
+    }
+
+    /// See the top of each shader for what the parameters mean.
+    pub fn default_params(&self) -> [f32; 4] {
+        match self {
+            BuiltinEffect::Fxaa => [8.0, 1.0 / 8.0, 1.0 / 128.0, 0.0],
+            BuiltinEffect::ChromaticAberration => [4.0, 0.0, 0.0, 0.0],
+            BuiltinEffect::Grayscale => [1.0, 0.0, 0.0, 0.0],
+            BuiltinEffect::Vignette => [0.8, 0.75, 0.45, 0.0],
+        }
+    }
+}
+
+/// One fullscreen pass in the chain. The fragment shader reads the
+/// previous pass from `t_input`/`s_input` and gets its parameters in
+/// `post.params`.
+pub struct PostEffect {
+    pub name: String,
+    pub enabled: bool,
+    pub params: [f32; 4],
+    pipeline: wgpu::RenderPipeline,
+    uniform: UniformBuffer<PostUniform>,
+}
+
+/// Renders the scene into an offscreen target and runs it through an
+/// ordered list of fullscreen effects, ping-ponging between two targets.
+/// The result is handed to a final pass, tonemapping, that writes the
+/// surface.
+pub struct PostProcessChain {
+    pub effects: Vec<PostEffect>,
+    format: wgpu::TextureFormat,
+    texel_size: [f32; 2],
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    scene: Texture,
+    scene_bind_group: wgpu::BindGroup,
+    targets: [Texture; 2],
+    target_bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl PostProcessChain {
+    /// `format` is used for the scene target and the intermediate targets.
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+                label: Some("post_texture_bind_group_layout"),
+            });
+
+        let (scene, scene_bind_group) =
+            create_target(device, &texture_bind_group_layout, width, height, format, "post_scene");
+        let (target_a, bind_group_a) =
+            create_target(device, &texture_bind_group_layout, width, height, format, "post_target_a");
+        let (target_b, bind_group_b) =
+            create_target(device, &texture_bind_group_layout, width, height, format, "post_target_b");
+
+        Self {
+            effects: Vec::new(),
+            format,
+            texel_size: texel_size(width, height),
+            texture_bind_group_layout,
+            scene,
+            scene_bind_group,
+            targets: [target_a, target_b],
+            target_bind_groups: [bind_group_a, bind_group_b],
+        }
+    }
+
+    /// The layout of the bind group [`PostProcessChain::run`] returns.
+    pub fn texture_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.texture_bind_group_layout
+    }
+
+    /// The scene target as a fullscreen pass input.
+    pub fn scene_bind_group(&self) -> &wgpu::BindGroup {
+        &self.scene_bind_group
+    }
+
+    /// The view the scene should be rendered (or MSAA-resolved) into.
+    pub fn scene_view(&self) -> &wgpu::TextureView {
+        &self.scene.view
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let layout = &self.texture_bind_group_layout;
+        (self.scene, self.scene_bind_group) =
+            create_target(device, layout, width, height, self.format, "post_scene");
+        let (target_a, bind_group_a) =
+            create_target(device, layout, width, height, self.format, "post_target_a");
+        let (target_b, bind_group_b) =
+            create_target(device, layout, width, height, self.format, "post_target_b");
+        self.targets = [target_a, target_b];
+        self.target_bind_groups = [bind_group_a, bind_group_b];
+        self.texel_size = texel_size(width, height);
+    }
+
+    pub fn add_builtin(&mut self, device: &wgpu::Device, effect: BuiltinEffect, enabled: bool) {
+        self.add_effect(device, effect.name(), effect.source(), effect.default_params());
+        if let Some(added) = self.effects.last_mut() {
+            added.enabled = enabled;
+        }
+    }
+
+    /// Appends an effect to the end of the chain. `source` only needs a
+    /// `fs_main` taking a `FullscreenOutput`, the vertex stage and the
+    /// input/parameter bindings are prepended for it.
+    pub fn add_effect(&mut self, device: &wgpu::Device, name: &str, source: &str, params: [f32; 4]) {
+        let uniform = UniformBuffer::new(
+            device,
+            &PostUniform {
+                texel_size: self.texel_size,
+                _padding: [0.0; 2],
+                params,
+            },
+            wgpu::ShaderStages::FRAGMENT,
+            name,
+        );
+        let pipeline = create_fullscreen_pipeline(
+            device,
+            &[&self.texture_bind_group_layout, &uniform.bind_group_layout],
+            self.format,
+            wgpu::BlendState::REPLACE,
+            &format!("{}\n{}\n{}", POST_INPUT_WGSL, POST_WGSL, source),
+            "fs_main",
+            name,
+        );
+
+        self.effects.push(PostEffect {
+            name: name.to_string(),
+            enabled: true,
+            params,
+            pipeline,
+            uniform,
+        });
+    }
+
+    pub fn toggle(&mut self, index: usize) {
+        if let Some(effect) = self.effects.get_mut(index) {
+            effect.enabled = !effect.enabled;
+            log::info!("{}: {}", effect.name, if effect.enabled { "on" } else { "off" });
+        }
+    }
+
+    /// Runs every enabled effect in order on the scene target and returns
+    /// the bind group of whichever target holds the result.
+    pub fn run(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder) -> &wgpu::BindGroup {
+        let mut input = &self.scene_bind_group;
+        let mut next = 0;
+
+        for effect in self.effects.iter().filter(|effect| effect.enabled) {
+            effect.uniform.update(queue, &PostUniform {
+                texel_size: self.texel_size,
+                _padding: [0.0; 2],
+                params: effect.params,
+            });
+
+            let mut pass = begin_fullscreen_pass(
+                encoder,
+                &self.targets[next].view,
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                &effect.name,
+            );
+            pass.set_pipeline(&effect.pipeline);
+            pass.set_bind_group(0, input, &[]);
+            pass.set_bind_group(1, &effect.uniform.bind_group, &[]);
+            pass.draw(0..3, 0..1);
+            drop(pass);
+
+            input = &self.target_bind_groups[next];
+            next = 1 - next;
+        }
+
+        input
+    }
+}
+
+fn texel_size(width: u32, height: u32) -> [f32; 2] {
+    [1.0 / width.max(1) as f32, 1.0 / height.max(1) as f32]
+}
+
+pub fn create_target(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    label: &str,
+) -> (Texture, wgpu::BindGroup) {
+    let texture = Texture::create_render_target(device, width, height, format, label);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            },
+        ],
+        label: Some(label),
+    });
+    (texture, bind_group)
+}
+
+pub fn begin_fullscreen_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    target: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+    label: &str,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    })
+}
+
+/// A pipeline drawing the fullscreen triangle from `fullscreen.wgsl` with
+/// the fragment stage `entry_point` from `fragment_source`.
+pub fn create_fullscreen_pipeline(
+    device: &wgpu::Device,
+    bind_group_layouts: &[&wgpu::BindGroupLayout],
+    format: wgpu::TextureFormat,
+    blend: wgpu::BlendState,
+    fragment_source: &str,
+    entry_point: &str,
+    label: &str,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(format!("{}\n{}", FULLSCREEN_WGSL, fragment_source).into()),
+    });
+
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some(entry_point),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            cull_mode: None,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
@@ -0,0 +1,7 @@
+struct PostUniform {
+    texel_size: vec2<f32>,
+    params: vec4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> post: PostUniform;
@@ -0,0 +1,508 @@
+//! Indexed meshes of basic shapes, centred on the origin, with normals,
+//! UVs and tangents. Triangles wind counter-clockwise seen from outside, and
+//! UVs have V going down the image like everywhere else.
+//!
+//! Counts too low to make the shape are raised to the least that does: 3
+//! sectors or segments around, 2 stacks of a sphere, 1 ring or subdivision.
+
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use cgmath::{InnerSpace, Vector3};
+
+use crate::mesh::{MeshData, MeshVertex, compute_tangents};
+
+/// A `width` by `height` rectangle in the XY plane facing +Z.
+pub fn quad(width: f32, height: f32) -> MeshData {
+    let mut data = empty();
+    grid(
+        &mut data,
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::new(width * 0.5, 0.0, 0.0),
+        Vector3::new(0.0, height * 0.5, 0.0),
+        1,
+    );
+    finish(data)
+}
+
+/// A `size` by `size` square in the XZ plane facing +Y, split into
+/// `subdivisions` squares along each side.
+pub fn plane(size: f32, subdivisions: u32) -> MeshData {
+    let mut data = empty();
+    let s = size * 0.5;
+    grid(
+        &mut data,
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::new(s, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, -s),
+        subdivisions,
+    );
+    finish(data)
+}
+
+/// An axis aligned cube `size` across, each face split into
+/// `subdivisions` squares along each side. Faces have their own vertices so
+/// the normals stay sharp.
+pub fn cube(size: f32, subdivisions: u32) -> MeshData {
+    let mut data = empty();
+    // (normal, up in the face's UVs)
+    let faces = [
+        (Vector3::unit_x(), Vector3::unit_y()),
+        (-Vector3::unit_x(), Vector3::unit_y()),
+        (Vector3::unit_y(), Vector3::unit_z()),
+        (-Vector3::unit_y(), Vector3::unit_z()),
+        (Vector3::unit_z(), Vector3::unit_y()),
+        (-Vector3::unit_z(), Vector3::unit_y()),
+    ];
+    let s = size * 0.5;
+    for (normal, up) in faces {
+        // u x v = n keeps every face counter-clockwise seen from outside.
+        grid(&mut data, normal * s, up.cross(normal) * s, up * s, subdivisions);
+    }
+    finish(data)
+}
+
+/// A sphere split into `sectors` around Y and `stacks` from pole to pole.
+pub fn uv_sphere(radius: f32, sectors: u32, stacks: u32) -> MeshData {
+    let stacks = stacks.max(2);
+    let profile = (0..=stacks)
+        .map(|i| {
+            let v = i as f32 / stacks as f32;
+            let (sin, cos) = (v * PI).sin_cos();
+            ProfilePoint::new([sin * radius, cos * radius], [sin, cos], v)
+        })
+        .collect();
+    lathe(&[profile], sectors)
+}
+
+/// A sphere made by splitting each face of an icosahedron into four
+/// `subdivisions` times, for triangles of much the same size all over.
+///
+/// UVs are wrapped around Y like [`uv_sphere`]'s. Vertices on the seam are
+/// split, and the poles get one for each triangle.
+pub fn icosphere(radius: f32, subdivisions: u32) -> MeshData {
+    let t = (1.0 + 5f32.sqrt()) * 0.5;
+    let mut positions: Vec<Vector3<f32>> = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .into_iter()
+    .map(|p| Vector3::from(p).normalize())
+    .collect();
+    #[rustfmt::skip]
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Edges shared by two triangles get one midpoint between them.
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                positions.push((positions[a as usize] + positions[b as usize]).normalize());
+                positions.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .into_iter()
+            .flat_map(|[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    // Vertices are made as triangles use them, one for each U a position
+    // ends up with. Triangles across the seam take U past 1 on its low side
+    // rather than going back over the whole texture, and poles, which have
+    // no U of their own, take the middle of the other two corners'.
+    let on_pole = |n: Vector3<f32>| n.x.abs() < 1e-6 && n.z.abs() < 1e-6;
+    let mut data = empty();
+    let mut made = HashMap::new();
+    for triangle in triangles {
+        let corners = triangle.map(|i| positions[i as usize]);
+        let mut us = corners.map(|n| n.x.atan2(n.z) / TAU + 0.5);
+        let around = || (0..3).filter(|&k| !on_pole(corners[k]));
+        let low = around().map(|k| us[k]).fold(f32::MAX, f32::min);
+        let high = around().map(|k| us[k]).fold(f32::MIN, f32::max);
+        if high - low > 0.5 {
+            for k in around() {
+                if us[k] < 0.5 {
+                    us[k] += 1.0;
+                }
+            }
+        }
+        for k in (0..3).filter(|&k| on_pole(corners[k])) {
+            us[k] = around().map(|k| us[k]).sum::<f32>() / 2.0;
+        }
+        for (k, i) in triangle.into_iter().enumerate() {
+            let index = *made.entry((i, us[k].to_bits())).or_insert_with(|| {
+                let n = corners[k];
+                data.vertices.push(MeshVertex {
+                    position: (n * radius).into(),
+                    tex_coords: [us[k], n.y.clamp(-1.0, 1.0).acos() / PI],
+                    normal: n.into(),
+                    tangent: [0.0; 4],
+                });
+                data.vertices.len() as u32 - 1
+            });
+            data.indices.push(index);
+        }
+    }
+    finish(data)
+}
+
+/// A capped cylinder `height` long along Y, split into `sectors` around it.
+pub fn cylinder(radius: f32, height: f32, sectors: u32) -> MeshData {
+    let h = height * 0.5;
+    lathe(
+        &[
+            vec![
+                ProfilePoint::new([0.0, h], [0.0, 1.0], 0.0),
+                ProfilePoint::new([radius, h], [0.0, 1.0], 1.0),
+            ],
+            vec![
+                ProfilePoint::new([radius, h], [1.0, 0.0], 0.0),
+                ProfilePoint::new([radius, -h], [1.0, 0.0], 1.0),
+            ],
+            vec![
+                ProfilePoint::new([radius, -h], [0.0, -1.0], 0.0),
+                ProfilePoint::new([0.0, -h], [0.0, -1.0], 1.0),
+            ],
+        ],
+        sectors,
+    )
+}
+
+/// A cone `height` tall along Y with its point up and a cap on its base,
+/// split into `sectors` around it.
+pub fn cone(radius: f32, height: f32, sectors: u32) -> MeshData {
+    let h = height * 0.5;
+    // Perpendicular to the slope.
+    let slope = Vector3::new(height, radius, 0.0).normalize();
+    lathe(
+        &[
+            vec![
+                ProfilePoint::new([0.0, h], [slope.x, slope.y], 0.0),
+                ProfilePoint::new([radius, -h], [slope.x, slope.y], 1.0),
+            ],
+            vec![
+                ProfilePoint::new([radius, -h], [0.0, -1.0], 0.0),
+                ProfilePoint::new([0.0, -h], [0.0, -1.0], 1.0),
+            ],
+        ],
+        sectors,
+    )
+}
+
+/// A ring around Y `major_radius` from the centre to the middle of its
+/// tube, which is `minor_radius` thick. Split into `major_segments` around
+/// the ring and `minor_segments` around the tube.
+pub fn torus(major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32) -> MeshData {
+    let minor_segments = minor_segments.max(3);
+    // Starting at the top of the tube and going round its outside first.
+    let profile = (0..=minor_segments)
+        .map(|i| {
+            let v = i as f32 / minor_segments as f32;
+            let (sin, cos) = (v * TAU).sin_cos();
+            ProfilePoint::new([major_radius + sin * minor_radius, cos * minor_radius], [sin, cos], v)
+        })
+        .collect();
+    lathe(&[profile], major_segments)
+}
+
+/// A cylinder `height` long along Y with a hemisphere on each end, split
+/// into `sectors` around it and `rings` from each pole to the cylinder.
+pub fn capsule(radius: f32, height: f32, sectors: u32, rings: u32) -> MeshData {
+    let rings = rings.max(1);
+    let h = height * 0.5;
+    // V goes evenly along the outline.
+    let length = PI * radius + height;
+    let arc = |i: u32| i as f32 / rings as f32 * FRAC_PI_2;
+    let top = (0..=rings).map(|i| {
+        let (sin, cos) = arc(i).sin_cos();
+        ProfilePoint::new([sin * radius, h + cos * radius], [sin, cos], arc(i) * radius / length)
+    });
+    let bottom = (0..=rings).map(|i| {
+        let (sin, cos) = arc(i).sin_cos();
+        ProfilePoint::new([cos * radius, -h - sin * radius], [cos, -sin], (arc(i) * radius + FRAC_PI_2 * radius + height) / length)
+    });
+    lathe(&[top.chain(bottom).collect()], sectors)
+}
+
+fn empty() -> MeshData {
+    MeshData {
+        vertices: Vec::new(),
+        indices: Vec::new(),
+    }
+}
+
+fn finish(mut data: MeshData) -> MeshData {
+    compute_tangents(&mut data.vertices, &data.indices);
+    data
+}
+
+/// Adds a square split into `subdivisions` along each side, spanning
+/// `centre ± u ± v` and facing `u x v`. U runs along `u` and V against `v`.
+fn grid(data: &mut MeshData, centre: Vector3<f32>, u: Vector3<f32>, v: Vector3<f32>, subdivisions: u32) {
+    let n = subdivisions.max(1);
+    let normal = u.cross(v).normalize();
+    let base = data.vertices.len() as u32;
+    for j in 0..=n {
+        for i in 0..=n {
+            let (s, t) = (i as f32 / n as f32, j as f32 / n as f32);
+            data.vertices.push(MeshVertex {
+                position: (centre + u * (s * 2.0 - 1.0) + v * (t * 2.0 - 1.0)).into(),
+                tex_coords: [s, 1.0 - t],
+                normal: normal.into(),
+                tangent: [0.0; 4],
+            });
+        }
+    }
+    let index = |i: u32, j: u32| base + j * (n + 1) + i;
+    for j in 0..n {
+        for i in 0..n {
+            data.indices.extend_from_slice(&[
+                index(i, j),
+                index(i + 1, j),
+                index(i + 1, j + 1),
+                index(i, j),
+                index(i + 1, j + 1),
+                index(i, j + 1),
+            ]);
+        }
+    }
+}
+
+/// A point on the outline of a shape turned around Y, in the XY plane.
+struct ProfilePoint {
+    /// Distance from Y and height.
+    position: [f32; 2],
+    /// The same way as `position`.
+    normal: [f32; 2],
+    v: f32,
+}
+
+impl ProfilePoint {
+    fn new(position: [f32; 2], normal: [f32; 2], v: f32) -> Self {
+        Self { position, normal, v }
+    }
+}
+
+/// Turns each of `strips` around Y in `sectors` steps, with U going once
+/// around. Strips are outlines running down the outside of the shape, with
+/// their own vertices so there can be hard edges between them. Points on
+/// the axis become poles, with one triangle per sector instead of two.
+fn lathe(strips: &[Vec<ProfilePoint>], sectors: u32) -> MeshData {
+    let sectors = sectors.max(3);
+    let mut data = empty();
+    for strip in strips {
+        let base = data.vertices.len() as u32;
+        for point in strip {
+            for j in 0..=sectors {
+                let u = j as f32 / sectors as f32;
+                let (sin, cos) = (u * TAU).sin_cos();
+                let [r, y] = point.position;
+                let [nr, ny] = point.normal;
+                data.vertices.push(MeshVertex {
+                    position: [r * sin, y, r * cos],
+                    tex_coords: [u, point.v],
+                    normal: Vector3::new(nr * sin, ny, nr * cos).normalize().into(),
+                    tangent: [0.0; 4],
+                });
+            }
+        }
+
+        let index = |row: usize, j: u32| base + row as u32 * (sectors + 1) + j;
+        for (row, pair) in strip.windows(2).enumerate() {
+            // sin(PI) isn't quite 0.
+            let on_axis = |point: &ProfilePoint| point.position[0].abs() < 1e-6;
+            let (pole_above, pole_below) = (on_axis(&pair[0]), on_axis(&pair[1]));
+            for j in 0..sectors {
+                if !pole_above {
+                    data.indices.extend_from_slice(&[index(row, j), index(row + 1, j), index(row, j + 1)]);
+                }
+                if !pole_below {
+                    data.indices.extend_from_slice(&[index(row, j + 1), index(row + 1, j), index(row + 1, j + 1)]);
+                }
+            }
+        }
+    }
+    finish(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    /// Every index is in range, every triangle has area, winds
+    /// counter-clockwise seen from the side its vertex normals point to,
+    /// and the normals and tangents are unit length and at right angles.
+    fn check(data: &MeshData) {
+        assert_eq!(data.indices.len() % 3, 0);
+        assert!(data.indices.iter().all(|&i| (i as usize) < data.vertices.len()));
+        for triangle in data.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| &data.vertices[triangle[i] as usize]);
+            let [pa, pb, pc] = [a, b, c].map(|v| Vector3::from(v.position));
+            let face = (pb - pa).cross(pc - pa);
+            assert!(face.magnitude() > 1e-6, "degenerate triangle {:?}", triangle);
+            let normal = Vector3::from(a.normal) + Vector3::from(b.normal) + Vector3::from(c.normal);
+            assert!(face.dot(normal) > 0.0, "triangle {:?} winds clockwise", triangle);
+        }
+        for vertex in &data.vertices {
+            let normal = Vector3::from(vertex.normal);
+            let [x, y, z, w] = vertex.tangent;
+            let tangent = Vector3::new(x, y, z);
+            assert!((normal.magnitude() - 1.0).abs() < 1e-4);
+            assert!((tangent.magnitude() - 1.0).abs() < 1e-4);
+            assert!(normal.dot(tangent).abs() < 1e-4);
+            assert!(w == 1.0 || w == -1.0);
+        }
+    }
+
+    /// Every normal points away from the origin.
+    fn check_outward(data: &MeshData) {
+        for vertex in &data.vertices {
+            let position = Vector3::from(vertex.position);
+            assert!(
+                Vector3::from(vertex.normal).dot(position) > 0.0,
+                "normal {:?} at {:?} points inwards",
+                vertex.normal,
+                vertex.position
+            );
+        }
+    }
+
+    #[test]
+    fn quad() {
+        let data = super::quad(2.0, 1.0);
+        check(&data);
+        assert_eq!((data.vertices.len(), data.indices.len()), (4, 6));
+        assert!(data.vertices.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn plane() {
+        let data = super::plane(4.0, 3);
+        check(&data);
+        assert_eq!((data.vertices.len(), data.indices.len()), (16, 54));
+        assert!(data.vertices.iter().all(|v| v.normal == [0.0, 1.0, 0.0]));
+        assert!(data.vertices.iter().all(|v| v.position[0].abs() <= 2.0 && v.position[2].abs() <= 2.0));
+    }
+
+    #[test]
+    fn cube() {
+        let data = super::cube(1.0, 2);
+        check(&data);
+        check_outward(&data);
+        assert_eq!((data.vertices.len(), data.indices.len()), (6 * 9, 6 * 24));
+    }
+
+    #[test]
+    fn uv_sphere() {
+        let data = super::uv_sphere(1.5, 16, 8);
+        check(&data);
+        check_outward(&data);
+        assert_eq!((data.vertices.len(), data.indices.len()), (9 * 17, 6 * 16 * 7));
+        assert!(data.vertices.iter().all(|v| (Vector3::from(v.position).magnitude() - 1.5).abs() < 1e-4));
+    }
+
+    #[test]
+    fn icosphere() {
+        for subdivisions in 0..3 {
+            let data = super::icosphere(1.0, subdivisions);
+            check(&data);
+            check_outward(&data);
+            let faces = 20 * 4usize.pow(subdivisions);
+            assert_eq!(data.indices.len(), faces * 3);
+            // Euler: V - E + F = 2, with every edge shared by two faces.
+            let positions: HashSet<_> = data.vertices.iter().map(|v| v.position.map(f32::to_bits)).collect();
+            assert_eq!(positions.len(), 2 + faces * 3 / 2 - faces);
+        }
+    }
+
+    #[test]
+    fn icosphere_uvs_dont_wrap_inside_a_triangle() {
+        for subdivisions in 0..4 {
+            let data = super::icosphere(1.0, subdivisions);
+            for triangle in data.indices.chunks_exact(3) {
+                let us = triangle.iter().map(|&i| data.vertices[i as usize].tex_coords[0]);
+                let low = us.clone().fold(f32::MAX, f32::min);
+                let high = us.fold(f32::MIN, f32::max);
+                assert!(high - low <= 0.5 + 1e-4, "triangle {:?} spans {} in U", triangle, high - low);
+            }
+        }
+    }
+
+    #[test]
+    fn cylinder() {
+        let data = super::cylinder(0.5, 2.0, 12);
+        check(&data);
+        check_outward(&data);
+        assert_eq!((data.vertices.len(), data.indices.len()), (6 * 13, 12 * 12));
+    }
+
+    #[test]
+    fn cone() {
+        let data = super::cone(0.5, 2.0, 12);
+        check(&data);
+        check_outward(&data);
+        assert_eq!((data.vertices.len(), data.indices.len()), (4 * 13, 6 * 12));
+    }
+
+    #[test]
+    fn torus() {
+        let data = super::torus(2.0, 0.5, 24, 12);
+        check(&data);
+        assert_eq!((data.vertices.len(), data.indices.len()), (13 * 25, 6 * 24 * 12));
+        for vertex in &data.vertices {
+            let position = Vector3::from(vertex.position);
+            let ring = Vector3::new(position.x, 0.0, position.z).normalize() * 2.0;
+            assert!(Vector3::from(vertex.normal).dot(position - ring) > 0.0);
+        }
+    }
+
+    #[test]
+    fn capsule() {
+        let data = super::capsule(0.5, 1.0, 16, 4);
+        check(&data);
+        check_outward(&data);
+        assert_eq!((data.vertices.len(), data.indices.len()), (10 * 17, 12 * 16 * 4));
+        let top = data.vertices.iter().map(|v| v.position[1]).fold(f32::MIN, f32::max);
+        assert!((top - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn zero_counts_are_raised() {
+        for data in [
+            super::plane(1.0, 0),
+            super::cube(1.0, 0),
+            super::uv_sphere(1.0, 0, 0),
+            super::cylinder(0.5, 1.0, 0),
+            super::cone(0.5, 1.0, 0),
+            super::torus(2.0, 0.5, 0, 0),
+            super::capsule(0.5, 1.0, 0, 0),
+        ] {
+            check(&data);
+            assert!(!data.indices.is_empty());
+            assert!(data.vertices.iter().all(|v| v.position.iter().chain(&v.normal).chain(&v.tex_coords).all(|x| x.is_finite())));
+        }
+        let data = super::uv_sphere(1.0, 0, 0);
+        assert_eq!((data.vertices.len(), data.indices.len()), (3 * 4, 6 * 3));
+    }
+}
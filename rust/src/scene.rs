@@ -0,0 +1,405 @@
+use std::ops::Range;
+
+use cgmath::{InnerSpace, Matrix4, One, Quaternion, SquareMatrix, Transform as _, Vector3};
+
+use crate::assets::Handle;
+use crate::camera::Camera;
+use crate::light::{PointLight, SpotLight};
+use crate::material::Material;
+use crate::mesh::{InstanceRaw, Mesh};
+
+/// A node added to a [`SceneGraph`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+/// Where a node sits relative to its parent: scaled, then rotated, then
+/// translated.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+/// A mesh drawn at a node.
+#[derive(Clone, Debug)]
+pub struct MeshAttachment {
+    pub mesh: Handle<Mesh>,
+    pub material: Handle<Material>,
+    /// Multiplies the mesh's vertex colours.
+    pub color: [f32; 4],
+}
+
+/// A light carried by a node. Its position and direction are in the node's
+/// space.
+#[derive(Clone, Debug)]
+pub enum LightAttachment {
+    Point(PointLight),
+    Spot(SpotLight),
+}
+
+#[derive(Default)]
+pub struct NodeDescriptor<'a> {
+    pub name: &'a str,
+    pub transform: Transform,
+    pub mesh: Option<MeshAttachment>,
+    pub light: Option<LightAttachment>,
+    /// A camera looking from `eye` to `target` in the node's space. Its
+    /// aspect is taken from wherever it ends up being used.
+    pub camera: Option<Camera>,
+}
+
+pub struct Node {
+    pub name: String,
+    pub mesh: Option<MeshAttachment>,
+    pub light: Option<LightAttachment>,
+    pub camera: Option<Camera>,
+    transform: Transform,
+    children: Vec<NodeId>,
+    world: Matrix4<f32>,
+    /// The transform changed since the world matrix was last worked out.
+    dirty: bool,
+}
+
+impl Node {
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    /// Node space to world space, as of the last
+    /// [`SceneGraph::update_world_matrices`].
+    pub fn world_matrix(&self) -> Matrix4<f32> {
+        self.world
+    }
+}
+
+/// One indexed draw of a mesh with a material, for a run of consecutive
+/// instances in the list built by [`SceneGraph::draw_list`].
+pub struct DrawCall {
+    pub mesh: Handle<Mesh>,
+    pub material: Handle<Material>,
+    pub instances: Range<u32>,
+}
+
+/// A tree of nodes, each placed relative to its parent and carrying
+/// meshes, lights and cameras along with it.
+///
+/// Moving a node only marks it dirty, the world matrices of it and
+/// everything below it are worked out again by the next
+/// [`SceneGraph::update_world_matrices`].
+#[derive(Default)]
+pub struct SceneGraph {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+    /// Something was added or moved since the last update.
+    changed: bool,
+}
+
+impl SceneGraph {
+    /// Adds a node under `parent`, or as a root without one.
+    pub fn add(&mut self, parent: Option<NodeId>, desc: NodeDescriptor) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node {
+            name: desc.name.to_string(),
+            mesh: desc.mesh,
+            light: desc.light,
+            camera: desc.camera,
+            transform: desc.transform,
+            children: Vec::new(),
+            world: Matrix4::identity(),
+            dirty: true,
+        });
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+        self.changed = true;
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    /// The first node called `name`, in the order they were added.
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes().find(|(_, node)| node.name == name).map(|(id, _)| id)
+    }
+
+    pub fn set_transform(&mut self, id: NodeId, transform: Transform) {
+        let node = &mut self.nodes[id.0];
+        node.transform = transform;
+        node.dirty = true;
+        self.changed = true;
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    /// Every node with its id, in the order they were added.
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes.iter().enumerate().map(|(i, node)| (NodeId(i), node))
+    }
+
+    /// Works out the world matrices of dirty nodes and their descendants.
+    /// Returns whether anything changed since the last call, in which case
+    /// the draw list and lights should be fetched again.
+    pub fn update_world_matrices(&mut self) -> bool {
+        if !self.changed {
+            return false;
+        }
+        // (node, parent's world matrix, whether the parent's changed)
+        let mut stack: Vec<_> = self
+            .roots
+            .iter()
+            .rev()
+            .map(|&root| (root, Matrix4::identity(), false))
+            .collect();
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = &mut self.nodes[id.0];
+            let changed = node.dirty || parent_changed;
+            if changed {
+                node.world = parent_world * node.transform.matrix();
+                node.dirty = false;
+            }
+            let world = node.world;
+            stack.extend(node.children.iter().rev().map(|&child| (child, world, changed)));
+        }
+        self.changed = false;
+        true
+    }
+
+    /// Calls `visit` with every node, parents before their children.
+    pub fn traverse(&self, mut visit: impl FnMut(NodeId, &Node)) {
+        let mut stack: Vec<_> = self.roots.iter().rev().copied().collect();
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id.0];
+            visit(id, node);
+            stack.extend(node.children.iter().rev());
+        }
+    }
+
+    /// The instances of every mesh in the graph, grouped into one draw call
+    /// for each mesh and material.
+    pub fn draw_list(&self) -> (Vec<InstanceRaw>, Vec<DrawCall>) {
+        let mut objects = Vec::new();
+        self.traverse(|_, node| {
+            if let Some(mesh) = &node.mesh {
+                objects.push((mesh.mesh.clone(), mesh.material.clone(), InstanceRaw::new(node.world, mesh.color)));
+            }
+        });
+        // Stable, so instances keep their traversal order within a call.
+        objects.sort_by(|(a_mesh, a_material, _), (b_mesh, b_material, _)| {
+            (a_mesh, a_material).cmp(&(b_mesh, b_material))
+        });
+
+        let mut draw_calls: Vec<DrawCall> = Vec::new();
+        for (i, (mesh, material, _)) in objects.iter().enumerate() {
+            let i = i as u32;
+            match draw_calls.last_mut() {
+                Some(call) if call.mesh == *mesh && call.material == *material => call.instances.end = i + 1,
+                _ => draw_calls.push(DrawCall {
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                    instances: i..i + 1,
+                }),
+            }
+        }
+        let instances = objects.into_iter().map(|(_, _, instance)| instance).collect();
+        (instances, draw_calls)
+    }
+
+    /// The point and spot lights in the graph, moved into world space.
+    pub fn lights(&self) -> (Vec<PointLight>, Vec<SpotLight>) {
+        let mut point_lights = Vec::new();
+        let mut spot_lights = Vec::new();
+        self.traverse(|_, node| match &node.light {
+            Some(LightAttachment::Point(light)) => point_lights.push(PointLight {
+                position: node.world.transform_point(light.position),
+                ..*light
+            }),
+            Some(LightAttachment::Spot(light)) => spot_lights.push(SpotLight {
+                position: node.world.transform_point(light.position),
+                direction: node.world.transform_vector(light.direction).normalize(),
+                ..*light
+            }),
+            None => {}
+        });
+        (point_lights, spot_lights)
+    }
+
+    /// The camera at `id` in world space, with `aspect`, if it has one.
+    pub fn camera(&self, id: NodeId, aspect: f32) -> Option<Camera> {
+        let node = &self.nodes[id.0];
+        node.camera.map(|camera| Camera {
+            eye: node.world.transform_point(camera.eye),
+            target: node.world.transform_point(camera.target),
+            up: node.world.transform_vector(camera.up).normalize(),
+            aspect,
+            ..camera
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Point3, Rotation3};
+
+    use super::*;
+
+    fn moved(x: f32, y: f32, z: f32) -> Transform {
+        Transform {
+            translation: Vector3::new(x, y, z),
+            ..Default::default()
+        }
+    }
+
+    fn origin(graph: &SceneGraph, id: NodeId) -> Point3<f32> {
+        graph.node(id).world_matrix().transform_point(Point3::new(0.0, 0.0, 0.0))
+    }
+
+    fn attachment(mesh: usize, material: usize, marker: f32) -> Option<MeshAttachment> {
+        Some(MeshAttachment {
+            mesh: Handle::detached(mesh),
+            material: Handle::detached(material),
+            color: [marker, 0.0, 0.0, 1.0],
+        })
+    }
+
+    #[test]
+    fn moving_a_parent_moves_its_children() {
+        let mut graph = SceneGraph::default();
+        let parent = graph.add(None, NodeDescriptor {
+            name: "parent",
+            transform: moved(1.0, 0.0, 0.0),
+            ..Default::default()
+        });
+        let child = graph.add(Some(parent), NodeDescriptor {
+            name: "child",
+            transform: moved(0.0, 2.0, 0.0),
+            ..Default::default()
+        });
+        let grandchild = graph.add(Some(child), NodeDescriptor {
+            name: "grandchild",
+            transform: moved(0.0, 0.0, 3.0),
+            ..Default::default()
+        });
+        assert!(graph.update_world_matrices());
+        assert_eq!(origin(&graph, grandchild), Point3::new(1.0, 2.0, 3.0));
+        assert!(!graph.update_world_matrices(), "nothing moved");
+
+        graph.set_transform(parent, moved(-1.0, 0.0, 0.0));
+        assert!(graph.update_world_matrices());
+        assert_eq!(origin(&graph, child), Point3::new(-1.0, 2.0, 0.0));
+        assert_eq!(origin(&graph, grandchild), Point3::new(-1.0, 2.0, 3.0));
+        assert!(!graph.update_world_matrices());
+
+        // A child moving leaves its parent where it was.
+        graph.set_transform(child, moved(0.0, 5.0, 0.0));
+        assert!(graph.update_world_matrices());
+        assert_eq!(origin(&graph, parent), Point3::new(-1.0, 0.0, 0.0));
+        assert_eq!(origin(&graph, grandchild), Point3::new(-1.0, 5.0, 3.0));
+    }
+
+    #[test]
+    fn draw_list_groups_by_mesh_and_material() {
+        let mut graph = SceneGraph::default();
+        let mut nodes = Vec::new();
+        // (mesh, material), in the order the nodes are traversed.
+        for (i, (mesh, material)) in [(1, 0), (0, 0), (1, 0), (0, 1), (0, 0)].into_iter().enumerate() {
+            nodes.push(graph.add(None, NodeDescriptor {
+                name: "node",
+                transform: moved(i as f32, 0.0, 0.0),
+                mesh: attachment(mesh, material, i as f32),
+                ..Default::default()
+            }));
+        }
+        graph.add(None, NodeDescriptor {
+            name: "empty",
+            ..Default::default()
+        });
+        graph.update_world_matrices();
+
+        let (instances, calls) = graph.draw_list();
+        let calls: Vec<_> = calls.iter().map(|call| (call.mesh.clone(), call.material.clone(), call.instances.clone())).collect();
+        assert_eq!(calls, vec![
+            (Handle::detached(0), Handle::detached(0), 0..2),
+            (Handle::detached(0), Handle::detached(1), 2..3),
+            (Handle::detached(1), Handle::detached(0), 3..5),
+        ]);
+        // Within a call, instances stay in traversal order.
+        let expected: Vec<InstanceRaw> = [1, 4, 3, 0, 2]
+            .map(|i| InstanceRaw::new(graph.node(nodes[i]).world_matrix(), [i as f32, 0.0, 0.0, 1.0]))
+            .into();
+        assert_eq!(bytemuck::cast_slice::<_, u8>(&instances), bytemuck::cast_slice::<_, u8>(&expected));
+    }
+
+    #[test]
+    fn lights_are_moved_into_world_space() {
+        let mut graph = SceneGraph::default();
+        let parent = graph.add(None, NodeDescriptor {
+            name: "parent",
+            transform: Transform {
+                translation: Vector3::new(0.0, 4.0, 0.0),
+                rotation: Quaternion::from_angle_y(Deg(90.0)),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        graph.add(Some(parent), NodeDescriptor {
+            name: "point",
+            light: Some(LightAttachment::Point(PointLight {
+                position: Point3::new(1.0, 0.0, 0.0),
+                color: [1.0; 3],
+                intensity: 2.0,
+                range: 10.0,
+            })),
+            ..Default::default()
+        });
+        graph.add(Some(parent), NodeDescriptor {
+            name: "spot",
+            light: Some(LightAttachment::Spot(SpotLight {
+                position: Point3::new(0.0, 0.0, 0.0),
+                direction: Vector3::new(2.0, 0.0, 0.0),
+                color: [1.0; 3],
+                intensity: 1.0,
+                range: 10.0,
+                inner_angle: Deg(20.0),
+                outer_angle: Deg(30.0),
+            })),
+            ..Default::default()
+        });
+        graph.update_world_matrices();
+
+        let (points, spots) = graph.lights();
+        assert_eq!((points.len(), spots.len()), (1, 1));
+        // A quarter turn about y takes +x to -z.
+        assert!((points[0].position - Point3::new(0.0, 4.0, -1.0)).magnitude() < 1e-5);
+        assert_eq!((points[0].intensity, points[0].range), (2.0, 10.0));
+        assert!((spots[0].position - Point3::new(0.0, 4.0, 0.0)).magnitude() < 1e-5);
+        assert!((spots[0].direction - Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-5);
+    }
+}
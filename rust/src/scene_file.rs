@@ -0,0 +1,984 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+use cgmath::{Deg, Euler, Quaternion};
+use ron::extensions::Extensions;
+use serde::{Deserialize, Serialize};
+
+use crate::assets::{Assets, Handle, MaterialTextures};
+use crate::camera::Camera;
+use crate::light::{DirectionalLight, PointLight, SpotLight};
+use crate::material::{Material, MaterialDescriptor, MaterialLayout};
+use crate::mesh::{Mesh, MeshData};
+use crate::primitives;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::scene::NodeId;
+use crate::scene::{LightAttachment, MeshAttachment, NodeDescriptor, SceneGraph, Transform};
+use crate::texture::Texture;
+
+/// A scene as written in a RON file: the textures, meshes and materials it
+/// uses, each under a name, and a tree of nodes using them by name.
+///
+/// ```ron
+/// #![enable(implicit_some)]
+/// (
+///     camera: (eye: (0.0, 6.0, 12.0), target: (0.0, 1.0, 0.0)),
+///     meshes: [(name: "cube", source: Cube(size: 1.0))],
+///     materials: [(name: "red", base_color_factor: (0.8, 0.3, 0.3, 1.0))],
+///     nodes: [(name: "box", mesh: (mesh: "cube", material: "red"))],
+/// )
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile {
+    #[serde(default = "default_clear_color")]
+    pub clear_color: [f32; 3],
+    pub camera: CameraDesc,
+    #[serde(default = "default_sun")]
+    pub sun: SunDesc,
+    #[serde(default)]
+    pub textures: Vec<TextureDesc>,
+    #[serde(default)]
+    pub meshes: Vec<MeshDesc>,
+    #[serde(default)]
+    pub materials: Vec<MaterialDesc>,
+    #[serde(default)]
+    pub nodes: Vec<NodeDesc>,
+}
+
+fn default_clear_color() -> [f32; 3] {
+    [0.1, 0.2, 0.3]
+}
+
+fn default_sun() -> SunDesc {
+    SunDesc {
+        direction: [-0.4, -1.0, -0.3],
+        color: [1.0, 0.95, 0.85],
+        intensity: 3.0,
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDesc {
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+    #[serde(default = "default_up")]
+    pub up: [f32; 3],
+    /// Vertical field of view in degrees.
+    #[serde(default = "default_fovy")]
+    pub fovy: f32,
+    #[serde(default = "default_znear")]
+    pub znear: f32,
+    #[serde(default = "default_zfar")]
+    pub zfar: f32,
+}
+
+fn default_up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_fovy() -> f32 {
+    45.0
+}
+
+fn default_znear() -> f32 {
+    0.1
+}
+
+fn default_zfar() -> f32 {
+    100.0
+}
+
+impl CameraDesc {
+    /// The camera, with its aspect left for whoever uses it to fill in.
+    pub fn camera(&self) -> Camera {
+        Camera {
+            eye: self.eye.into(),
+            target: self.target.into(),
+            up: self.up.into(),
+            aspect: 1.0,
+            fovy: self.fovy,
+            znear: self.znear,
+            zfar: self.zfar,
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self {
+            eye: camera.eye.into(),
+            target: camera.target.into(),
+            up: camera.up.into(),
+            fovy: camera.fovy,
+            znear: camera.znear,
+            zfar: camera.zfar,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SunDesc {
+    /// The direction the light travels in.
+    pub direction: [f32; 3],
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl SunDesc {
+    pub fn light(&self) -> DirectionalLight {
+        DirectionalLight {
+            direction: self.direction.into(),
+            color: self.color,
+            intensity: self.intensity,
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_light(light: &DirectionalLight) -> Self {
+        Self {
+            direction: light.direction.into(),
+            color: light.color,
+            intensity: light.intensity,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TextureDesc {
+    pub name: String,
+    pub source: TextureSource,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum TextureSource {
+    /// An sRGB image file, for colour maps.
+    File(String),
+    /// A linear normal map of `tiles`x`tiles` bevelled tiles, `size` pixels
+    /// across.
+    TileNormalMap { size: u32, tiles: u32 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MeshDesc {
+    pub name: String,
+    pub source: MeshSource,
+}
+
+/// Where a mesh comes from: a file, or one of the shapes in
+/// [`primitives`], with the same parameters.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum MeshSource {
+    /// A Wavefront OBJ file.
+    Obj(String),
+    Quad { width: f32, height: f32 },
+    Plane {
+        size: f32,
+        #[serde(default = "one_subdivision")]
+        subdivisions: u32,
+    },
+    Cube {
+        size: f32,
+        #[serde(default = "one_subdivision")]
+        subdivisions: u32,
+    },
+    UvSphere { radius: f32, sectors: u32, stacks: u32 },
+    Icosphere { radius: f32, subdivisions: u32 },
+    Cylinder { radius: f32, height: f32, sectors: u32 },
+    Cone { radius: f32, height: f32, sectors: u32 },
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+        major_segments: u32,
+        minor_segments: u32,
+    },
+    Capsule { radius: f32, height: f32, sectors: u32, rings: u32 },
+}
+
+fn one_subdivision() -> u32 {
+    1
+}
+
+impl MeshSource {
+    /// The shape, or `None` for a file.
+    fn primitive(&self) -> Option<MeshData> {
+        Some(match *self {
+            MeshSource::Obj(_) => return None,
+            MeshSource::Quad { width, height } => primitives::quad(width, height),
+            MeshSource::Plane { size, subdivisions } => primitives::plane(size, subdivisions),
+            MeshSource::Cube { size, subdivisions } => primitives::cube(size, subdivisions),
+            MeshSource::UvSphere { radius, sectors, stacks } => primitives::uv_sphere(radius, sectors, stacks),
+            MeshSource::Icosphere { radius, subdivisions } => primitives::icosphere(radius, subdivisions),
+            MeshSource::Cylinder { radius, height, sectors } => primitives::cylinder(radius, height, sectors),
+            MeshSource::Cone { radius, height, sectors } => primitives::cone(radius, height, sectors),
+            MeshSource::Torus { major_radius, minor_radius, major_segments, minor_segments } => {
+                primitives::torus(major_radius, minor_radius, major_segments, minor_segments)
+            }
+            MeshSource::Capsule { radius, height, sectors, rings } => {
+                primitives::capsule(radius, height, sectors, rings)
+            }
+        })
+    }
+
+    /// What's wrong with the parameters, if anything.
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut positive = |name: &str, value: f32| {
+            if value <= 0.0 {
+                problems.push(format!("{} {} isn't positive", name, value));
+            }
+        };
+        // (name, count, the least it can be)
+        let mut counts = Vec::new();
+        match *self {
+            MeshSource::Obj(ref path) => {
+                if path.is_empty() {
+                    return vec!["path is empty".to_string()];
+                }
+            }
+            MeshSource::Quad { width, height } => {
+                positive("width", width);
+                positive("height", height);
+            }
+            MeshSource::Plane { size, subdivisions } | MeshSource::Cube { size, subdivisions } => {
+                positive("size", size);
+                counts.push(("subdivisions", subdivisions, 1));
+            }
+            MeshSource::UvSphere { radius, sectors, stacks } => {
+                positive("radius", radius);
+                counts.extend([("sectors", sectors, 3), ("stacks", stacks, 2)]);
+            }
+            MeshSource::Icosphere { radius, .. } => positive("radius", radius),
+            MeshSource::Cylinder { radius, height, sectors } | MeshSource::Cone { radius, height, sectors } => {
+                positive("radius", radius);
+                positive("height", height);
+                counts.push(("sectors", sectors, 3));
+            }
+            MeshSource::Torus { major_radius, minor_radius, major_segments, minor_segments } => {
+                positive("major_radius", major_radius);
+                positive("minor_radius", minor_radius);
+                counts.extend([("major_segments", major_segments, 3), ("minor_segments", minor_segments, 3)]);
+            }
+            MeshSource::Capsule { radius, height, sectors, rings } => {
+                positive("radius", radius);
+                positive("height", height);
+                counts.extend([("sectors", sectors, 3), ("rings", rings, 1)]);
+            }
+        }
+        for (name, count, least) in counts {
+            if count < least {
+                problems.push(format!("{} {} is less than {}", name, count, least));
+            }
+        }
+        if let MeshSource::Icosphere { subdivisions, .. } = *self {
+            // Each one makes four times the triangles.
+            if subdivisions > 6 {
+                problems.push(format!("{} subdivisions is more than 6", subdivisions));
+            }
+        }
+        problems
+    }
+}
+
+/// A [`MaterialDescriptor`] with its textures named.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaterialDesc {
+    pub name: String,
+    pub base_color_factor: [f32; 4],
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub emissive_factor: [f32; 3],
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_color_texture: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metallic_roughness_texture: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normal_texture: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub occlusion_texture: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emissive_texture: Option<String>,
+}
+
+impl Default for MaterialDesc {
+    fn default() -> Self {
+        let desc = MaterialDescriptor::default();
+        Self {
+            name: String::new(),
+            base_color_factor: desc.base_color_factor,
+            metallic_factor: desc.metallic_factor,
+            roughness_factor: desc.roughness_factor,
+            emissive_factor: desc.emissive_factor,
+            normal_scale: desc.normal_scale,
+            occlusion_strength: desc.occlusion_strength,
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+        }
+    }
+}
+
+impl MaterialDesc {
+    /// Every texture slot with its name in the file, for errors.
+    fn textures(&self) -> [(&'static str, &Option<String>); 5] {
+        [
+            ("base_color_texture", &self.base_color_texture),
+            ("metallic_roughness_texture", &self.metallic_roughness_texture),
+            ("normal_texture", &self.normal_texture),
+            ("occlusion_texture", &self.occlusion_texture),
+            ("emissive_texture", &self.emissive_texture),
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NodeDesc {
+    pub name: String,
+    #[serde(default = "zero", skip_serializing_if = "is_zero")]
+    pub translation: [f32; 3],
+    /// Euler angles in degrees about X, then Y, then Z.
+    #[serde(default = "zero", skip_serializing_if = "is_zero")]
+    pub rotation: [f32; 3],
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    pub scale: [f32; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<NodeMeshDesc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub light: Option<LightDesc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera: Option<CameraDesc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<NodeDesc>,
+}
+
+fn zero() -> [f32; 3] {
+    [0.0; 3]
+}
+
+fn is_zero(v: &[f32; 3]) -> bool {
+    *v == zero()
+}
+
+fn one() -> [f32; 3] {
+    [1.0; 3]
+}
+
+fn is_one(v: &[f32; 3]) -> bool {
+    *v == one()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NodeMeshDesc {
+    pub mesh: String,
+    pub material: String,
+    #[serde(default = "white", skip_serializing_if = "is_white")]
+    pub color: [f32; 4],
+}
+
+fn white() -> [f32; 4] {
+    [1.0; 4]
+}
+
+fn is_white(color: &[f32; 4]) -> bool {
+    *color == white()
+}
+
+/// A light in its node's space.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum LightDesc {
+    Point {
+        #[serde(default = "zero", skip_serializing_if = "is_zero")]
+        position: [f32; 3],
+        color: [f32; 3],
+        intensity: f32,
+        range: f32,
+    },
+    Spot {
+        #[serde(default = "zero", skip_serializing_if = "is_zero")]
+        position: [f32; 3],
+        direction: [f32; 3],
+        color: [f32; 3],
+        intensity: f32,
+        range: f32,
+        /// Half angles in degrees.
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+impl LightDesc {
+    fn attachment(&self) -> LightAttachment {
+        match *self {
+            LightDesc::Point { position, color, intensity, range } => LightAttachment::Point(PointLight {
+                position: position.into(),
+                color,
+                intensity,
+                range,
+            }),
+            LightDesc::Spot { position, direction, color, intensity, range, inner_angle, outer_angle } => {
+                LightAttachment::Spot(SpotLight {
+                    position: position.into(),
+                    direction: direction.into(),
+                    color,
+                    intensity,
+                    range,
+                    inner_angle: Deg(inner_angle),
+                    outer_angle: Deg(outer_angle),
+                })
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn from_attachment(light: &LightAttachment) -> Self {
+        match light {
+            LightAttachment::Point(light) => LightDesc::Point {
+                position: light.position.into(),
+                color: light.color,
+                intensity: light.intensity,
+                range: light.range,
+            },
+            LightAttachment::Spot(light) => LightDesc::Spot {
+                position: light.position.into(),
+                direction: light.direction.into(),
+                color: light.color,
+                intensity: light.intensity,
+                range: light.range,
+                inner_angle: light.inner_angle.0,
+                outer_angle: light.outer_angle.0,
+            },
+        }
+    }
+}
+
+/// Everything wrong with a scene file, each prefixed with where it is.
+#[derive(Default)]
+struct Problems(Vec<String>);
+
+impl Problems {
+    fn add(&mut self, at: &str, problem: String) {
+        self.0.push(format!("{}: {}", at, problem));
+    }
+
+    fn check(&mut self, ok: bool, at: &str, problem: impl FnOnce() -> String) {
+        if !ok {
+            self.add(at, problem());
+        }
+    }
+
+    /// Checks `names` are given and used once, returning them.
+    fn names<'a>(&mut self, list: &str, names: impl Iterator<Item = &'a str>) -> HashSet<&'a str> {
+        let mut seen = HashSet::new();
+        for (i, name) in names.enumerate() {
+            let at = format!("{}[{}]", list, i);
+            self.check(!name.is_empty(), &at, || "name is empty".to_string());
+            self.check(seen.insert(name) || name.is_empty(), &at, || format!("{:?} is already used", name));
+        }
+        seen
+    }
+}
+
+impl SceneFile {
+    /// Parses and validates a scene file. Syntax errors and unknown fields
+    /// come with a line and column; references to things that aren't in the
+    /// file and values that make no sense are all listed, each with where in
+    /// the file it is, such as `nodes[2].children[0] "moon"`.
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        let scene: Self = ron::Options::default()
+            .with_default_extension(Extensions::IMPLICIT_SOME)
+            .from_str(source)?;
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    /// Pretty RON that [`SceneFile::parse`] reads back.
+    #[cfg(any(test, not(target_arch = "wasm32")))]
+    pub fn to_ron(&self) -> anyhow::Result<String> {
+        let config = ron::ser::PrettyConfig::new()
+            // Deep enough for nodes to nest a few levels before they're
+            // written on one line each, which keeps long lists of lights
+            // readable.
+            .depth_limit(4)
+            .extensions(Extensions::IMPLICIT_SOME);
+        Ok(ron::ser::to_string_pretty(self, config)?)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let mut problems = Problems::default();
+
+        validate_camera(&mut problems, "camera", &self.camera);
+        problems.check(self.sun.direction != [0.0; 3], "sun", || "direction is zero".to_string());
+
+        let textures = problems.names("textures", self.textures.iter().map(|t| t.name.as_str()));
+        for (i, texture) in self.textures.iter().enumerate() {
+            let at = format!("textures[{}] {:?}", i, texture.name);
+            match &texture.source {
+                TextureSource::File(path) => problems.check(!path.is_empty(), &at, || "path is empty".to_string()),
+                &TextureSource::TileNormalMap { size, tiles } => {
+                    problems.check(tiles > 0 && tiles <= size, &at, || {
+                        format!("can't fit {} tiles in {} pixels", tiles, size)
+                    })
+                }
+            }
+        }
+
+        let meshes = problems.names("meshes", self.meshes.iter().map(|m| m.name.as_str()));
+        for (i, mesh) in self.meshes.iter().enumerate() {
+            let at = format!("meshes[{}] {:?}", i, mesh.name);
+            for problem in mesh.source.problems() {
+                problems.add(&at, problem);
+            }
+        }
+
+        let materials = problems.names("materials", self.materials.iter().map(|m| m.name.as_str()));
+        for (i, material) in self.materials.iter().enumerate() {
+            let at = format!("materials[{}] {:?}", i, material.name);
+            for (slot, texture) in material.textures() {
+                if let Some(texture) = texture {
+                    problems.check(textures.contains(texture.as_str()), &at, || {
+                        format!("{} {:?} isn't in textures", slot, texture)
+                    });
+                }
+            }
+            problems.check((0.0..=1.0).contains(&material.metallic_factor), &at, || {
+                format!("metallic_factor {} isn't between 0 and 1", material.metallic_factor)
+            });
+            problems.check((0.0..=1.0).contains(&material.roughness_factor), &at, || {
+                format!("roughness_factor {} isn't between 0 and 1", material.roughness_factor)
+            });
+        }
+
+        // (node, where it is)
+        let mut stack: Vec<_> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, node)| (node, format!("nodes[{}]", i)))
+            .collect();
+        while let Some((node, path)) = stack.pop() {
+            let at = format!("{} {:?}", path, node.name);
+            problems.check(node.scale.iter().all(|&s| s != 0.0), &at, || "scale has a zero in it".to_string());
+            if let Some(mesh) = &node.mesh {
+                problems.check(meshes.contains(mesh.mesh.as_str()), &at, || {
+                    format!("mesh {:?} isn't in meshes", mesh.mesh)
+                });
+                problems.check(materials.contains(mesh.material.as_str()), &at, || {
+                    format!("material {:?} isn't in materials", mesh.material)
+                });
+            }
+            match node.light {
+                Some(LightDesc::Point { range, .. }) => {
+                    problems.check(range > 0.0, &at, || format!("light range {} isn't positive", range))
+                }
+                Some(LightDesc::Spot { direction, range, inner_angle, outer_angle, .. }) => {
+                    problems.check(range > 0.0, &at, || format!("light range {} isn't positive", range));
+                    problems.check(direction != [0.0; 3], &at, || "light direction is zero".to_string());
+                    problems.check(0.0 <= inner_angle && inner_angle <= outer_angle && outer_angle < 90.0, &at, || {
+                        format!(
+                            "light angles {} and {} aren't 0 <= inner <= outer < 90",
+                            inner_angle, outer_angle
+                        )
+                    });
+                }
+                None => {}
+            }
+            if let Some(camera) = &node.camera {
+                validate_camera(&mut problems, &at, camera);
+            }
+            stack.extend(
+                node.children
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(i, child)| (child, format!("{}.children[{}]", path, i))),
+            );
+        }
+
+        if problems.0.is_empty() {
+            return Ok(());
+        }
+        anyhow::bail!("invalid scene:\n  {}", problems.0.join("\n  "))
+    }
+
+    /// Loads the scene's assets and builds its graph. Files load in the
+    /// background like any other asset.
+    pub fn instantiate(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        assets: &mut Assets,
+        material_layout: &MaterialLayout,
+    ) -> LoadedScene {
+        let textures: Vec<(&str, Handle<Texture>)> = self
+            .textures
+            .iter()
+            .map(|desc| {
+                let texture = match desc.source {
+                    TextureSource::File(ref path) => assets.load_texture(path),
+                    TextureSource::TileNormalMap { size, tiles } => assets.add_texture(&desc.name, || {
+                        Texture::from_image_with_format(
+                            device,
+                            queue,
+                            &tile_normal_map(size, tiles),
+                            wgpu::TextureFormat::Rgba8Unorm,
+                            Some(&desc.name),
+                        )
+                    }),
+                };
+                (desc.name.as_str(), texture)
+            })
+            .collect();
+        let texture = |name: &Option<String>| {
+            let name = name.as_deref()?;
+            textures.iter().find(|(n, _)| *n == name).map(|(_, texture)| texture.clone())
+        };
+
+        let meshes: Vec<(String, Handle<Mesh>)> = self
+            .meshes
+            .iter()
+            .map(|desc| {
+                let mesh = match &desc.source {
+                    MeshSource::Obj(path) => assets.load_mesh(path),
+                    source => assets.mesh(&desc.name, || {
+                        let data = source.primitive().expect("everything but files is a primitive");
+                        Mesh::new(device, &desc.name, &data)
+                    }),
+                };
+                (desc.name.clone(), mesh)
+            })
+            .collect();
+
+        let materials: Vec<(String, Handle<Material>)> = self
+            .materials
+            .iter()
+            .map(|desc| {
+                let material = assets.material(
+                    device,
+                    material_layout,
+                    &MaterialDescriptor {
+                        name: &desc.name,
+                        base_color_factor: desc.base_color_factor,
+                        metallic_factor: desc.metallic_factor,
+                        roughness_factor: desc.roughness_factor,
+                        emissive_factor: desc.emissive_factor,
+                        normal_scale: desc.normal_scale,
+                        occlusion_strength: desc.occlusion_strength,
+                        ..Default::default()
+                    },
+                    MaterialTextures {
+                        base_color: texture(&desc.base_color_texture),
+                        metallic_roughness: texture(&desc.metallic_roughness_texture),
+                        normal: texture(&desc.normal_texture),
+                        occlusion: texture(&desc.occlusion_texture),
+                        emissive: texture(&desc.emissive_texture),
+                    },
+                );
+                (desc.name.clone(), material)
+            })
+            .collect();
+
+        let source = SceneSource {
+            #[cfg(not(target_arch = "wasm32"))]
+            textures: self.textures.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            meshes: self.meshes.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            materials: self.materials.clone(),
+            mesh_handles: meshes,
+            material_handles: materials,
+        };
+        LoadedScene {
+            graph: self.build_graph(&source),
+            camera: self.camera.camera(),
+            sun: self.sun.light(),
+            clear_color: self.clear_color,
+            #[cfg(not(target_arch = "wasm32"))]
+            source,
+        }
+    }
+
+    /// The scene's node tree, using the meshes and materials in `source`.
+    fn build_graph(&self, source: &SceneSource) -> SceneGraph {
+        let mut graph = SceneGraph::default();
+        // (node, its parent)
+        let mut stack: Vec<_> = self.nodes.iter().rev().map(|node| (node, None)).collect();
+        while let Some((node, parent)) = stack.pop() {
+            let mesh = node.mesh.as_ref().map(|mesh| MeshAttachment {
+                mesh: source.mesh_handle(&mesh.mesh),
+                material: source.material_handle(&mesh.material),
+                color: mesh.color,
+            });
+            let id = graph.add(parent, NodeDescriptor {
+                name: &node.name,
+                transform: Transform {
+                    translation: node.translation.into(),
+                    rotation: Quaternion::from(Euler {
+                        x: Deg(node.rotation[0]),
+                        y: Deg(node.rotation[1]),
+                        z: Deg(node.rotation[2]),
+                    }),
+                    scale: node.scale.into(),
+                },
+                mesh,
+                light: node.light.as_ref().map(LightDesc::attachment),
+                camera: node.camera.as_ref().map(CameraDesc::camera),
+            });
+            stack.extend(node.children.iter().rev().map(|child| (child, Some(id))));
+        }
+        graph
+    }
+}
+
+fn validate_camera(problems: &mut Problems, at: &str, camera: &CameraDesc) {
+    problems.check(camera.eye != camera.target, at, || "camera eye and target are the same".to_string());
+    problems.check(camera.fovy > 0.0 && camera.fovy < 180.0, at, || {
+        format!("camera fovy {} isn't between 0 and 180", camera.fovy)
+    });
+    problems.check(camera.znear > 0.0 && camera.zfar > camera.znear, at, || {
+        format!("camera znear {} and zfar {} aren't 0 < znear < zfar", camera.znear, camera.zfar)
+    });
+}
+
+/// What [`SceneFile::instantiate`] made.
+pub struct LoadedScene {
+    pub graph: SceneGraph,
+    pub camera: Camera,
+    pub sun: DirectionalLight,
+    pub clear_color: [f32; 3],
+    #[cfg(not(target_arch = "wasm32"))]
+    pub source: SceneSource,
+}
+
+/// The assets a scene was loaded with. Natively it's kept to write the
+/// scene out again with `SceneSource::to_file`, holding on to the meshes and
+/// materials so they stay loaded whether or not a node uses them right now.
+pub struct SceneSource {
+    #[cfg(not(target_arch = "wasm32"))]
+    textures: Vec<TextureDesc>,
+    #[cfg(not(target_arch = "wasm32"))]
+    meshes: Vec<MeshDesc>,
+    #[cfg(not(target_arch = "wasm32"))]
+    materials: Vec<MaterialDesc>,
+    mesh_handles: Vec<(String, Handle<Mesh>)>,
+    material_handles: Vec<(String, Handle<Material>)>,
+}
+
+impl SceneSource {
+    fn mesh_handle(&self, name: &str) -> Handle<Mesh> {
+        let (_, mesh) = self.mesh_handles.iter().find(|(n, _)| n == name).expect("validated");
+        mesh.clone()
+    }
+
+    fn material_handle(&self, name: &str) -> Handle<Material> {
+        let (_, material) = self.material_handles.iter().find(|(n, _)| n == name).expect("validated");
+        material.clone()
+    }
+
+    /// A scene file for `graph` as it is now, seen by `camera`. Nodes can
+    /// only use meshes and materials the scene was loaded with.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn to_file(&self, graph: &SceneGraph, camera: &Camera, sun: &DirectionalLight, clear_color: [f32; 3]) -> SceneFile {
+        SceneFile {
+            clear_color,
+            camera: CameraDesc::from_camera(camera),
+            sun: SunDesc::from_light(sun),
+            textures: self.textures.clone(),
+            meshes: self.meshes.clone(),
+            materials: self.materials.clone(),
+            nodes: graph.roots().iter().map(|&root| self.node_desc(graph, root)).collect(),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn node_desc(&self, graph: &SceneGraph, id: NodeId) -> NodeDesc {
+        let node = graph.node(id);
+        let transform = node.transform();
+        let euler = Euler::from(transform.rotation);
+        // Going through a quaternion leaves angles like 19.999998.
+        let degrees = |angle: cgmath::Rad<f32>| (Deg::from(angle).0 * 1e4).round() / 1e4;
+        let mesh = node.mesh.as_ref().map(|mesh| NodeMeshDesc {
+            mesh: name_of(&self.mesh_handles, &mesh.mesh),
+            material: name_of(&self.material_handles, &mesh.material),
+            color: mesh.color,
+        });
+        NodeDesc {
+            name: node.name.clone(),
+            translation: transform.translation.into(),
+            rotation: [degrees(euler.x), degrees(euler.y), degrees(euler.z)],
+            scale: transform.scale.into(),
+            mesh,
+            light: node.light.as_ref().map(LightDesc::from_attachment),
+            camera: node.camera.as_ref().map(CameraDesc::from_camera),
+            children: node.children().iter().map(|&child| self.node_desc(graph, child)).collect(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn name_of<T>(handles: &[(String, Handle<T>)], handle: &Handle<T>) -> String {
+    let (name, _) = handles
+        .iter()
+        .find(|(_, h)| h == handle)
+        .expect("nodes only use assets the scene was loaded with");
+    name.clone()
+}
+
+/// Reads and parses the scene file at `path`, relative to the asset root
+/// natively and to the page on the web.
+pub async fn load(path: &str) -> anyhow::Result<SceneFile> {
+    let bytes = crate::loader::read_bytes(path).await?;
+    let source = String::from_utf8(bytes).with_context(|| format!("reading {}", path))?;
+    SceneFile::parse(&source).with_context(|| format!("loading {}", path))
+}
+
+/// Writes `scene` to `path` relative to the asset root, where [`load`]
+/// reads it from.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save(path: &str, scene: &SceneFile) -> anyhow::Result<()> {
+    let full_path = crate::loader::asset_root().join(path);
+    std::fs::write(&full_path, scene.to_ron()?).with_context(|| format!("writing {}", full_path.display()))
+}
+
+/// A tangent space normal map of `tiles`x`tiles` raised tiles with
+/// bevelled edges.
+fn tile_normal_map(size: u32, tiles: u32) -> image::DynamicImage {
+    let cell = size as f32 / tiles as f32;
+    let bevel = 0.12;
+    let img = image::RgbaImage::from_fn(size, size, |x, y| {
+        let fx = (x as f32 + 0.5) / cell % 1.0;
+        let fy = (y as f32 + 0.5) / cell % 1.0;
+        // Image rows go down, tangent space Y goes up.
+        let (mut nx, mut ny) = (0.0f32, 0.0f32);
+        if fx < bevel {
+            nx = -0.7;
+        } else if fx > 1.0 - bevel {
+            nx = 0.7;
+        }
+        if fy < bevel {
+            ny = 0.7;
+        } else if fy > 1.0 - bevel {
+            ny = -0.7;
+        }
+        let nz = (1.0 - nx * nx - ny * ny).max(0.0).sqrt();
+        let encode = |n: f32| ((n * 0.5 + 0.5) * 255.0).round() as u8;
+        image::Rgba([encode(nx), encode(ny), encode(nz), 255])
+    });
+    image::DynamicImage::ImageRgba8(img)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENE: &str = r#"
+        (
+            camera: (eye: (0.0, 2.0, 5.0), target: (0.0, 0.0, 0.0)),
+            meshes: [(name: "cube", source: Cube(size: 1.0))],
+            materials: [(name: "red", base_color_factor: (0.8, 0.3, 0.3, 1.0))],
+            nodes: [
+                (name: "box", mesh: (mesh: "cube", material: "red")),
+                (
+                    name: "group",
+                    children: [
+                        (name: "lamp", light: Point(color: (1.0, 1.0, 1.0), intensity: 2.0, range: 5.0)),
+                        (name: "moon", mesh: (mesh: "ball", material: "blue")),
+                    ],
+                ),
+            ],
+        )
+    "#;
+
+    /// The graph [`SceneFile::instantiate`] would build, with handles that
+    /// stand in for the meshes and materials.
+    fn without_assets(scene: &SceneFile) -> (SceneGraph, SceneSource) {
+        fn handles<'a, T>(names: impl Iterator<Item = &'a String>) -> Vec<(String, Handle<T>)> {
+            names.enumerate().map(|(i, name)| (name.clone(), Handle::detached(i))).collect()
+        }
+        let source = SceneSource {
+            textures: scene.textures.clone(),
+            meshes: scene.meshes.clone(),
+            materials: scene.materials.clone(),
+            mesh_handles: handles(scene.meshes.iter().map(|mesh| &mesh.name)),
+            material_handles: handles(scene.materials.iter().map(|material| &material.name)),
+        };
+        (scene.build_graph(&source), source)
+    }
+
+    fn assert_same_nodes(saved: &[NodeDesc], loaded: &[NodeDesc]) {
+        assert_eq!(saved.len(), loaded.len());
+        for (saved, loaded) in saved.iter().zip(loaded) {
+            assert_eq!(saved.name, loaded.name);
+            assert_eq!(saved.translation, loaded.translation, "{}", saved.name);
+            for (a, b) in saved.rotation.into_iter().zip(loaded.rotation) {
+                assert!((a - b).abs() < 1e-3, "{} rotated {:?}, not {:?}", saved.name, saved.rotation, loaded.rotation);
+            }
+            assert_eq!(saved.scale, loaded.scale, "{}", saved.name);
+            let names = |node: &NodeDesc| node.mesh.as_ref().map(|mesh| (mesh.mesh.clone(), mesh.material.clone(), mesh.color));
+            assert_eq!(names(saved), names(loaded), "{}", saved.name);
+            assert_eq!(format!("{:?}", saved.light), format!("{:?}", loaded.light), "{}", saved.name);
+            assert_eq!(format!("{:?}", saved.camera), format!("{:?}", loaded.camera), "{}", saved.name);
+            assert_same_nodes(&saved.children, &loaded.children);
+        }
+    }
+
+    fn error(source: &str) -> String {
+        format!("{:#}", SceneFile::parse(source).expect_err("the scene is invalid"))
+    }
+
+    #[test]
+    fn missing_references_say_where_they_are() {
+        let message = error(SCENE);
+        assert!(message.contains(r#"nodes[1].children[1] "moon": mesh "ball" isn't in meshes"#), "{}", message);
+        assert!(message.contains(r#"nodes[1].children[1] "moon": material "blue" isn't in materials"#), "{}", message);
+        assert!(!message.contains("lamp"), "{}", message);
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let source = SCENE.replace(r#"(mesh: "ball", material: "blue")"#, r#"(mesh: "cube", material: "red")"#);
+        SceneFile::parse(&source).unwrap();
+        let source = source.replace(r#"materials: [(name: "red""#, r#"materials: [(name: "red"), (name: "red""#);
+        let message = error(&source);
+        assert!(message.contains(r#"materials[1]: "red" is already used"#), "{}", message);
+    }
+
+    #[test]
+    fn round_trip() {
+        let source = SCENE.replace(r#"(mesh: "ball", material: "blue")"#, r#"(mesh: "cube", material: "red")"#);
+        for source in [source.as_str(), include_str!("../scenes/demo.ron")] {
+            let scene = SceneFile::parse(source).unwrap();
+            let written = scene.to_ron().unwrap();
+            let read_back = SceneFile::parse(&written).unwrap();
+            assert_eq!(read_back.to_ron().unwrap(), written);
+            assert_eq!(read_back.camera.eye, scene.camera.eye);
+            assert_eq!(read_back.materials[0].base_color_factor, scene.materials[0].base_color_factor);
+            assert_eq!(read_back.nodes.len(), scene.nodes.len());
+            assert_eq!(read_back.nodes[1].children.len(), scene.nodes[1].children.len());
+        }
+    }
+
+    #[test]
+    fn saving_the_graph_gives_back_the_file() {
+        let source = SCENE
+            .replace(r#"(mesh: "ball", material: "blue")"#, r#"(mesh: "cube", material: "red")"#)
+            .replace(r#"name: "group","#, r#"name: "group", translation: (1.0, 2.0, 3.0), rotation: (10.0, 20.0, 30.0),"#);
+        for source in [source.as_str(), include_str!("../scenes/demo.ron")] {
+            let scene = SceneFile::parse(source).unwrap();
+            let (graph, scene_source) = without_assets(&scene);
+            let saved = scene_source.to_file(&graph, &scene.camera.camera(), &scene.sun.light(), scene.clear_color);
+            let read_back = SceneFile::parse(&saved.to_ron().unwrap()).unwrap();
+            assert_same_nodes(&read_back.nodes, &scene.nodes);
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, SquareMatrix, Transform, Vector3, Vector4};
+
+use crate::camera::{Camera, OPENGL_TO_WGPU_MATRIX};
+use crate::light::{DirectionalLight, SpotLight, MAX_SPOT_LIGHTS};
+use crate::mesh::{InstanceRaw, MeshVertex};
+use crate::post;
+use crate::uniform::{UniformBuffer, WgslType};
+use crate::wgsl_layout;
+
+pub const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+pub const CASCADE_COUNT: usize = 3;
+/// The sun's cascades come first in the shadow map array, then one layer
+/// per spot light.
+pub const SHADOW_LAYERS: usize = CASCADE_COUNT + MAX_SPOT_LIGHTS;
+
+/// How far behind a cascade, towards the sun, casters are still rendered.
+const CASTER_MARGIN: f32 = 30.0;
+/// Blend between logarithmic (1.0) and uniform (0.0) cascade splits.
+const SPLIT_LAMBDA: f32 = 0.75;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowViewUniform {
+    view_proj: [[f32; 4]; 4],
+}
+
+wgsl_layout!(ShadowViewUniform {
+    view_proj: WgslType::Mat4x4,
+});
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowDebugUniform {
+    layer: u32,
+}
+
+wgsl_layout!(ShadowDebugUniform {
+    layer: WgslType::U32,
+});
+
+/// Depth maps for the sun (cascaded) and for each spot light, all layers
+/// of a single depth texture array.
+pub struct ShadowMaps {
+    /// Cascades cover the view frustum up to this distance from the camera.
+    pub shadow_distance: f32,
+    /// PCF kernel radius in texels, 0 means a single hardware 2x2 tap.
+    pub pcf_radius: u32,
+    /// How far, in world units, receivers are pushed along their normal
+    /// before the lookup, fights acne on surfaces facing away from the light.
+    pub normal_bias: f32,
+    /// Which layer, if any, is drawn in the corner of the screen.
+    pub debug_layer: Option<usize>,
+    cascade_splits: [f32; CASCADE_COUNT],
+    view_projs: [Matrix4<f32>; SHADOW_LAYERS],
+    active_spot_lights: usize,
+    array_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    layer_views: Vec<wgpu::TextureView>,
+    layer_uniforms: Vec<UniformBuffer<ShadowViewUniform>>,
+    pipeline: wgpu::RenderPipeline,
+    debug_pipeline: wgpu::RenderPipeline,
+    debug_uniform: UniformBuffer<ShadowDebugUniform>,
+}
+
+impl ShadowMaps {
+    /// `output_format` is the format of the surface the debug view is
+    /// drawn onto.
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("shadow_maps"),
+            size: wgpu::Extent3d {
+                width: SHADOW_MAP_SIZE,
+                height: SHADOW_MAP_SIZE,
+                depth_or_array_layers: SHADOW_LAYERS as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("shadow_maps_array"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        // Render targets only, see the debug view below.
+        let layer_view = |layer: usize| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("shadow_map_layer"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                aspect: wgpu::TextureAspect::DepthOnly,
+                base_array_layer: layer as u32,
+                array_layer_count: Some(1),
+                ..Default::default()
+            })
+        };
+        let layer_views: Vec<_> = (0..SHADOW_LAYERS).map(layer_view).collect();
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("shadow_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            // Linear filtering on a comparison sampler gives a free 2x2 PCF.
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let layer_uniforms: Vec<_> = (0..SHADOW_LAYERS)
+            .map(|layer| {
+                UniformBuffer::new(
+                    device,
+                    &ShadowViewUniform {
+                        view_proj: Matrix4::identity().into(),
+                    },
+                    wgpu::ShaderStages::VERTEX,
+                    &format!("shadow_view_{}", layer),
+                )
+            })
+            .collect();
+
+        let pipeline = create_shadow_pipeline(device, &layer_uniforms[0].bind_group_layout);
+
+        // The debug view reads the whole array and picks the layer in the
+        // shader, GL can't sample a view of a single layer.
+        let debug_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("shadow_maps_debug"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            aspect: wgpu::TextureAspect::DepthOnly,
+            ..Default::default()
+        });
+        let debug_uniform = UniformBuffer::with_resources(
+            device,
+            &ShadowDebugUniform { layer: 0 },
+            wgpu::ShaderStages::FRAGMENT,
+            &[(
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                wgpu::BindingResource::TextureView(&debug_view),
+            )],
+            "shadow_debug",
+        );
+        let debug_pipeline = post::create_fullscreen_pipeline(
+            device,
+            &[&debug_uniform.bind_group_layout],
+            output_format,
+            wgpu::BlendState::REPLACE,
+            include_str!("shadow_debug.wgsl"),
+            "fs_main",
+            "Shadow Debug Pipeline",
+        );
+
+        Self {
+            shadow_distance: 40.0,
+            pcf_radius: 1,
+            normal_bias: 0.03,
+            debug_layer: None,
+            cascade_splits: [0.0; CASCADE_COUNT],
+            view_projs: [Matrix4::identity(); SHADOW_LAYERS],
+            active_spot_lights: 0,
+            array_view,
+            sampler,
+            layer_views,
+            layer_uniforms,
+            pipeline,
+            debug_pipeline,
+            debug_uniform,
+        }
+    }
+
+    /// Layout entries and resources for sampling the shadow maps, to go in
+    /// the bind group of whatever shader does the lighting.
+    pub fn sampling_resources(
+        &self,
+        first_binding: u32,
+    ) -> [(wgpu::BindGroupLayoutEntry, wgpu::BindingResource<'_>); 2] {
+        [
+            (
+                wgpu::BindGroupLayoutEntry {
+                    binding: first_binding,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindingResource::TextureView(&self.array_view),
+            ),
+            (
+                wgpu::BindGroupLayoutEntry {
+                    binding: first_binding + 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                wgpu::BindingResource::Sampler(&self.sampler),
+            ),
+        ]
+    }
+
+    /// View-space distances at which each cascade ends.
+    pub fn cascade_splits(&self) -> [f32; CASCADE_COUNT] {
+        self.cascade_splits
+    }
+
+    /// The light view-projection rendered into `layer`.
+    pub fn view_proj(&self, layer: usize) -> Matrix4<f32> {
+        self.view_projs[layer]
+    }
+
+    /// Fits the sun's cascades around the camera frustum and points a
+    /// shadow frustum down each spot light.
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        sun: &DirectionalLight,
+        spot_lights: &[SpotLight],
+    ) {
+        self.cascade_splits = split_cascades(camera.znear, self.shadow_distance.min(camera.zfar));
+        let mut near = camera.znear;
+        for (cascade, &split) in self.cascade_splits.iter().enumerate() {
+            self.view_projs[cascade] = cascade_view_proj(camera, near, split, sun.direction.normalize());
+            near = split;
+        }
+
+        self.active_spot_lights = spot_lights.len().min(MAX_SPOT_LIGHTS);
+        for (i, spot) in spot_lights.iter().take(MAX_SPOT_LIGHTS).enumerate() {
+            self.view_projs[CASCADE_COUNT + i] = spot.view_proj();
+        }
+
+        for (uniform, view_proj) in self.layer_uniforms.iter().zip(self.view_projs.iter()) {
+            uniform.update(queue, &ShadowViewUniform {
+                view_proj: (*view_proj).into(),
+            });
+        }
+    }
+
+    /// Renders every active layer. `draw_casters` is called once per layer
+    /// with the depth-only pipeline bound and should bind the vertex and
+    /// instance buffers and issue the draws.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, mut draw_casters: impl FnMut(&mut wgpu::RenderPass)) {
+        for layer in 0..CASCADE_COUNT + self.active_spot_lights {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.layer_views[layer],
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.layer_uniforms[layer].bind_group, &[]);
+            draw_casters(&mut pass);
+        }
+    }
+
+    /// Draws the selected layer into the bottom left corner of `output`.
+    pub fn draw_debug(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        width: u32,
+        height: u32,
+    ) {
+        let Some(layer) = self.debug_layer else {
+            return;
+        };
+        self.debug_uniform.update(queue, &ShadowDebugUniform { layer: layer as u32 });
+
+        let size = (width.min(height) / 3) as f32;
+        let mut pass = post::begin_fullscreen_pass(encoder, output, wgpu::LoadOp::Load, "Shadow Debug Pass");
+        pass.set_viewport(0.0, height as f32 - size, size, size, 0.0, 1.0);
+        pass.set_pipeline(&self.debug_pipeline);
+        pass.set_bind_group(0, &self.debug_uniform.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    /// Steps the debug view through off, every cascade, then every spot
+    /// light in use.
+    pub fn cycle_debug_layer(&mut self) {
+        let layers = CASCADE_COUNT + self.active_spot_lights;
+        self.debug_layer = match self.debug_layer {
+            None => Some(0),
+            Some(layer) if layer + 1 < layers => Some(layer + 1),
+            Some(_) => None,
+        };
+    }
+}
+
+/// Where each cascade between `near` and `far` ends, closer together near
+/// the camera where shadows need more detail.
+fn split_cascades(near: f32, far: f32) -> [f32; CASCADE_COUNT] {
+    std::array::from_fn(|cascade| {
+        let p = (cascade + 1) as f32 / CASCADE_COUNT as f32;
+        let log = near * (far / near).powf(p);
+        let uniform = near + (far - near) * p;
+        SPLIT_LAMBDA * log + (1.0 - SPLIT_LAMBDA) * uniform
+    })
+}
+
+/// A stable orthographic projection around the slice of the camera frustum
+/// between `near` and `far`, looking along `light_direction`.
+fn cascade_view_proj(camera: &Camera, near: f32, far: f32, light_direction: Vector3<f32>) -> Matrix4<f32> {
+    let inv_view = camera.view_matrix().invert().unwrap_or(Matrix4::identity());
+    let tan_half_fovy = Rad::from(cgmath::Deg(camera.fovy * 0.5)).0.tan();
+
+    let mut corners = Vec::with_capacity(8);
+    for z in [near, far] {
+        let h = z * tan_half_fovy;
+        let w = h * camera.aspect;
+        for (x, y) in [(-w, -h), (w, -h), (w, h), (-w, h)] {
+            corners.push(inv_view.transform_point(Point3::new(x, y, -z)));
+        }
+    }
+
+    // A bounding sphere rather than a tight box keeps the projection the same
+    // size as the camera turns, which stops the shadow edges swimming.
+    let center = Point3::centroid(&corners);
+    let radius = corners
+        .iter()
+        .map(|corner| (corner - center).magnitude())
+        .fold(0.0, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let up = if light_direction.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() };
+    let eye = center - light_direction * (radius + CASTER_MARGIN);
+    let view = Matrix4::look_at_rh(eye, center, up);
+    let mut proj = OPENGL_TO_WGPU_MATRIX
+        * cgmath::ortho(-radius, radius, -radius, radius, 0.0, 2.0 * radius + CASTER_MARGIN);
+
+    // Snap the projection to whole shadow map texels.
+    let origin = proj * view * Vector4::new(0.0, 0.0, 0.0, 1.0);
+    let texels = origin.truncate().truncate() * (SHADOW_MAP_SIZE as f32 / 2.0);
+    let offset = (texels.map(f32::round) - texels) * (2.0 / SHADOW_MAP_SIZE as f32);
+    proj.w.x += offset.x;
+    proj.w.y += offset.y;
+
+    proj * view
+}
+
+fn create_shadow_pipeline(device: &wgpu::Device, view_layout: &wgpu::BindGroupLayout) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::include_wgsl!("shadow.wgsl"));
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Shadow Pipeline Layout"),
+        bind_group_layouts: &[view_layout],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Shadow Pipeline"),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[MeshVertex::desc(), InstanceRaw::desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: None,
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: SHADOW_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState {
+                constant: 2,
+                slope_scale: 2.0,
+                clamp: 0.0,
+            },
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cascades_split_closer_near_the_camera() {
+        let splits = split_cascades(0.1, 60.0);
+        assert!((splits[CASCADE_COUNT - 1] - 60.0).abs() < 1e-3);
+        let mut near = 0.1;
+        for split in splits {
+            assert!(split > near);
+            near = split;
+        }
+        // Each cascade covers more than the one before.
+        assert!(splits[0] - 0.1 < splits[1] - splits[0]);
+        assert!(splits[1] - splits[0] < splits[2] - splits[1]);
+    }
+
+    #[test]
+    fn cascade_holds_its_slice_of_the_frustum() {
+        let camera = Camera {
+            eye: Point3::new(0.0, 6.0, 12.0),
+            target: Point3::new(0.0, 1.0, 0.0),
+            up: Vector3::unit_y(),
+            aspect: 16.0 / 9.0,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+        };
+        let light_direction = Vector3::new(-0.4, -1.0, -0.3).normalize();
+        let (near, far) = (5.0, 20.0);
+        let view_proj = cascade_view_proj(&camera, near, far, light_direction);
+
+        let inv_view = camera.view_matrix().invert().unwrap();
+        let tan_half_fovy = Rad::from(cgmath::Deg(camera.fovy * 0.5)).0.tan();
+        for z in [near, (near + far) / 2.0, far] {
+            let h = z * tan_half_fovy;
+            let w = h * camera.aspect;
+            for (x, y) in [(-w, -h), (w, -h), (w, h), (-w, h), (0.0, 0.0)] {
+                let world = inv_view.transform_point(Point3::new(x, y, -z));
+                let clip = view_proj.transform_point(world);
+                assert!(clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0, "{:?} is outside the cascade", clip);
+                assert!((0.0..=1.0).contains(&clip.z), "{:?} is clipped by depth", clip);
+            }
+        }
+
+        // Casters out of view, up to the margin towards the sun, still land
+        // in front of the near plane.
+        let center = inv_view.transform_point(Point3::new(0.0, 0.0, -(near + far) / 2.0));
+        let caster = view_proj.transform_point(center - light_direction * CASTER_MARGIN);
+        assert!(caster.z >= 0.0, "{:?}", caster);
+    }
+}
@@ -0,0 +1,17 @@
+struct ShadowDebug {
+    layer: u32,
+}
+@group(0) @binding(0)
+var<uniform> shadow_debug: ShadowDebug;
+// Bound as plain floats rather than depth, which lets GL fetch single
+// texels from it.
+@group(0) @binding(1)
+var t_shadow: texture_2d_array<f32>;
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_shadow));
+    let texel = vec2<i32>(clamp(in.uv * size, vec2<f32>(0.0), size - 1.0));
+    let depth = textureLoad(t_shadow, texel, shadow_debug.layer, 0).r;
+    return vec4<f32>(vec3<f32>(depth), 1.0);
+}
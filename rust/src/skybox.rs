@@ -0,0 +1,170 @@
+use cgmath::{Matrix4, SquareMatrix, Vector4};
+
+use crate::camera::Camera;
+use crate::texture::Texture;
+use crate::uniform::{UniformBuffer, WgslType};
+use crate::wgsl_layout;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyboxUniform {
+    inv_view_proj: [[f32; 4]; 4],
+}
+
+wgsl_layout!(SkyboxUniform {
+    inv_view_proj: WgslType::Mat4x4,
+});
+
+/// A cube map drawn behind the scene, following the camera's rotation but
+/// not its position.
+pub struct Skybox {
+    uniform: UniformBuffer<SkyboxUniform>,
+    cubemap_layout: wgpu::BindGroupLayout,
+    cubemaps: Vec<(String, wgpu::BindGroup)>,
+    current: usize,
+}
+
+impl Skybox {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let uniform = UniformBuffer::new(
+            device,
+            &SkyboxUniform {
+                inv_view_proj: Matrix4::identity().into(),
+            },
+            wgpu::ShaderStages::FRAGMENT,
+            "skybox_uniform",
+        );
+        let cubemap_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("skybox_cubemap_bind_group_layout"),
+        });
+
+        Self {
+            uniform,
+            cubemap_layout,
+            cubemaps: Vec::new(),
+            current: 0,
+        }
+    }
+
+    pub fn bind_group_layouts(&self) -> [&wgpu::BindGroupLayout; 2] {
+        [&self.uniform.bind_group_layout, &self.cubemap_layout]
+    }
+
+    /// Adds a cube map to choose from, the first one added is shown until
+    /// [`Skybox::next_cubemap`] is called.
+    pub fn add_cubemap(&mut self, device: &wgpu::Device, name: &str, cubemap: &Texture) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.cubemap_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&cubemap.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&cubemap.sampler),
+                },
+            ],
+            label: Some(name),
+        });
+        self.cubemaps.push((name.to_string(), bind_group));
+    }
+
+    /// Switches to the next cube map and returns its name.
+    pub fn next_cubemap(&mut self) -> Option<&str> {
+        if self.cubemaps.is_empty() {
+            return None;
+        }
+        self.current = (self.current + 1) % self.cubemaps.len();
+        Some(&self.cubemaps[self.current].0)
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let mut view = camera.view_matrix();
+        view.w = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        let view_proj = camera.projection_matrix() * view;
+        self.uniform.update(queue, &SkyboxUniform {
+            inv_view_proj: view_proj.invert().unwrap_or(Matrix4::identity()).into(),
+        });
+    }
+
+    /// Draws the sky with the pipeline from [`create_pipeline`], which has to
+    /// be bound already. Draw it after the opaque geometry so the depth test
+    /// skips every covered pixel.
+    pub fn draw(&self, pass: &mut wgpu::RenderPass) {
+        let Some((_, cubemap)) = self.cubemaps.get(self.current) else {
+            return;
+        };
+        pass.set_bind_group(0, &self.uniform.bind_group, &[]);
+        pass.set_bind_group(1, cubemap, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+pub fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::include_wgsl!("skybox.wgsl"));
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Skybox Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: color_format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            cull_mode: None,
+            ..Default::default()
+        },
+        // Sits at depth 1.0, which still passes against the cleared buffer.
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
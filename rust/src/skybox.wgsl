@@ -0,0 +1,36 @@
+struct Skybox {
+    inv_view_proj: mat4x4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> skybox: Skybox;
+@group(1) @binding(0)
+var t_cubemap: texture_cube<f32>;
+@group(1) @binding(1)
+var s_cubemap: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+// A fullscreen triangle on the far plane, so it only shows where nothing
+// else has been drawn.
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    out.ndc = uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+    out.clip_position = vec4<f32>(out.ndc, 1.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // The view has no translation, so the point on the far plane is also
+    // the direction from the eye.
+    let world = skybox.inv_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let dir = world.xyz / world.w;
+    return vec4<f32>(textureSample(t_cubemap, s_cubemap, dir).rgb, 1.0);
+}
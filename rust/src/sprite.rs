@@ -0,0 +1,381 @@
+use cgmath::{Deg, Rad};
+
+use crate::texture::Texture;
+use crate::uniform::{UniformBuffer, WgslType};
+use crate::wgsl_layout;
+
+/// A texture added to a [`SpriteBatch`] with [`SpriteBatch::add_texture`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SpriteTexture(usize);
+
+/// The part of a texture a sprite shows, in texture coordinates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UvRect {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl UvRect {
+    pub const FULL: UvRect = UvRect {
+        min: [0.0, 0.0],
+        max: [1.0, 1.0],
+    };
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Sprite {
+    pub texture: SpriteTexture,
+    /// The centre, in pixels from the top left corner of the window.
+    pub position: [f32; 2],
+    /// Clockwise about the centre.
+    pub rotation: Deg<f32>,
+    /// Multiples of the size in texels of `uv_rect`.
+    pub scale: [f32; 2],
+    /// Multiplies the texture's colour.
+    pub tint: [f32; 4],
+    pub uv_rect: UvRect,
+    /// Sprites with a higher z-order are drawn over those with a lower one.
+    /// Equal ones are drawn in the order they were queued.
+    pub z_order: i32,
+}
+
+impl Sprite {
+    /// The whole of `texture` at its own size, untinted.
+    pub fn new(texture: SpriteTexture, position: [f32; 2]) -> Self {
+        Self {
+            texture,
+            position,
+            rotation: Deg(0.0),
+            scale: [1.0, 1.0],
+            tint: [1.0; 4],
+            uv_rect: UvRect::FULL,
+            z_order: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SpriteVertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    tint: [f32; 4],
+}
+
+impl SpriteVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+            wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<SpriteVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ScreenUniform {
+    size: [f32; 2],
+}
+
+wgsl_layout!(ScreenUniform {
+    size: WgslType::Vec2,
+});
+
+/// A run of quads drawn with one texture.
+struct SpriteDraw {
+    texture: SpriteTexture,
+    indices: std::ops::Range<u32>,
+}
+
+/// Textured quads queued over a frame and drawn over the window in as few
+/// draws as possible: sorted by z-order and then by texture, with one draw
+/// for each run of the same texture.
+pub struct SpriteBatch {
+    texture_layout: wgpu::BindGroupLayout,
+    /// Bind group and size in texels of each added texture.
+    textures: Vec<(wgpu::BindGroup, [f32; 2])>,
+    screen_uniform: UniformBuffer<ScreenUniform>,
+    pipeline: wgpu::RenderPipeline,
+    sprites: Vec<Sprite>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    /// How many quads the buffers have room for.
+    capacity: usize,
+    draws: Vec<SpriteDraw>,
+}
+
+impl SpriteBatch {
+    /// `texture_layout` is a texture and filtering sampler, like the one the
+    /// textured pipeline uses. Sprites are drawn into `output_format`
+    /// targets.
+    pub fn new(
+        device: &wgpu::Device,
+        texture_layout: &wgpu::BindGroupLayout,
+        output_format: wgpu::TextureFormat,
+    ) -> Self {
+        let screen_uniform = UniformBuffer::new(
+            device,
+            &ScreenUniform { size: [1.0, 1.0] },
+            wgpu::ShaderStages::VERTEX,
+            "sprite_screen",
+        );
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sprite Pipeline Layout"),
+            bind_group_layouts: &[&screen_uniform.bind_group_layout, texture_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("sprite.wgsl"));
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sprite Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[SpriteVertex::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: output_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            // Negative scales mirror sprites, which flips their winding.
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let capacity = 64;
+        let (vertex_buffer, index_buffer) = create_buffers(device, capacity);
+        Self {
+            texture_layout: texture_layout.clone(),
+            textures: Vec::new(),
+            screen_uniform,
+            pipeline,
+            sprites: Vec::new(),
+            vertex_buffer,
+            index_buffer,
+            capacity,
+            draws: Vec::new(),
+        }
+    }
+
+    pub fn add_texture(&mut self, device: &wgpu::Device, texture: &Texture, label: &str) -> SpriteTexture {
+        self.textures.push(self.bind_texture(device, texture, label));
+        SpriteTexture(self.textures.len() - 1)
+    }
+
+    /// Swaps what `sprite_texture` shows for `texture`, such as once it has
+    /// finished loading.
+    pub fn set_texture(&mut self, device: &wgpu::Device, sprite_texture: SpriteTexture, texture: &Texture, label: &str) {
+        self.textures[sprite_texture.0] = self.bind_texture(device, texture, label);
+    }
+
+    fn bind_texture(&self, device: &wgpu::Device, texture: &Texture, label: &str) -> (wgpu::BindGroup, [f32; 2]) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some(label),
+        });
+        let size = [texture.texture.width() as f32, texture.texture.height() as f32];
+        (bind_group, size)
+    }
+
+    /// Adds `sprite` to this frame's batch.
+    pub fn queue(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    /// Sorts the queued sprites and streams them to the GPU for a `width` by
+    /// `height` window, ready to draw. The queue is emptied.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.screen_uniform.update(queue, &ScreenUniform {
+            size: [width as f32, height as f32],
+        });
+
+        if self.sprites.len() > self.capacity {
+            self.capacity = self.sprites.len().next_power_of_two();
+            (self.vertex_buffer, self.index_buffer) = create_buffers(device, self.capacity);
+        }
+
+        let vertices;
+        (vertices, self.draws) = batch(&mut self.sprites, |texture| self.textures[texture.0].1);
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+
+    /// Draws the prepared sprites over `output`.
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        if self.draws.is_empty() {
+            return;
+        }
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Sprite Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.screen_uniform.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for draw in &self.draws {
+            pass.set_bind_group(1, &self.textures[draw.texture.0].0, &[]);
+            pass.draw_indexed(draw.indices.clone(), 0, 0..1);
+        }
+    }
+}
+
+/// Sorts and empties `sprites` into their vertices, with one draw for each
+/// run of the same texture. `texture_size` gives a texture's size in texels.
+fn batch(
+    sprites: &mut Vec<Sprite>,
+    texture_size: impl Fn(SpriteTexture) -> [f32; 2],
+) -> (Vec<SpriteVertex>, Vec<SpriteDraw>) {
+    // Stable, so sprites with the same z-order and texture keep their
+    // queued order.
+    sprites.sort_by_key(|sprite| (sprite.z_order, sprite.texture));
+
+    let mut vertices = Vec::with_capacity(sprites.len() * 4);
+    let mut draws: Vec<SpriteDraw> = Vec::new();
+    for (i, sprite) in sprites.drain(..).enumerate() {
+        vertices.extend(quad(&sprite, texture_size(sprite.texture)));
+
+        let quad_indices = i as u32 * 6..(i as u32 + 1) * 6;
+        match draws.last_mut() {
+            Some(draw) if draw.texture == sprite.texture => draw.indices.end = quad_indices.end,
+            _ => draws.push(SpriteDraw {
+                texture: sprite.texture,
+                indices: quad_indices,
+            }),
+        }
+    }
+    (vertices, draws)
+}
+
+/// The corners of `sprite`, clockwise from the top left, for a texture
+/// `texture_size` texels across.
+fn quad(sprite: &Sprite, texture_size: [f32; 2]) -> [SpriteVertex; 4] {
+    let UvRect { min, max } = sprite.uv_rect;
+    let half_width = (max[0] - min[0]) * texture_size[0] * sprite.scale[0] * 0.5;
+    let half_height = (max[1] - min[1]) * texture_size[1] * sprite.scale[1] * 0.5;
+    let (sin, cos) = Rad::from(sprite.rotation).0.sin_cos();
+    let corners = [
+        ([-half_width, -half_height], [min[0], min[1]]),
+        ([half_width, -half_height], [max[0], min[1]]),
+        ([half_width, half_height], [max[0], max[1]]),
+        ([-half_width, half_height], [min[0], max[1]]),
+    ];
+    // With y pointing down, this rotation turns clockwise on screen.
+    corners.map(|([x, y], tex_coords)| SpriteVertex {
+        position: [
+            sprite.position[0] + x * cos - y * sin,
+            sprite.position[1] + x * sin + y * cos,
+        ],
+        tex_coords,
+        tint: sprite.tint,
+    })
+}
+
+fn create_buffers(device: &wgpu::Device, capacity: usize) -> (wgpu::Buffer, wgpu::Buffer) {
+    use wgpu::util::DeviceExt;
+
+    let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("sprite_vertex_buffer"),
+        size: (capacity * 4 * std::mem::size_of::<SpriteVertex>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    // Every quad is indexed the same way, so the indices only change when
+    // the buffers grow.
+    let indices: Vec<u32> = (0..capacity as u32)
+        .flat_map(|quad| [0, 1, 2, 0, 2, 3].map(|i| quad * 4 + i))
+        .collect();
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("sprite_index_buffer"),
+        contents: bytemuck::cast_slice(&indices),
+        usage: wgpu::BufferUsages::INDEX,
+    });
+    (vertex_buffer, index_buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprite(texture: usize, z_order: i32, x: f32) -> Sprite {
+        Sprite {
+            z_order,
+            ..Sprite::new(SpriteTexture(texture), [x, 0.0])
+        }
+    }
+
+    #[test]
+    fn sorted_by_z_order_then_texture() {
+        let mut sprites = vec![
+            sprite(1, 0, 0.0),
+            sprite(0, 1, 1.0),
+            sprite(0, 0, 2.0),
+            sprite(1, 0, 3.0),
+            sprite(0, 0, 4.0),
+            sprite(1, -1, 5.0),
+        ];
+        let (vertices, draws) = batch(&mut sprites, |_| [2.0, 2.0]);
+        assert!(sprites.is_empty());
+
+        let draws: Vec<_> = draws.iter().map(|draw| (draw.texture.0, draw.indices.clone())).collect();
+        assert_eq!(draws, [(1, 0..6), (0, 6..18), (1, 18..30), (0, 30..36)]);
+        // Ties keep the order they were queued in.
+        let centres: Vec<_> = vertices.chunks(4).map(|quad| (quad[0].position[0] + quad[2].position[0]) / 2.0).collect();
+        assert_eq!(centres, [5.0, 2.0, 4.0, 0.0, 3.0, 1.0]);
+    }
+
+    #[test]
+    fn quads_are_sized_by_their_texture_and_uv_rect() {
+        let half = Sprite {
+            scale: [2.0, 1.0],
+            uv_rect: UvRect {
+                min: [0.0, 0.5],
+                max: [0.5, 1.0],
+            },
+            ..Sprite::new(SpriteTexture(0), [100.0, 50.0])
+        };
+        let corners = quad(&half, [64.0, 32.0]).map(|vertex| (vertex.position, vertex.tex_coords));
+        assert_eq!(corners, [
+            ([68.0, 42.0], [0.0, 0.5]),
+            ([132.0, 42.0], [0.5, 0.5]),
+            ([132.0, 58.0], [0.5, 1.0]),
+            ([68.0, 58.0], [0.0, 1.0]),
+        ]);
+    }
+}
@@ -0,0 +1,277 @@
+use cgmath::{InnerSpace, Vector3};
+
+use crate::camera::CAMERA_WGSL;
+use crate::post;
+use crate::texture::Texture;
+use crate::uniform::{UniformBuffer, WgslType};
+use crate::wgsl_layout;
+
+/// Must match `MAX_KERNEL_SIZE` in ssao.wgsl.
+pub const MAX_KERNEL_SIZE: usize = 64;
+
+const OCCLUSION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// How the ambient occlusion is sampled and smoothed.
+#[derive(Copy, Clone, Debug)]
+pub struct SsaoSettings {
+    /// Samples taken per pixel, at most [`MAX_KERNEL_SIZE`].
+    pub kernel_size: u32,
+    /// World space radius of the hemisphere that is sampled.
+    pub radius: f32,
+    /// How much closer a surface has to be than a sample to occlude it,
+    /// which keeps flat surfaces from occluding themselves.
+    pub bias: f32,
+    /// Exponent applied to the result, higher is darker.
+    pub power: f32,
+    /// Half width of the box blur in texels, 0 turns the blur off.
+    pub blur_radius: u32,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            kernel_size: 32,
+            radius: 0.5,
+            bias: 0.025,
+            power: 1.5,
+            blur_radius: 2,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    kernel: [[f32; 4]; MAX_KERNEL_SIZE],
+    kernel_size: u32,
+    radius: f32,
+    bias: f32,
+    power: f32,
+}
+
+wgsl_layout!(SsaoUniform {
+    kernel: WgslType::Array(&WgslType::Vec4, MAX_KERNEL_SIZE),
+    kernel_size: WgslType::U32,
+    radius: WgslType::F32,
+    bias: WgslType::F32,
+    power: WgslType::F32,
+});
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BlurUniform {
+    radius: i32,
+}
+
+wgsl_layout!(BlurUniform {
+    radius: WgslType::I32,
+});
+
+/// Screen space ambient occlusion from a G-buffer's normals and depth. The
+/// result, 1 where nothing occludes, is meant to scale ambient light only.
+pub struct Ssao {
+    pub enabled: bool,
+    pub settings: SsaoSettings,
+    occlusion: Texture,
+    blurred: Texture,
+    uniform: UniformBuffer<SsaoUniform>,
+    blur_uniform: UniformBuffer<BlurUniform>,
+    pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+}
+
+impl Ssao {
+    /// `normal` holds world space normals as n * 0.5 + 0.5 and `depth` the
+    /// depth buffer they were drawn with, both `width` by `height`.
+    pub fn new(
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+        normal: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+    ) -> Self {
+        let (occlusion, blurred) = create_targets(device, width, height);
+        let uniform = UniformBuffer::with_resources(
+            device,
+            &bytemuck::Zeroable::zeroed(),
+            wgpu::ShaderStages::FRAGMENT,
+            &[
+                (texture_entry(1), wgpu::BindingResource::TextureView(normal)),
+                (texture_entry(2), wgpu::BindingResource::TextureView(depth)),
+            ],
+            "ssao",
+        );
+        let blur_uniform = UniformBuffer::with_resources(
+            device,
+            &bytemuck::Zeroable::zeroed(),
+            wgpu::ShaderStages::FRAGMENT,
+            &[(texture_entry(1), wgpu::BindingResource::TextureView(&occlusion.view))],
+            "ssao_blur",
+        );
+
+        let pipeline = post::create_fullscreen_pipeline(
+            device,
+            &[camera_layout, &uniform.bind_group_layout],
+            OCCLUSION_FORMAT,
+            wgpu::BlendState::REPLACE,
+            &format!("{}\n{}", CAMERA_WGSL, include_str!("ssao.wgsl")),
+            "fs_main",
+            "SSAO Pipeline",
+        );
+        let blur_pipeline = post::create_fullscreen_pipeline(
+            device,
+            &[&blur_uniform.bind_group_layout],
+            OCCLUSION_FORMAT,
+            wgpu::BlendState::REPLACE,
+            include_str!("ssao_blur.wgsl"),
+            "fs_main",
+            "SSAO Blur Pipeline",
+        );
+
+        Self {
+            enabled: true,
+            settings: SsaoSettings::default(),
+            occlusion,
+            blurred,
+            uniform,
+            blur_uniform,
+            pipeline,
+            blur_pipeline,
+        }
+    }
+
+    /// Recreates the targets for new G-buffer textures.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        normal: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+    ) {
+        (self.occlusion, self.blurred) = create_targets(device, width, height);
+        let layout = self.uniform.bind_group_layout.clone();
+        self.uniform = UniformBuffer::with_layout(
+            device,
+            &bytemuck::Zeroable::zeroed(),
+            &layout,
+            &[
+                (1, wgpu::BindingResource::TextureView(normal)),
+                (2, wgpu::BindingResource::TextureView(depth)),
+            ],
+            "ssao",
+        );
+        let blur_layout = self.blur_uniform.bind_group_layout.clone();
+        self.blur_uniform = UniformBuffer::with_layout(
+            device,
+            &bytemuck::Zeroable::zeroed(),
+            &blur_layout,
+            &[(1, wgpu::BindingResource::TextureView(&self.occlusion.view))],
+            "ssao_blur",
+        );
+    }
+
+    /// The ambient occlusion [`Ssao::run`] writes, all white while disabled.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.blurred.view
+    }
+
+    /// Works out the occlusion for the G-buffer as seen through `camera`.
+    pub fn run(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, camera: &wgpu::BindGroup) {
+        if !self.enabled {
+            post::begin_fullscreen_pass(
+                encoder,
+                &self.blurred.view,
+                wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                "SSAO Clear Pass",
+            );
+            return;
+        }
+
+        let settings = &self.settings;
+        let kernel_size = settings.kernel_size.clamp(1, MAX_KERNEL_SIZE as u32);
+        let mut raw = SsaoUniform {
+            kernel: [[0.0; 4]; MAX_KERNEL_SIZE],
+            kernel_size,
+            radius: settings.radius,
+            bias: settings.bias,
+            power: settings.power,
+        };
+        for (i, sample) in raw.kernel.iter_mut().take(kernel_size as usize).enumerate() {
+            *sample = kernel_sample(i as u32, kernel_size).extend(0.0).into();
+        }
+        self.uniform.update(queue, &raw);
+        self.blur_uniform.update(queue, &BlurUniform {
+            radius: settings.blur_radius as i32,
+        });
+
+        {
+            let mut pass = post::begin_fullscreen_pass(
+                encoder,
+                &self.occlusion.view,
+                wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                "SSAO Pass",
+            );
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, camera, &[]);
+            pass.set_bind_group(1, &self.uniform.bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        let mut pass = post::begin_fullscreen_pass(
+            encoder,
+            &self.blurred.view,
+            wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+            "SSAO Blur Pass",
+        );
+        pass.set_pipeline(&self.blur_pipeline);
+        pass.set_bind_group(0, &self.blur_uniform.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn texture_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+        },
+        count: None,
+    }
+}
+
+fn create_targets(device: &wgpu::Device, width: u32, height: u32) -> (Texture, Texture) {
+    (
+        Texture::create_render_target(device, width, height, OCCLUSION_FORMAT, "ssao_occlusion"),
+        Texture::create_render_target(device, width, height, OCCLUSION_FORMAT, "ssao_blurred"),
+    )
+}
+
+/// The `index`th of `count` points in the unit hemisphere around +z, spread
+/// with a Halton sequence and packed towards the centre, where occluders
+/// matter most.
+fn kernel_sample(index: u32, count: u32) -> Vector3<f32> {
+    let direction = Vector3::new(
+        halton(index + 1, 2) * 2.0 - 1.0,
+        halton(index + 1, 3) * 2.0 - 1.0,
+        halton(index + 1, 5).max(0.05),
+    )
+    .normalize();
+    let t = index as f32 / count as f32;
+    let scale = 0.1 + 0.9 * t * t;
+    direction * halton(index + 1, 7).max(0.1) * scale
+}
+
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
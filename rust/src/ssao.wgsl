@@ -0,0 +1,70 @@
+const MAX_KERNEL_SIZE: u32 = 64u;
+
+struct Ssao {
+    kernel: array<vec4<f32>, MAX_KERNEL_SIZE>,
+    kernel_size: u32,
+    radius: f32,
+    bias: f32,
+    power: f32,
+}
+@group(1) @binding(0)
+var<uniform> ssao: Ssao;
+@group(1) @binding(1)
+var t_normal: texture_2d<f32>;
+@group(1) @binding(2)
+var t_depth: texture_2d<f32>;
+
+const PI: f32 = 3.14159265359;
+
+// A different angle for every pixel in a 4x4 block, so neighbouring pixels
+// sample along different directions and the blur averages them out.
+fn noise_angle(coords: vec2<i32>) -> f32 {
+    var bayer = array<f32, 16>(0.0, 8.0, 2.0, 10.0, 12.0, 4.0, 14.0, 6.0, 3.0, 11.0, 1.0, 9.0, 15.0, 7.0, 13.0, 5.0);
+    let index = u32(coords.y & 3) * 4u + u32(coords.x & 3);
+    return bayer[index] / 16.0 * 2.0 * PI;
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(in.clip_position.xy);
+    let depth = textureLoad(t_depth, coords, 0).r;
+    if (depth >= 1.0) {
+        return vec4<f32>(1.0);
+    }
+    let position = world_position_at(in.uv, depth);
+    let view_depth = view_depth_of(position);
+    let n = normalize(textureLoad(t_normal, coords, 0).xyz * 2.0 - vec3<f32>(1.0));
+
+    // The kernel is a hemisphere around +z, turned by the noise angle about
+    // the normal.
+    let helper = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(n.y) > 0.99);
+    let t0 = normalize(cross(helper, n));
+    let b0 = cross(n, t0);
+    let angle = noise_angle(coords);
+    let t = t0 * cos(angle) + b0 * sin(angle);
+    let tbn = mat3x3<f32>(t, cross(n, t), n);
+
+    let size = vec2<f32>(textureDimensions(t_depth));
+    let last = vec2<i32>(textureDimensions(t_depth)) - vec2<i32>(1);
+    var occlusion = 0.0;
+    for (var i = 0u; i < ssao.kernel_size; i += 1u) {
+        let sample_position = position + tbn * ssao.kernel[i].xyz * ssao.radius;
+        let clip = camera.view_proj * vec4<f32>(sample_position, 1.0);
+        let ndc = clip.xy / clip.w;
+        if (any(abs(ndc) > vec2<f32>(1.0))) {
+            continue;
+        }
+        let uv = ndc * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5);
+        // A uv of exactly 1.0 would land one texel past the edge.
+        let scene_depth = textureLoad(t_depth, clamp(vec2<i32>(uv * size), vec2<i32>(0), last), 0).r;
+        let scene_view_depth = view_depth_of(world_position_at(uv, scene_depth));
+        // Whatever is much closer than the radius is a different object and
+        // shouldn't darken this one.
+        let range = smoothstep(0.0, 1.0, ssao.radius / abs(view_depth - scene_view_depth));
+        if (scene_view_depth <= view_depth_of(sample_position) - ssao.bias) {
+            occlusion += range;
+        }
+    }
+    let visibility = 1.0 - occlusion / f32(max(ssao.kernel_size, 1u));
+    return vec4<f32>(pow(visibility, ssao.power));
+}
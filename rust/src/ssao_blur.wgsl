@@ -0,0 +1,23 @@
+struct Blur {
+    radius: i32,
+}
+@group(0) @binding(0)
+var<uniform> blur: Blur;
+@group(0) @binding(1)
+var t_occlusion: texture_2d<f32>;
+
+// A box blur over (2 * radius + 1)^2 texels, which is enough to hide the
+// 4x4 noise pattern at a radius of 2.
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(in.clip_position.xy);
+    let last = vec2<i32>(textureDimensions(t_occlusion)) - vec2<i32>(1);
+    var sum = 0.0;
+    for (var y = -blur.radius; y <= blur.radius; y += 1) {
+        for (var x = -blur.radius; x <= blur.radius; x += 1) {
+            sum += textureLoad(t_occlusion, clamp(coords + vec2<i32>(x, y), vec2<i32>(0), last), 0).r;
+        }
+    }
+    let taps = f32((2 * blur.radius + 1) * (2 * blur.radius + 1));
+    return vec4<f32>(sum / taps);
+}
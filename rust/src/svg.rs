@@ -0,0 +1,467 @@
+//! Reads the part of SVG that simple icons and drawings use: paths and the
+//! basic shapes, solid fills and strokes, groups and transforms. Gradients,
+//! patterns, text, `use`, clipping, masks and opacity are skipped, shapes
+//! painted with them are drawn without that paint.
+
+use std::str::FromStr;
+
+use anyhow::Context;
+use cgmath::Deg;
+use svgtypes::{Length, LengthUnit, SimplePathSegment, SimplifyingPathParser};
+
+use crate::vector::{FillRule, Geometry, LineCap, LineJoin, Path, Stroke};
+
+/// How far apart in the drawing's coordinates curves can be from the
+/// straight lines they're drawn with, as a fraction of the view box size.
+const TOLERANCE: f32 = 1.0 / 2000.0;
+/// Later shapes are drawn this much nearer than earlier ones, so they end
+/// up on top with the depth test.
+const DEPTH_STEP: f32 = 1e-5;
+
+#[derive(Clone, Debug)]
+pub struct Drawing {
+    /// The part of the drawing's coordinates to show, as x, y, width and
+    /// height with y going down.
+    pub view_box: [f32; 4],
+    /// In painting order, each over the ones before.
+    pub shapes: Vec<Shape>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Shape {
+    pub path: Path,
+    /// Takes the path into the drawing's coordinates, as the `a` to `f` of
+    /// SVG's `matrix()`.
+    pub transform: [f32; 6],
+    pub fill: Option<([u8; 4], FillRule)>,
+    pub stroke: Option<([u8; 4], Stroke)>,
+}
+
+/// Where the texture coordinates of a drawing's geometry point.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Paint {
+    /// At the shape's colour in [`Drawing::palette`].
+    Colours,
+    /// Across the view box, the whole texture covering the whole drawing.
+    Texture,
+}
+
+impl Drawing {
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        let document = roxmltree::Document::parse(source)?;
+        let root = document.root_element();
+        if root.tag_name().name() != "svg" {
+            anyhow::bail!("the root element is <{}> rather than <svg>", root.tag_name().name());
+        }
+
+        let view_box = match root.attribute("viewBox") {
+            Some(view_box) => {
+                let view_box = svgtypes::ViewBox::from_str(view_box).context("parsing viewBox")?;
+                [view_box.x, view_box.y, view_box.w, view_box.h].map(|v| v as f32)
+            }
+            None => match (root.attribute("width"), root.attribute("height")) {
+                (Some(_), Some(_)) => [0.0, 0.0, length(root, "width")?, length(root, "height")?],
+                _ => anyhow::bail!("the <svg> needs a viewBox or a width and height"),
+            },
+        };
+        if !(view_box[2] > 0.0 && view_box[3] > 0.0) {
+            anyhow::bail!("the view box {:?} has no area", view_box);
+        }
+
+        let mut shapes = Vec::new();
+        add_children(root, &Style::default(), &mut shapes)?;
+        Ok(Self { view_box, shapes })
+    }
+
+    /// One pixel for each colour the shapes are painted with, for
+    /// [`Paint::Colours`].
+    pub fn palette(&self) -> image::RgbaImage {
+        let colours = self.colours();
+        image::RgbaImage::from_fn(colours.len().max(1) as u32, 1, |x, _| {
+            image::Rgba(colours.get(x as usize).copied().unwrap_or([0; 4]))
+        })
+    }
+
+    fn colours(&self) -> Vec<[u8; 4]> {
+        let mut colours = Vec::new();
+        for shape in &self.shapes {
+            for colour in [shape.fill.map(|(colour, _)| colour), shape.stroke.map(|(colour, _)| colour)].into_iter().flatten() {
+                if !colours.contains(&colour) {
+                    colours.push(colour);
+                }
+            }
+        }
+        colours
+    }
+
+    /// The drawing tessellated, fit into the rectangle from `min` to `max`
+    /// in the XY plane, y up, keeping its aspect ratio and centred as SVG
+    /// does by default. Everything is within a hair of z = 0, later shapes
+    /// nearer.
+    pub fn geometry(&self, min: [f32; 2], max: [f32; 2], paint: Paint) -> anyhow::Result<Geometry> {
+        let [x, y, width, height] = self.view_box;
+        let scale = ((max[0] - min[0]) / width).min((max[1] - min[1]) / height);
+        let offset = [
+            (min[0] + max[0] - width * scale) * 0.5,
+            (min[1] + max[1] + height * scale) * 0.5,
+        ];
+        let colours = self.colours();
+        let layers = self.shapes.iter().map(|shape| shape.fill.is_some() as usize + shape.stroke.is_some() as usize).sum::<usize>();
+
+        let mut geometry = Geometry::default();
+        let mut layer = 0;
+        for (i, shape) in self.shapes.iter().enumerate() {
+            let [a, b, c, d, e, f] = shape.transform;
+            let to_drawing = |p: [f32; 2]| [a * p[0] + c * p[1] + e, b * p[0] + d * p[1] + f];
+            // Flattening in the path's own coordinates, so tighter the more
+            // it's scaled up.
+            let path_scale = (a * d - b * c).abs().sqrt().max(f32::EPSILON);
+            let tolerance = TOLERANCE * width.max(height) / path_scale;
+
+            let painted = [
+                shape.fill.map(|(colour, rule)| (colour, shape.path.fill(rule, tolerance))),
+                shape.stroke.map(|(colour, stroke)| (colour, shape.path.stroke(&stroke, tolerance))),
+            ];
+            for (colour, tessellation) in painted.into_iter().flatten() {
+                let tessellation = tessellation.with_context(|| format!("tessellating shape {}", i))?;
+                let z = (layers - layer) as f32 * DEPTH_STEP;
+                layer += 1;
+                let texel = colours.iter().position(|&c| c == colour).unwrap_or(0);
+                geometry.push(
+                    &tessellation,
+                    |p| {
+                        let p = to_drawing(p);
+                        [offset[0] + (p[0] - x) * scale, offset[1] - (p[1] - y) * scale, z]
+                    },
+                    |p| match paint {
+                        Paint::Colours => [(texel as f32 + 0.5) / colours.len().max(1) as f32, 0.5],
+                        Paint::Texture => {
+                            let p = to_drawing(p);
+                            [(p[0] - x) / width, (p[1] - y) / height]
+                        }
+                    },
+                )?;
+            }
+        }
+        Ok(geometry)
+    }
+}
+
+/// Reads and parses the SVG file at `path`, relative to the asset root
+/// natively and to the page on the web.
+pub async fn load(path: &str) -> anyhow::Result<Drawing> {
+    let bytes = crate::loader::read_bytes(path).await?;
+    let source = String::from_utf8(bytes).with_context(|| format!("reading {}", path))?;
+    Drawing::parse(&source).with_context(|| format!("loading {}", path))
+}
+
+/// The properties a shape gets from its ancestors unless it sets them.
+#[derive(Clone, Debug)]
+struct Style {
+    transform: [f32; 6],
+    /// `currentColor`.
+    color: [u8; 4],
+    fill: Option<[u8; 4]>,
+    fill_rule: FillRule,
+    stroke: Option<[u8; 4]>,
+    stroke_style: Stroke,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            transform: [1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+            color: [0, 0, 0, 255],
+            fill: Some([0, 0, 0, 255]),
+            fill_rule: FillRule::NonZero,
+            stroke: None,
+            stroke_style: Stroke::default(),
+        }
+    }
+}
+
+fn add_children(node: roxmltree::Node, parent: &Style, shapes: &mut Vec<Shape>) -> anyhow::Result<()> {
+    for child in node.children().filter(|child| child.is_element()) {
+        add_element(child, parent, shapes).with_context(|| {
+            let position = child.document().text_pos_at(child.range().start);
+            format!("<{}> at line {}", child.tag_name().name(), position.row)
+        })?;
+    }
+    Ok(())
+}
+
+fn add_element(node: roxmltree::Node, parent: &Style, shapes: &mut Vec<Shape>) -> anyhow::Result<()> {
+    let name = node.tag_name().name();
+    match name {
+        "defs" | "title" | "desc" | "metadata" | "style" => return Ok(()),
+        "g" | "svg" | "a" | "switch" | "path" | "rect" | "circle" | "ellipse" | "line" | "polyline" | "polygon" => {}
+        _ => {
+            log::warn!("Skipping unsupported SVG element <{}>", name);
+            return Ok(());
+        }
+    }
+    if node.attribute("display") == Some("none") {
+        return Ok(());
+    }
+    let style = style(node, parent)?;
+
+    let mut path = Path::new();
+    match name {
+        "path" => {
+            for segment in SimplifyingPathParser::from(node.attribute("d").unwrap_or_default()) {
+                let p = |x: f64, y: f64| [x as f32, y as f32];
+                // Like browsers, draw what came before an error.
+                match segment {
+                    Ok(SimplePathSegment::MoveTo { x, y }) => path.move_to(p(x, y)),
+                    Ok(SimplePathSegment::LineTo { x, y }) => path.line_to(p(x, y)),
+                    Ok(SimplePathSegment::Quadratic { x1, y1, x, y }) => path.quadratic_to(p(x1, y1), p(x, y)),
+                    Ok(SimplePathSegment::CurveTo { x1, y1, x2, y2, x, y }) => {
+                        path.cubic_to(p(x1, y1), p(x2, y2), p(x, y))
+                    }
+                    Ok(SimplePathSegment::ClosePath) => path.close(),
+                    Err(e) => {
+                        log::warn!("Path data stops early: {}", e);
+                        break;
+                    }
+                };
+            }
+        }
+        "rect" => {
+            let (x, y) = (length(node, "x")?, length(node, "y")?);
+            let (width, height) = (length(node, "width")?, length(node, "height")?);
+            // A missing radius is the same as the other one.
+            let (rx, ry) = match (node.attribute("rx"), node.attribute("ry")) {
+                (None, None) => (0.0, 0.0),
+                (Some(_), None) => (length(node, "rx")?, length(node, "rx")?),
+                (None, Some(_)) => (length(node, "ry")?, length(node, "ry")?),
+                (Some(_), Some(_)) => (length(node, "rx")?, length(node, "ry")?),
+            };
+            let (rx, ry) = (rx.min(width / 2.0), ry.min(height / 2.0));
+            if width > 0.0 && height > 0.0 {
+                let radii = [rx, ry];
+                path.move_to([x + rx, y]).line_to([x + width - rx, y]);
+                if rx > 0.0 && ry > 0.0 {
+                    path.arc_to(radii, Deg(0.0), false, true, [x + width, y + ry]);
+                }
+                path.line_to([x + width, y + height - ry]);
+                if rx > 0.0 && ry > 0.0 {
+                    path.arc_to(radii, Deg(0.0), false, true, [x + width - rx, y + height]);
+                }
+                path.line_to([x + rx, y + height]);
+                if rx > 0.0 && ry > 0.0 {
+                    path.arc_to(radii, Deg(0.0), false, true, [x, y + height - ry]);
+                }
+                path.line_to([x, y + ry]);
+                if rx > 0.0 && ry > 0.0 {
+                    path.arc_to(radii, Deg(0.0), false, true, [x + rx, y]);
+                }
+                path.close();
+            }
+        }
+        "circle" | "ellipse" => {
+            let (cx, cy) = (length(node, "cx")?, length(node, "cy")?);
+            let radii = if name == "circle" {
+                [length(node, "r")?; 2]
+            } else {
+                [length(node, "rx")?, length(node, "ry")?]
+            };
+            if radii[0] > 0.0 && radii[1] > 0.0 {
+                path.move_to([cx + radii[0], cy])
+                    .arc_to(radii, Deg(0.0), false, true, [cx - radii[0], cy])
+                    .arc_to(radii, Deg(0.0), false, true, [cx + radii[0], cy])
+                    .close();
+            }
+        }
+        "line" => {
+            path.move_to([length(node, "x1")?, length(node, "y1")?])
+                .line_to([length(node, "x2")?, length(node, "y2")?]);
+        }
+        "polyline" | "polygon" => {
+            let mut points = svgtypes::PointsParser::from(node.attribute("points").unwrap_or_default());
+            if let Some((x, y)) = points.next() {
+                path.move_to([x as f32, y as f32]);
+                for (x, y) in points {
+                    path.line_to([x as f32, y as f32]);
+                }
+                if name == "polygon" {
+                    path.close();
+                }
+            }
+        }
+        _ => return add_children(node, &style, shapes),
+    }
+
+    if !path.is_empty() && (style.fill.is_some() || style.stroke.is_some()) {
+        shapes.push(Shape {
+            path,
+            transform: style.transform,
+            // Lines have no inside.
+            fill: style.fill.filter(|_| name != "line").map(|colour| (colour, style.fill_rule)),
+            stroke: style
+                .stroke
+                .filter(|_| style.stroke_style.width > 0.0)
+                .map(|colour| (colour, style.stroke_style)),
+        });
+    }
+    Ok(())
+}
+
+/// `node`'s style, from its presentation attributes and then its `style`
+/// attribute, inheriting the rest from `parent`.
+fn style(node: roxmltree::Node, parent: &Style) -> anyhow::Result<Style> {
+    let mut style = parent.clone();
+    if let Some(transform) = node.attribute("transform") {
+        let t = svgtypes::Transform::from_str(transform).context("parsing transform")?;
+        let [a, b, c, d, e, f] = parent.transform;
+        let [ta, tb, tc, td, te, tf] = [t.a, t.b, t.c, t.d, t.e, t.f].map(|v| v as f32);
+        style.transform = [
+            a * ta + c * tb,
+            b * ta + d * tb,
+            a * tc + c * td,
+            b * tc + d * td,
+            a * te + c * tf + e,
+            b * te + d * tf + f,
+        ];
+    }
+
+    let attributes = node.attributes().map(|attribute| (attribute.name(), attribute.value()));
+    let declarations = node.attribute("style").unwrap_or_default().split(';').filter_map(|declaration| {
+        let (name, value) = declaration.split_once(':')?;
+        Some((name.trim(), value.trim()))
+    });
+    // `color` first, as `currentColor` anywhere else means its new value.
+    let properties: Vec<(&str, &str)> = attributes.chain(declarations).filter(|&(_, value)| value != "inherit").collect();
+    for &(_, value) in properties.iter().filter(|&&(name, _)| name == "color") {
+        let colour = svgtypes::Color::from_str(value).with_context(|| format!("parsing color {:?}", value))?;
+        style.color = [colour.red, colour.green, colour.blue, colour.alpha];
+    }
+
+    for (name, value) in properties {
+        let number = || value.parse::<f32>().with_context(|| format!("parsing {} {:?}", name, value));
+        match name {
+            "fill" => style.fill = paint(value, style.color)?,
+            "stroke" => style.stroke = paint(value, style.color)?,
+            "fill-rule" => {
+                style.fill_rule = match value {
+                    "evenodd" => FillRule::EvenOdd,
+                    _ => FillRule::NonZero,
+                }
+            }
+            "stroke-width" => {
+                let width = Length::from_str(value).with_context(|| format!("parsing stroke-width {:?}", value))?;
+                style.stroke_style.width = to_user_units(width)?;
+            }
+            "stroke-linejoin" => {
+                style.stroke_style.join = match value {
+                    "round" => LineJoin::Round,
+                    "bevel" => LineJoin::Bevel,
+                    _ => LineJoin::Miter,
+                }
+            }
+            "stroke-linecap" => {
+                style.stroke_style.cap = match value {
+                    "round" => LineCap::Round,
+                    "square" => LineCap::Square,
+                    _ => LineCap::Butt,
+                }
+            }
+            "stroke-miterlimit" => style.stroke_style.miter_limit = number()?,
+            _ => {}
+        }
+    }
+    Ok(style)
+}
+
+/// The colour of a `fill` or `stroke`, `None` if it's not painted.
+fn paint(value: &str, current_color: [u8; 4]) -> anyhow::Result<Option<[u8; 4]>> {
+    let colour = |colour: svgtypes::Color| Some([colour.red, colour.green, colour.blue, colour.alpha]);
+    Ok(match svgtypes::Paint::from_str(value).with_context(|| format!("parsing paint {:?}", value))? {
+        svgtypes::Paint::Color(c) => colour(c),
+        svgtypes::Paint::CurrentColor => Some(current_color),
+        svgtypes::Paint::FuncIRI(_, Some(svgtypes::PaintFallback::Color(c))) => colour(c),
+        svgtypes::Paint::FuncIRI(_, Some(svgtypes::PaintFallback::CurrentColor)) => Some(current_color),
+        svgtypes::Paint::FuncIRI(iri, _) => {
+            log::warn!("Not painting with unsupported paint server {}", iri);
+            None
+        }
+        _ => None,
+    })
+}
+
+/// The length in `node`'s `name` attribute, 0 if it's missing.
+fn length(node: roxmltree::Node, name: &str) -> anyhow::Result<f32> {
+    let Some(value) = node.attribute(name) else {
+        return Ok(0.0);
+    };
+    let length = Length::from_str(value).with_context(|| format!("parsing {} {:?}", name, value))?;
+    to_user_units(length).with_context(|| format!("in {}", name))
+}
+
+fn to_user_units(length: Length) -> anyhow::Result<f32> {
+    // At 96 pixels an inch.
+    let scale = match length.unit {
+        LengthUnit::None | LengthUnit::Px => 1.0,
+        LengthUnit::In => 96.0,
+        LengthUnit::Cm => 96.0 / 2.54,
+        LengthUnit::Mm => 9.6 / 2.54,
+        LengthUnit::Pt => 96.0 / 72.0,
+        LengthUnit::Pc => 16.0,
+        unit => anyhow::bail!("lengths in {:?} aren't supported", unit),
+    };
+    Ok((length.number * scale) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shapes_and_styles() {
+        let drawing = Drawing::parse(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 50">
+                <g fill="red" transform="translate(10 0)">
+                    <rect width="20" height="10" rx="2"/>
+                    <circle cx="50" cy="25" r="10" style="fill: none; stroke: #00f; stroke-width: 4"/>
+                    <path d="M 0 0 L 10 0 L 10 10 z M 2 2 l 6 0 0 6 z" fill-rule="evenodd"/>
+                </g>
+                <line x1="0" y1="0" x2="100" y2="50" stroke="currentColor" color="lime" stroke-linecap="round"/>
+                <linearGradient id="g"/>
+            </svg>"##,
+        )
+        .unwrap();
+        assert_eq!(drawing.view_box, [0.0, 0.0, 100.0, 50.0]);
+        assert_eq!(drawing.shapes.len(), 4);
+        assert_eq!(drawing.shapes[0].transform, [1.0, 0.0, 0.0, 1.0, 10.0, 0.0]);
+        assert_eq!(drawing.shapes[0].fill, Some(([255, 0, 0, 255], FillRule::NonZero)));
+        assert_eq!(drawing.shapes[1].fill, None);
+        assert_eq!(drawing.shapes[1].stroke.unwrap().0, [0, 0, 255, 255]);
+        assert_eq!(drawing.shapes[1].stroke.unwrap().1.width, 4.0);
+        assert_eq!(drawing.shapes[2].fill.unwrap().1, FillRule::EvenOdd);
+        assert_eq!(drawing.shapes[3].fill, None);
+        assert_eq!(drawing.shapes[3].stroke.unwrap(), ([0, 255, 0, 255], Stroke { cap: LineCap::Round, ..Stroke::default() }));
+        assert_eq!(drawing.palette().width(), 3);
+
+        let geometry = drawing.geometry([-1.0, -1.0], [1.0, 1.0], Paint::Colours).unwrap();
+        assert!(!geometry.indices.is_empty());
+        // Wide, so it fills the width and is centred in the height, give or
+        // take the line's round caps.
+        for vertex in &geometry.vertices {
+            let [x, y, z] = vertex.position;
+            assert!((-1.01..=1.01).contains(&x) && (-0.51..=0.51).contains(&y), "{:?} is outside", vertex.position);
+            assert!(z > 0.0 && z < 1e-3);
+        }
+        for triangle in geometry.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| geometry.vertices[triangle[i] as usize].position);
+            assert!((b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Drawing::parse("<svg").is_err());
+        assert!(Drawing::parse("<html/>").is_err());
+        assert!(Drawing::parse("<svg/>").is_err());
+        let error = Drawing::parse("<svg viewBox='0 0 1 1'>\n<rect width='1em' height='1'/></svg>").unwrap_err();
+        assert!(format!("{:#}", error).contains("<rect> at line 2"), "{:#}", error);
+    }
+}
@@ -0,0 +1,694 @@
+use std::collections::HashMap;
+
+use ab_glyph::{Font, FontArc, GlyphId, PxScale, ScaleFont};
+use cgmath::{Matrix4, Point3, Transform};
+
+use crate::camera::CAMERA_WGSL;
+use crate::texture::Texture;
+use crate::uniform::{UniformBuffer, WgslType};
+use crate::wgsl_layout;
+
+const ATLAS_SIZE: u32 = 1024;
+/// Texels left empty around each glyph so linear filtering doesn't pick up
+/// its neighbours.
+const ATLAS_PADDING: u32 = 1;
+/// World space text is rasterised at this size and scaled, it can be seen
+/// from any distance so there is no one right size.
+const WORLD_RASTER_SIZE: f32 = 48.0;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Where a [`TextSection`] goes. Either way the alignment is relative to the
+/// anchor, centred text is centred on it.
+#[derive(Copy, Clone, Debug)]
+pub enum TextPlacement {
+    /// Pixels from the top left corner of the window, the first line's top
+    /// edge at `y`.
+    Screen { x: f32, y: f32 },
+    /// A plane in the scene, the text running along its x axis with y up and
+    /// the first line's top edge at the origin. `size` is in world units.
+    World(Matrix4<f32>),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TextSection<'a> {
+    pub text: &'a str,
+    /// The font size, in pixels on screen and world units in the scene.
+    pub size: f32,
+    pub color: [f32; 4],
+    pub align: TextAlign,
+    /// Lines are broken between words to stay within this width.
+    pub max_width: Option<f32>,
+    pub placement: TextPlacement,
+}
+
+/// A glyph placed by [`layout`], at its pen position on the baseline with y
+/// pointing down.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LaidOutGlyph {
+    pub id: GlyphId,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Places the glyphs of `text` at `size` pixels per line, kerned, broken at
+/// newlines and, given `max_width`, between words. Whitespace isn't drawn
+/// and is left out.
+pub fn layout(font: &FontArc, text: &str, size: f32, max_width: Option<f32>, align: TextAlign) -> Vec<LaidOutGlyph> {
+    let font = font.as_scaled(PxScale::from(size));
+    let line_height = font.height() + font.line_gap();
+    let mut glyphs = Vec::new();
+    let mut line_start = 0;
+    let mut y = font.ascent();
+    for paragraph in text.split('\n') {
+        let mut caret = 0.0;
+        // Up to the last visible glyph, trailing spaces don't count.
+        let mut line_width = 0.0;
+        let mut previous: Option<GlyphId> = None;
+        for word in paragraph.split_inclusive(char::is_whitespace) {
+            let word: Vec<(char, GlyphId)> = word.chars().map(|c| (c, font.glyph_id(c))).collect();
+
+            let mut word_end = caret;
+            let mut advance = caret;
+            let mut last = previous;
+            for &(c, id) in &word {
+                advance += last.map_or(0.0, |last| font.kern(last, id)) + font.h_advance(id);
+                if !c.is_whitespace() {
+                    word_end = advance;
+                }
+                last = Some(id);
+            }
+            // A word too long for a line of its own still gets one.
+            if max_width.is_some_and(|max_width| caret > 0.0 && word_end > max_width) {
+                align_line(&mut glyphs[line_start..], line_width, align);
+                line_start = glyphs.len();
+                y += line_height;
+                caret = 0.0;
+                line_width = 0.0;
+                previous = None;
+            }
+
+            for (c, id) in word {
+                caret += previous.map_or(0.0, |previous| font.kern(previous, id));
+                if !c.is_whitespace() {
+                    glyphs.push(LaidOutGlyph { id, x: caret, y });
+                }
+                caret += font.h_advance(id);
+                if !c.is_whitespace() {
+                    line_width = caret;
+                }
+                previous = Some(id);
+            }
+        }
+        align_line(&mut glyphs[line_start..], line_width, align);
+        line_start = glyphs.len();
+        y += line_height;
+    }
+    glyphs
+}
+
+fn align_line(line: &mut [LaidOutGlyph], width: f32, align: TextAlign) {
+    let offset = match align {
+        TextAlign::Left => return,
+        TextAlign::Center => -width * 0.5,
+        TextAlign::Right => -width,
+    };
+    for glyph in line {
+        glyph.x += offset;
+    }
+}
+
+/// Where a rasterised glyph is in the atlas, and where its pixels go
+/// relative to the pen position.
+#[derive(Copy, Clone, Debug)]
+struct AtlasGlyph {
+    offset: [f32; 2],
+    size: [f32; 2],
+    uv_min: [f32; 2],
+    uv_max: [f32; 2],
+}
+
+/// Glyph coverage packed into one texture in rows, rasterised the first
+/// time each glyph is drawn at each size.
+struct GlyphAtlas {
+    texture: Texture,
+    bind_group: wgpu::BindGroup,
+    /// `None` for glyphs with nothing to draw.
+    glyphs: HashMap<(GlyphId, u32), Option<AtlasGlyph>>,
+    cursor: (u32, u32),
+    row_height: u32,
+    /// Set when a glyph didn't fit, the atlas has to be cleared.
+    overflowed: bool,
+}
+
+impl GlyphAtlas {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("glyph_atlas"),
+            size: wgpu::Extent3d {
+                width: ATLAS_SIZE,
+                height: ATLAS_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some("glyph_atlas_bind_group"),
+        });
+
+        Self {
+            texture: Texture { texture, view, sampler },
+            bind_group,
+            glyphs: HashMap::new(),
+            cursor: (0, 0),
+            row_height: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Forgets every glyph, they get rasterised again as they are used.
+    fn clear(&mut self) {
+        self.glyphs.clear();
+        self.cursor = (0, 0);
+        self.row_height = 0;
+        self.overflowed = false;
+    }
+
+    fn glyph(&mut self, queue: &wgpu::Queue, font: &FontArc, id: GlyphId, size: u32) -> Option<AtlasGlyph> {
+        if let Some(&glyph) = self.glyphs.get(&(id, size)) {
+            return glyph;
+        }
+
+        let outlined = font.outline_glyph(id.with_scale(size as f32));
+        let Some(outlined) = outlined else {
+            self.glyphs.insert((id, size), None);
+            return None;
+        };
+        let bounds = outlined.px_bounds();
+        let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+        if width == 0 || height == 0 {
+            self.glyphs.insert((id, size), None);
+            return None;
+        }
+
+        if self.cursor.0 + width + ATLAS_PADDING > ATLAS_SIZE {
+            self.cursor = (0, self.cursor.1 + self.row_height);
+            self.row_height = 0;
+        }
+        // Past the bottom, or too wide for even an empty row.
+        if self.cursor.0 + width + ATLAS_PADDING > ATLAS_SIZE || self.cursor.1 + height + ATLAS_PADDING > ATLAS_SIZE {
+            self.overflowed = true;
+            return None;
+        }
+        let (x, y) = (self.cursor.0 + ATLAS_PADDING, self.cursor.1 + ATLAS_PADDING);
+        self.cursor.0 += width + ATLAS_PADDING;
+        self.row_height = self.row_height.max(height + ATLAS_PADDING);
+
+        let mut coverage = vec![0u8; (width * height) as usize];
+        outlined.draw(|px, py, c| {
+            coverage[(py * width + px) as usize] = (c.clamp(0.0, 1.0) * 255.0) as u8;
+        });
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &coverage,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(width),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let texel = 1.0 / ATLAS_SIZE as f32;
+        let glyph = AtlasGlyph {
+            offset: [bounds.min.x, bounds.min.y],
+            size: [width as f32, height as f32],
+            uv_min: [x as f32 * texel, y as f32 * texel],
+            uv_max: [(x + width) as f32 * texel, (y + height) as f32 * texel],
+        };
+        self.glyphs.insert((id, size), Some(glyph));
+        Some(glyph)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TextVertex {
+    position: [f32; 3],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+}
+
+impl TextVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+            wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2, 2 => Float32x4];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<TextVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ScreenUniform {
+    size: [f32; 2],
+}
+
+wgsl_layout!(ScreenUniform {
+    size: WgslType::Vec2,
+});
+
+/// A section as it was queued, laid out but not yet rasterised.
+struct QueuedSection {
+    glyphs: Vec<LaidOutGlyph>,
+    /// The size the glyphs are rasterised at.
+    raster_size: u32,
+    color: [f32; 4],
+    placement: TextPlacement,
+    /// World units per raster pixel, for world space text.
+    scale: f32,
+}
+
+/// Draws text from one font. Sections queued during a frame are batched
+/// into a single vertex buffer, drawn with one call for the world space
+/// text and one for the screen overlay.
+pub struct TextRenderer {
+    font: FontArc,
+    atlas_layout: wgpu::BindGroupLayout,
+    atlas: GlyphAtlas,
+    screen_uniform: UniformBuffer<ScreenUniform>,
+    screen_pipeline: wgpu::RenderPipeline,
+    sections: Vec<QueuedSection>,
+    vertex_buffer: wgpu::Buffer,
+    world_vertices: u32,
+    screen_vertices: u32,
+}
+
+impl TextRenderer {
+    /// `font_data` is a TrueType or OpenType font. The screen overlay is
+    /// drawn into `output_format` targets.
+    pub fn new(device: &wgpu::Device, font_data: Vec<u8>, output_format: wgpu::TextureFormat) -> anyhow::Result<Self> {
+        let font = FontArc::try_from_vec(font_data)?;
+        let atlas_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("glyph_atlas_bind_group_layout"),
+        });
+        let atlas = GlyphAtlas::new(device, &atlas_layout);
+
+        let screen_uniform = UniformBuffer::new(
+            device,
+            &ScreenUniform { size: [1.0, 1.0] },
+            wgpu::ShaderStages::VERTEX,
+            "text_screen",
+        );
+        let screen_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Screen Text Pipeline Layout"),
+            bind_group_layouts: &[&screen_uniform.bind_group_layout, &atlas_layout],
+            push_constant_ranges: &[],
+        });
+        let screen_pipeline = create_text_pipeline(
+            device,
+            &screen_layout,
+            output_format,
+            1,
+            false,
+            format!("{}\n{}", include_str!("text.wgsl"), include_str!("text_screen.wgsl")),
+            "Screen Text Pipeline",
+        );
+
+        Ok(Self {
+            font,
+            atlas_layout,
+            atlas,
+            screen_uniform,
+            screen_pipeline,
+            sections: Vec::new(),
+            vertex_buffer: create_vertex_buffer(device, 1024),
+            world_vertices: 0,
+            screen_vertices: 0,
+        })
+    }
+
+    /// The layouts the world space pipeline from [`create_world_pipeline`]
+    /// is built with, given the camera's.
+    pub fn world_bind_group_layouts<'a>(
+        &'a self,
+        camera_layout: &'a wgpu::BindGroupLayout,
+    ) -> [&'a wgpu::BindGroupLayout; 2] {
+        [camera_layout, &self.atlas_layout]
+    }
+
+    /// Adds `section` to this frame's text.
+    pub fn queue(&mut self, section: TextSection) {
+        let (raster_size, scale) = match section.placement {
+            TextPlacement::Screen { .. } => (section.size.round().max(1.0), 1.0),
+            TextPlacement::World(_) => (WORLD_RASTER_SIZE, section.size / WORLD_RASTER_SIZE),
+        };
+        let max_width = section.max_width.map(|max_width| max_width / scale);
+        self.sections.push(QueuedSection {
+            glyphs: layout(&self.font, section.text, raster_size, max_width, section.align),
+            raster_size: raster_size as u32,
+            color: section.color,
+            placement: section.placement,
+            scale,
+        });
+    }
+
+    /// Rasterises any new glyphs and uploads the queued sections for a
+    /// `width` by `height` window, ready to draw. The queue is emptied.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.screen_uniform.update(queue, &ScreenUniform {
+            size: [width as f32, height as f32],
+        });
+
+        let mut vertices = self.build_vertices(queue);
+        // Glyphs from earlier frames may be taking up the room, start over
+        // with only the ones in use.
+        if self.atlas.overflowed {
+            self.atlas.clear();
+            vertices = self.build_vertices(queue);
+            if self.atlas.overflowed {
+                log::warn!("The glyph atlas is full, some text is missing");
+                self.atlas.overflowed = false;
+            }
+        }
+        self.sections.clear();
+
+        let (world, screen) = vertices;
+        self.world_vertices = world.len() as u32;
+        self.screen_vertices = screen.len() as u32;
+        let vertices = [world, screen].concat();
+        let size = std::mem::size_of_val(vertices.as_slice()) as wgpu::BufferAddress;
+        if size > self.vertex_buffer.size() {
+            self.vertex_buffer = create_vertex_buffer(device, size.next_power_of_two());
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+
+    /// The quads of every queued section, world space and screen space.
+    fn build_vertices(&mut self, queue: &wgpu::Queue) -> (Vec<TextVertex>, Vec<TextVertex>) {
+        let mut world = Vec::new();
+        let mut screen = Vec::new();
+        for section in &self.sections {
+            for glyph in &section.glyphs {
+                let Some(atlas_glyph) = self.atlas.glyph(queue, &self.font, glyph.id, section.raster_size) else {
+                    continue;
+                };
+                match section.placement {
+                    TextPlacement::Screen { x, y } => {
+                        // Whole pixels keep the glyphs as sharp as they were
+                        // rasterised.
+                        let left = (x + glyph.x).round() + atlas_glyph.offset[0];
+                        let top = (y + glyph.y).round() + atlas_glyph.offset[1];
+                        screen.extend(quad(&atlas_glyph, section.color, |[u, v]| {
+                            [left + u * atlas_glyph.size[0], top + v * atlas_glyph.size[1], 0.0]
+                        }));
+                    }
+                    TextPlacement::World(transform) => {
+                        let left = glyph.x + atlas_glyph.offset[0];
+                        let top = glyph.y + atlas_glyph.offset[1];
+                        world.extend(quad(&atlas_glyph, section.color, |[u, v]| {
+                            let x = (left + u * atlas_glyph.size[0]) * section.scale;
+                            let y = -(top + v * atlas_glyph.size[1]) * section.scale;
+                            transform.transform_point(Point3::new(x, y, 0.0)).into()
+                        }));
+                    }
+                }
+            }
+        }
+        (world, screen)
+    }
+
+    /// Draws the world space text with the pipeline from
+    /// [`create_world_pipeline`], which has to be bound already along with
+    /// the camera at group 0. Draw it after the opaque geometry, it is
+    /// blended and doesn't write depth.
+    pub fn draw_world(&self, pass: &mut wgpu::RenderPass) {
+        if self.world_vertices == 0 {
+            return;
+        }
+        pass.set_bind_group(1, &self.atlas.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.draw(0..self.world_vertices, 0..1);
+    }
+
+    /// Draws the screen space text over `output`.
+    pub fn draw_screen(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        if self.screen_vertices == 0 {
+            return;
+        }
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Screen Text Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        let stride = std::mem::size_of::<TextVertex>() as wgpu::BufferAddress;
+        pass.set_pipeline(&self.screen_pipeline);
+        pass.set_bind_group(0, &self.screen_uniform.bind_group, &[]);
+        pass.set_bind_group(1, &self.atlas.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(self.world_vertices as u64 * stride..));
+        pass.draw(0..self.screen_vertices, 0..1);
+    }
+}
+
+/// Two triangles covering `glyph`, `corner` turning (0, 0) to (1, 1) from
+/// its top left to bottom right into positions.
+fn quad(glyph: &AtlasGlyph, color: [f32; 4], corner: impl Fn([f32; 2]) -> [f32; 3]) -> [TextVertex; 6] {
+    [[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [0.0, 0.0], [1.0, 1.0], [1.0, 0.0]].map(|[u, v]| TextVertex {
+        position: corner([u, v]),
+        tex_coords: [
+            glyph.uv_min[0] + u * (glyph.uv_max[0] - glyph.uv_min[0]),
+            glyph.uv_min[1] + v * (glyph.uv_max[1] - glyph.uv_min[1]),
+        ],
+        color,
+    })
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("text_vertex_buffer"),
+        size,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// The pipeline world space text is drawn with, in the scene pass.
+pub fn create_world_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    create_text_pipeline(
+        device,
+        layout,
+        color_format,
+        sample_count,
+        true,
+        format!(
+            "{}\n{}\n{}",
+            CAMERA_WGSL,
+            include_str!("text.wgsl"),
+            include_str!("text_world.wgsl")
+        ),
+        "World Text Pipeline",
+    )
+}
+
+fn create_text_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+    depth_test: bool,
+    source: String,
+    label: &str,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[TextVertex::desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: color_format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        // World space text can be read from behind, mirrored.
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            cull_mode: None,
+            ..Default::default()
+        },
+        depth_stencil: depth_test.then(|| wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: f32 = 20.0;
+
+    fn font() -> FontArc {
+        FontArc::try_from_slice(include_bytes!("../fonts/DejaVuSans.ttf")).unwrap()
+    }
+
+    fn advance(font: &FontArc, c: char) -> f32 {
+        let font = font.as_scaled(PxScale::from(SIZE));
+        font.h_advance(font.glyph_id(c))
+    }
+
+    #[test]
+    fn kerning() {
+        let font = font();
+        let scaled = font.as_scaled(PxScale::from(SIZE));
+        let (a, v) = (scaled.glyph_id('A'), scaled.glyph_id('V'));
+        let kern = scaled.kern(a, v);
+        assert!(kern < 0.0, "DejaVu Sans kerns AV");
+        let glyphs = layout(&font, "AV", SIZE, None, TextAlign::Left);
+        assert_eq!(glyphs.len(), 2);
+        assert!((glyphs[1].x - (scaled.h_advance(a) + kern)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn word_wrap() {
+        let font = font();
+        let scaled = font.as_scaled(PxScale::from(SIZE));
+        let word = advance(&font, 'a') * 3.0;
+        let glyphs = layout(&font, "aaa bbb ccc", SIZE, Some(word * 2.5), TextAlign::Left);
+        assert_eq!(glyphs.len(), 9);
+        // "aaa bbb" fits, "ccc" goes on the next line.
+        let first_line = glyphs[0].y;
+        assert!(glyphs[..6].iter().all(|glyph| glyph.y == first_line));
+        assert_eq!(glyphs[6].x, 0.0);
+        assert!((glyphs[6].y - first_line - (scaled.height() + scaled.line_gap())).abs() < 1e-4);
+
+        // A word longer than the line isn't broken up.
+        let glyphs = layout(&font, "aaaaaaaa", SIZE, Some(word), TextAlign::Left);
+        assert!(glyphs.iter().all(|glyph| glyph.y == glyphs[0].y));
+    }
+
+    #[test]
+    fn alignment() {
+        let font = font();
+        // Trailing spaces don't count towards the width.
+        let width = advance(&font, 'i') * 2.0;
+        let center = layout(&font, "ii  ", SIZE, None, TextAlign::Center);
+        assert!((center[0].x + width * 0.5).abs() < 1e-4);
+        let right = layout(&font, "ii  \nii", SIZE, None, TextAlign::Right);
+        assert!((right[0].x + width).abs() < 1e-4);
+        assert!((right[2].x + width).abs() < 1e-4);
+        assert!(right[2].y > right[0].y);
+    }
+
+    #[test]
+    fn multi_byte_utf8() {
+        let font = font();
+        let text = "héllo wörld → ✓";
+        let glyphs = layout(&font, text, SIZE, None, TextAlign::Left);
+        let visible: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+        assert_eq!(glyphs.len(), visible.len());
+        for (glyph, c) in glyphs.iter().zip(visible) {
+            assert_eq!(glyph.id, font.glyph_id(c));
+            assert_ne!(glyph.id, GlyphId(0), "{:?} isn't in the font", c);
+        }
+        assert!(glyphs.windows(2).all(|pair| pair[1].x > pair[0].x));
+    }
+}
@@ -0,0 +1,16 @@
+struct Screen {
+    size: vec2<f32>,
+}
+@group(0) @binding(0)
+var<uniform> screen: Screen;
+
+// Positions are in pixels from the top left corner.
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    let ndc = model.position.xy / screen.size * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.tex_coords = model.tex_coords;
+    out.color = model.color;
+    return out;
+}
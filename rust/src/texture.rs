@@ -1,33 +1,49 @@
 use image::GenericImageView;
 use anyhow::*;
 
+use crate::loader;
+use crate::post;
+use crate::uniform::{UniformBuffer, WgslType};
+use crate::wgsl_layout;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct CubeFaceUniform {
+    face: u32,
+}
+
+wgsl_layout!(CubeFaceUniform {
+    face: WgslType::U32,
+});
+
 pub struct Texture {
-    #[allow(unused)]
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
 }
 
 impl Texture {
-    pub fn from_bytes(
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        bytes: &[u8], 
-        label: &str
+        img: &image::DynamicImage,
+        label: Option<&str>
     ) -> Result<Self> {
-        let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+        Self::from_image_with_format(device, queue, img, wgpu::TextureFormat::Rgba8UnormSrgb, label)
     }
 
-    pub fn from_image(
+    /// Like [`Texture::from_image`], but lets data textures such as normal
+    /// or roughness maps be uploaded as linear `Rgba8Unorm` instead of sRGB.
+    pub fn from_image_with_format(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
+        format: wgpu::TextureFormat,
         label: Option<&str>
     ) -> Result<Self> {
-        let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
-
         let size = wgpu::Extent3d {
             width: dimensions.0,
             height: dimensions.1,
@@ -40,41 +56,525 @@ impl Texture {
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            }
+        );
+
+        write_rgba(queue, &texture, &img.to_rgba8());
+
+        Ok(Self::with_sampler(device, texture))
+    }
+
+    /// An sRGB texture of an image from the [`Loader`](crate::loader::Loader).
+    /// On the web that's an `ImageBitmap` the browser decoded, copied into
+    /// the texture as it is.
+    pub fn from_loaded(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &loader::Image,
+        label: Option<&str>
+    ) -> Result<Self> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            Self::from_image(device, queue, image, label)
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            let texture = device.create_texture(
+                &wgpu::TextureDescriptor {
+                    label,
+                    size: wgpu::Extent3d {
+                        width: image.width(),
+                        height: image.height(),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                    // Copying an external image in needs it to be renderable.
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_DST
+                        | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    view_formats: &[],
+                }
+            );
+            copy_bitmap(queue, &texture, image);
+            Ok(Self::with_sampler(device, texture))
+        }
+    }
+
+    fn with_sampler(device: &wgpu::Device, texture: wgpu::Texture) -> Self {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Nearest,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            }
+        );
+        Self { texture, view, sampler }
+    }
+
+    /// Overwrites a texture made by [`Texture::from_loaded`] with `image` in
+    /// place, so bind groups using it see the new image. Returns false,
+    /// leaving the texture as it was, if `image` is a different size.
+    pub fn write_loaded(&self, queue: &wgpu::Queue, image: &loader::Image) -> bool {
+        let size = self.texture.size();
+        if (image.width(), image.height()) != (size.width, size.height) {
+            return false;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        write_rgba(queue, &self.texture, &image.to_rgba8());
+        #[cfg(target_arch = "wasm32")]
+        copy_bitmap(queue, &self.texture, image);
+        true
+    }
+
+    /// A 1x1 texture of a single colour, e.g. a stand-in for a missing map.
+    pub fn from_color(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: [u8; 4],
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Self {
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(color)));
+        Self::from_image_with_format(device, queue, &img, format, Some(label))
+            .expect("a 1x1 image always uploads")
+    }
+
+    /// A multisampled colour target, resolved into the single-sampled
+    /// scene target at the end of the render pass.
+    pub fn create_msaa_texture(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            }
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        Self { texture, view, sampler }
+    }
+
+    /// A single-sampled texture that can be rendered into and then sampled
+    /// by a later pass, e.g. the scene colour fed to post-processing.
+    pub fn create_render_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            }
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            }
+        );
+
+        Self { texture, view, sampler }
+    }
+
+    /// The scene's depth buffer. Its sample count has to match the colour
+    /// target it is used with.
+    pub fn create_depth_texture(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: Self::DEPTH_FORMAT,
+                // Single sampled depth can be read back by later passes.
+                // WebGL2 can't sample multisampled textures at all.
+                usage: if sample_count == 1 {
+                    wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+                } else {
+                    wgpu::TextureUsages::RENDER_ATTACHMENT
+                },
+                view_formats: &[],
+            }
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        Self { texture, view, sampler }
+    }
+
+    /// A cube map from six square faces of the same size, in +X, -X, +Y,
+    /// -Y, +Z, -Z order.
+    pub fn cube_from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::DynamicImage],
+        label: &str,
+    ) -> Result<Self> {
+        if faces.len() != 6 {
+            bail!("{}: a cube map needs 6 faces, got {}", label, faces.len());
+        }
+        let (width, height) = faces[0].dimensions();
+        for (i, face) in faces.iter().enumerate() {
+            if face.dimensions() != (width, width) || height != width {
+                bail!(
+                    "{}: face {} is {:?}, every face must be {}x{}",
+                    label,
+                    i,
+                    face.dimensions(),
+                    width,
+                    width,
+                );
+            }
+        }
+
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height: width,
+                    depth_or_array_layers: 6,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
                 format: wgpu::TextureFormat::Rgba8UnormSrgb,
                 usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
                 view_formats: &[],
             }
         );
 
+        for (i, face) in faces.iter().enumerate() {
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x: 0, y: 0, z: i as u32 },
+                },
+                &face.to_rgba8(),
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * width),
+                    rows_per_image: Some(width),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height: width,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        Ok(Self::from_cube_texture(device, texture))
+    }
+
+    /// Decodes an equirectangular Radiance `.hdr` panorama and renders it
+    /// into a cube map with `size`x`size` faces. `format` has to
+    /// be renderable, pick it with [`crate::hdr::scene_format`] to keep the
+    /// range above 1.0 where the adapter allows it.
+    pub fn cube_from_equirectangular(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        size: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Result<Self> {
+        // `image::load_from_memory` would tonemap the panorama down to 8 bits.
+        let decoder = image::codecs::hdr::HdrDecoder::new(bytes)
+            .with_context(|| format!("{}: not a Radiance HDR image", label))?;
+        let meta = decoder.metadata();
+        let (width, height) = (meta.width, meta.height);
+        let rgb = decoder
+            .read_image_hdr()
+            .with_context(|| format!("{}: can't decode panorama", label))?;
+        // Half floats, 32 bit float textures can't be filtered everywhere.
+        let pixels: Vec<half::f16> = rgb
+            .iter()
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 1.0])
+            .map(half::f16::from_f32)
+            .collect();
+
+        let equirect_size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let equirect = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: equirect_size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba16Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            }
+        );
         queue.write_texture(
             wgpu::TexelCopyTextureInfo {
                 aspect: wgpu::TextureAspect::All,
-                texture: &texture,
+                texture: &equirect,
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
-            &rgba,
+            bytemuck::cast_slice(&pixels),
             wgpu::TexelCopyBufferLayout {
                 offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
+                bytes_per_row: Some(8 * width),
+                rows_per_image: Some(height),
             },
-            size,
+            equirect_size,
+        );
+        let equirect_view = equirect.create_view(&wgpu::TextureViewDescriptor::default());
+        let equirect_sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::Repeat,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }
         );
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let cube = Self::create_cube_render_target(device, size, 1, format, label);
+
+        let resources = [
+            (
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindingResource::TextureView(&equirect_view),
+            ),
+            (
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindingResource::Sampler(&equirect_sampler),
+            ),
+        ];
+        // One uniform per face, so all six passes can go in one submit.
+        let faces: Vec<_> = (0..6)
+            .map(|face| {
+                UniformBuffer::with_resources(
+                    device,
+                    &CubeFaceUniform { face },
+                    wgpu::ShaderStages::FRAGMENT,
+                    &resources,
+                    "equirect_face",
+                )
+            })
+            .collect();
+        let pipeline = post::create_fullscreen_pipeline(
+            device,
+            &[&faces[0].bind_group_layout],
+            format,
+            wgpu::BlendState::REPLACE,
+            &format!("{}\n{}", include_str!("cube.wgsl"), include_str!("equirect_to_cube.wgsl")),
+            "fs_main",
+            "Equirect To Cube Pipeline",
+        );
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Equirect To Cube Encoder"),
+        });
+        for (face, uniform) in faces.iter().enumerate() {
+            let face_view = cube.cube_face_view(face as u32, 0);
+            let mut pass = post::begin_fullscreen_pass(
+                &mut encoder,
+                &face_view,
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                "Equirect To Cube Pass",
+            );
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &uniform.bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        Ok(cube)
+    }
+
+    /// An empty cube map that can be rendered into one face and mip level at
+    /// a time through [`Texture::cube_face_view`].
+    pub fn create_cube_render_target(
+        device: &wgpu::Device,
+        size: u32,
+        mip_level_count: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 6,
+                },
+                mip_level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            }
+        );
+
+        Self::from_cube_texture(device, texture)
+    }
+
+    /// A view of one face at one mip level of a cube map, to render into.
+    pub fn cube_face_view(&self, face: u32, mip_level: u32) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("cube_face"),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_mip_level: mip_level,
+            mip_level_count: Some(1),
+            base_array_layer: face,
+            array_layer_count: Some(1),
+            ..Default::default()
+        })
+    }
+
+    fn from_cube_texture(device: &wgpu::Device, texture: wgpu::Texture) -> Self {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
         let sampler = device.create_sampler(
             &wgpu::SamplerDescriptor {
                 address_mode_u: wgpu::AddressMode::ClampToEdge,
                 address_mode_v: wgpu::AddressMode::ClampToEdge,
                 address_mode_w: wgpu::AddressMode::ClampToEdge,
                 mag_filter: wgpu::FilterMode::Linear,
-                min_filter: wgpu::FilterMode::Nearest,
-                mipmap_filter: wgpu::FilterMode::Nearest,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear,
                 ..Default::default()
             }
         );
 
-        Ok(Self { texture, view, sampler })
+        Self { texture, view, sampler }
     }
 }
+
+/// Uploads `rgba` over the whole of the first mip level of `texture`.
+fn write_rgba(queue: &wgpu::Queue, texture: &wgpu::Texture, rgba: &image::RgbaImage) {
+    let (width, height) = rgba.dimensions();
+    queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+            aspect: wgpu::TextureAspect::All,
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        rgba,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * width),
+            rows_per_image: Some(height),
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+#[cfg(target_arch = "wasm32")]
+fn copy_bitmap(queue: &wgpu::Queue, texture: &wgpu::Texture, bitmap: &wgpu::web_sys::ImageBitmap) {
+    queue.copy_external_image_to_texture(
+        &wgpu::CopyExternalImageSourceInfo {
+            source: wgpu::ExternalImageSource::ImageBitmap(bitmap.clone()),
+            origin: wgpu::Origin2d::ZERO,
+            flip_y: false,
+        },
+        wgpu::CopyExternalImageDestInfo {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+            color_space: wgpu::PredefinedColorSpace::Srgb,
+            premultiplied_alpha: false,
+        },
+        texture.size(),
+    );
+}
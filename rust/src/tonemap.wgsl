@@ -0,0 +1,92 @@
+struct TonemapUniform {
+    exposure: f32,
+    tonemapper: u32,
+    encode_srgb: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> tonemap: TonemapUniform;
+
+const TONEMAP_REINHARD: u32 = 0u;
+const TONEMAP_ACES: u32 = 1u;
+const TONEMAP_AGX: u32 = 2u;
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+// Stephen Hill's fit of the ACES RRT + ODT, matrices converted from his
+// row-major HLSL to WGSL's column-major constructors.
+fn rrt_and_odt_fit(v: vec3<f32>) -> vec3<f32> {
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return a / b;
+}
+
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let aces_input = mat3x3<f32>(
+        vec3<f32>(0.59719, 0.07600, 0.02840),
+        vec3<f32>(0.35458, 0.90834, 0.13383),
+        vec3<f32>(0.04823, 0.01566, 0.83777),
+    );
+    let aces_output = mat3x3<f32>(
+        vec3<f32>(1.60475, -0.10208, -0.00327),
+        vec3<f32>(-0.53108, 1.10813, -0.07276),
+        vec3<f32>(-0.07367, -0.00605, 1.07602),
+    );
+    return clamp(aces_output * rrt_and_odt_fit(aces_input * color), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Minimal AgX with the default look, using a polynomial fit of the
+// contrast curve.
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let agx_in = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let agx_out = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var c = agx_in * color;
+    c = clamp(log2(max(c, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    c = (c - min_ev) / (max_ev - min_ev);
+    c = agx_out * agx_contrast(c);
+    // AgX produces display-encoded values, decoded here so every
+    // tonemapper hands on linear ones.
+    return pow(max(c, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let hdr = textureSample(t_input, s_input, in.uv);
+    let color = hdr.rgb * exp2(tonemap.exposure);
+
+    var mapped: vec3<f32>;
+    switch tonemap.tonemapper {
+        case TONEMAP_ACES: {
+            mapped = aces(color);
+        }
+        case TONEMAP_AGX: {
+            mapped = agx(color);
+        }
+        default: {
+            mapped = reinhard(color);
+        }
+    }
+    if (tonemap.encode_srgb != 0u) {
+        mapped = pow(mapped, vec3<f32>(1.0 / 2.2));
+    }
+    return vec4<f32>(mapped, hdr.a);
+}
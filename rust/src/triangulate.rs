@@ -0,0 +1,399 @@
+//! Splits simple polygons, with holes, into triangles by ear clipping. Each
+//! hole is first joined to the outline by a cut there and back, so there's
+//! a single ring of points to clip ears off.
+
+use crate::Vertex;
+
+/// The polygon inside `outline` and outside every one of `holes`, as
+/// [`Vertex`] data in the XY plane facing +Z. UVs map the polygon's bounding
+/// square onto the texture, upright.
+pub fn polygon(outline: &[[f32; 2]], holes: &[Vec<[f32; 2]>]) -> anyhow::Result<(Vec<Vertex>, Vec<u16>)> {
+    let triangles = triangulate(outline, holes)?;
+    let points: Vec<[f32; 2]> = outline.iter().chain(holes.iter().flatten()).copied().collect();
+    if points.len() > u16::MAX as usize + 1 {
+        anyhow::bail!("{} points is too many for 16 bit indices", points.len());
+    }
+
+    let (mut min, mut max) = ([f32::MAX; 2], [f32::MIN; 2]);
+    for p in &points {
+        min = [min[0].min(p[0]), min[1].min(p[1])];
+        max = [max[0].max(p[0]), max[1].max(p[1])];
+    }
+    let size = (max[0] - min[0]).max(max[1] - min[1]);
+    let vertices = points
+        .iter()
+        .map(|p| Vertex {
+            position: [p[0], p[1], 0.0],
+            tex_coords: [(p[0] - min[0]) / size, (max[1] - p[1]) / size],
+        })
+        .collect();
+    Ok((vertices, triangles.into_iter().map(|i| i as u16).collect()))
+}
+
+/// Triangles covering the inside of `outline` minus `holes`, wound
+/// counter-clockwise, as indices into the outline's points followed by each
+/// hole's. Rings can go either way round.
+///
+/// Fails if any ring crosses or touches itself or another, has fewer than
+/// three points or no area, or if a hole isn't inside the outline or is
+/// inside another hole.
+pub fn triangulate(outline: &[[f32; 2]], holes: &[Vec<[f32; 2]>]) -> anyhow::Result<Vec<u32>> {
+    let rings: Vec<&[[f32; 2]]> = std::iter::once(outline).chain(holes.iter().map(Vec::as_slice)).collect();
+    validate(&rings)?;
+
+    let points: Vec<[f64; 2]> = rings.iter().flat_map(|ring| ring.iter()).map(|p| p.map(f64::from)).collect();
+    let mut start = 0;
+    let mut rings: Vec<Vec<usize>> = rings
+        .iter()
+        .map(|ring| {
+            start += ring.len();
+            (start - ring.len()..start).collect()
+        })
+        .collect();
+
+    let mut polygon = rings.remove(0);
+    if signed_area(&points, &polygon) < 0.0 {
+        polygon.reverse();
+    }
+    for hole in &mut rings {
+        if signed_area(&points, hole) > 0.0 {
+            hole.reverse();
+        }
+    }
+    // Rightmost first, so the cut to each hole can't cross one still to
+    // come.
+    let max_x = |ring: &Vec<usize>| ring.iter().map(|&i| points[i][0]).fold(f64::MIN, f64::max);
+    rings.sort_by(|a, b| max_x(b).total_cmp(&max_x(a)));
+    for hole in &rings {
+        bridge(&points, &mut polygon, hole)?;
+    }
+    clip_ears(&points, polygon)
+}
+
+fn ring_name(ring: usize) -> String {
+    match ring {
+        0 => "the outline".to_string(),
+        hole => format!("hole {}", hole - 1),
+    }
+}
+
+fn validate(rings: &[&[[f32; 2]]]) -> anyhow::Result<()> {
+    let points: Vec<Vec<[f64; 2]>> = rings.iter().map(|ring| ring.iter().map(|p| p.map(f64::from)).collect()).collect();
+    for (r, ring) in points.iter().enumerate() {
+        if ring.len() < 3 {
+            anyhow::bail!("{} has {} points, it needs at least 3", ring_name(r), ring.len());
+        }
+        if let Some(i) = ring.iter().position(|p| !p[0].is_finite() || !p[1].is_finite()) {
+            anyhow::bail!("point {} of {} isn't finite", i, ring_name(r));
+        }
+        if let Some(i) = (0..ring.len()).find(|&i| ring[i] == ring[(i + 1) % ring.len()]) {
+            anyhow::bail!("point {} of {} is the same as the next one", i, ring_name(r));
+        }
+    }
+
+    // Every pair of edges that don't share a point.
+    let edge = |r: usize, i: usize| (points[r][i], points[r][(i + 1) % points[r].len()]);
+    for r1 in 0..points.len() {
+        for i in 0..points[r1].len() {
+            for r2 in r1..points.len() {
+                let first = if r1 == r2 { i + 1 } else { 0 };
+                for j in first..points[r2].len() {
+                    let len = points[r1].len();
+                    let adjacent = r1 == r2 && (j == i + 1 || (i == 0 && j == len - 1));
+                    let ((a, b), (c, d)) = (edge(r1, i), edge(r2, j));
+                    let crossing = if adjacent {
+                        // Only overlapping along their length counts, they
+                        // meet at the shared point anyway.
+                        let shared = if j == i + 1 { b } else { a };
+                        let (p, q) = (if shared == a { b } else { a }, if shared == c { d } else { c });
+                        orient(p, shared, q) == 0.0 && dot(sub(p, shared), sub(q, shared)) > 0.0
+                    } else {
+                        segments_touch(a, b, c, d)
+                    };
+                    if crossing {
+                        anyhow::bail!(
+                            "edge {} of {} crosses or touches edge {} of {}",
+                            i,
+                            ring_name(r1),
+                            j,
+                            ring_name(r2)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    for (r, ring) in points.iter().enumerate() {
+        let indices: Vec<usize> = (0..ring.len()).collect();
+        if signed_area(ring, &indices) == 0.0 {
+            anyhow::bail!("{} has no area", ring_name(r));
+        }
+    }
+
+    // With no edges crossing, one point is enough to tell what a hole is
+    // in.
+    for h in 1..points.len() {
+        if !inside(&points[0], points[h][0]) {
+            anyhow::bail!("{} isn't inside the outline", ring_name(h));
+        }
+        if let Some(other) = (1..points.len()).find(|&other| other != h && inside(&points[other], points[h][0])) {
+            anyhow::bail!("{} is inside {}", ring_name(h), ring_name(other));
+        }
+    }
+    Ok(())
+}
+
+/// Splices `hole`, clockwise, into `polygon`, counter-clockwise, with a cut
+/// from the hole's rightmost point to a point of the polygon it can see.
+fn bridge(points: &[[f64; 2]], polygon: &mut Vec<usize>, hole: &[usize]) -> anyhow::Result<()> {
+    let m_at = (0..hole.len())
+        .max_by(|&a, &b| points[hole[a]][0].total_cmp(&points[hole[b]][0]))
+        .unwrap();
+    let m = points[hole[m_at]];
+    let n = polygon.len();
+
+    // The closest edge straight to the right of M, going up as edges seen
+    // from the inside of a counter-clockwise polygon on their right do.
+    let mut hit: Option<(f64, usize)> = None;
+    for i in 0..n {
+        let (a, b) = (points[polygon[i]], points[polygon[(i + 1) % n]]);
+        if !(a[1] <= m[1] && m[1] <= b[1]) || a[1] == b[1] {
+            continue;
+        }
+        let x = a[0] + (m[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0]);
+        if x >= m[0] && hit.is_none_or(|(closest, _)| x < closest) {
+            hit = Some((x, i));
+        }
+    }
+    let Some((x, edge)) = hit else {
+        anyhow::bail!("no edge to join a hole to");
+    };
+    let (a_at, b_at) = (edge, (edge + 1) % n);
+    let (a, b) = (points[polygon[a_at]], points[polygon[b_at]]);
+    let intersection = [x, m[1]];
+
+    // The end of the edge further right can be seen from M, unless a reflex
+    // point pokes into the triangle between them, in which case the one of
+    // those closest in angle to the ray can.
+    let mut p_at = if intersection == a {
+        a_at
+    } else if intersection == b {
+        b_at
+    } else if a[0] > b[0] {
+        a_at
+    } else {
+        b_at
+    };
+    let p = points[polygon[p_at]];
+    if intersection != p {
+        let (triangle_a, triangle_b) = if orient(m, intersection, p) > 0.0 { (intersection, p) } else { (p, intersection) };
+        let mut best: Option<(f64, f64)> = None;
+        for i in 0..n {
+            let v = points[polygon[i]];
+            let reflex = orient(points[polygon[(i + n - 1) % n]], v, points[polygon[(i + 1) % n]]) < 0.0;
+            if i == p_at || !reflex || v == m || !in_triangle(v, m, triangle_a, triangle_b) {
+                continue;
+            }
+            let offset = sub(v, m);
+            let distance = dot(offset, offset);
+            let angle = (offset[1] / distance.sqrt()).abs();
+            if best.is_none_or(|(best_angle, best_distance)| (angle, distance) < (best_angle, best_distance)) {
+                best = Some((angle, distance));
+                p_at = i;
+            }
+        }
+    }
+
+    let around_hole = hole[m_at..].iter().chain(&hole[..=m_at]).copied();
+    let spliced: Vec<usize> = polygon[..=p_at]
+        .iter()
+        .copied()
+        .chain(around_hole)
+        .chain(std::iter::once(polygon[p_at]))
+        .chain(polygon[p_at + 1..].iter().copied())
+        .collect();
+    *polygon = spliced;
+    Ok(())
+}
+
+/// Cuts ears off the counter-clockwise `polygon` until it's down to one
+/// triangle.
+fn clip_ears(points: &[[f64; 2]], mut polygon: Vec<usize>) -> anyhow::Result<Vec<u32>> {
+    let mut triangles = Vec::new();
+    let mut i = 0;
+    // Points looked at since the last ear, to give up once every one has
+    // been.
+    let mut misses = 0;
+    while polygon.len() > 3 {
+        let n = polygon.len();
+        let (prev, next) = ((i + n - 1) % n, (i + 1) % n);
+        let [a, b, c] = [prev, i, next].map(|k| polygon[k]);
+        let turn = orient(points[a], points[b], points[c]);
+        if turn == 0.0 {
+            // In a straight line or doubling back along a cut, dropping it
+            // changes nothing.
+            polygon.remove(i);
+        } else if turn > 0.0 && is_ear(points, &polygon, [prev, i, next]) {
+            triangles.extend_from_slice(&[a as u32, b as u32, c as u32]);
+            polygon.remove(i);
+        } else {
+            i += 1;
+            misses += 1;
+            if misses > n {
+                anyhow::bail!("no ear left to clip with {} points to go", n);
+            }
+            i %= n;
+            continue;
+        }
+        misses = 0;
+        // Back one, the point before might have just become an ear.
+        i = (i + polygon.len() - 1) % polygon.len();
+    }
+    let [a, b, c] = [polygon[0], polygon[1], polygon[2]];
+    if orient(points[a], points[b], points[c]) > 0.0 {
+        triangles.extend_from_slice(&[a as u32, b as u32, c as u32]);
+    }
+    Ok(triangles)
+}
+
+/// Whether no reflex point of `polygon` is in the triangle at positions
+/// `corners`, other than copies of the corners themselves.
+fn is_ear(points: &[[f64; 2]], polygon: &[usize], corners: [usize; 3]) -> bool {
+    let n = polygon.len();
+    let [a, b, c] = corners.map(|k| points[polygon[k]]);
+    (0..n).all(|k| {
+        let v = points[polygon[k]];
+        if corners.contains(&k) || v == a || v == b || v == c {
+            return true;
+        }
+        let reflex = orient(points[polygon[(k + n - 1) % n]], v, points[polygon[(k + 1) % n]]) <= 0.0;
+        !reflex || !in_triangle(v, a, b, c)
+    })
+}
+
+fn sub(a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn dot(a: [f64; 2], b: [f64; 2]) -> f64 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+/// Positive if `a`, `b`, `c` turn left, negative if right, 0 if in line.
+fn orient(a: [f64; 2], b: [f64; 2], c: [f64; 2]) -> f64 {
+    let (ab, ac) = (sub(b, a), sub(c, a));
+    ab[0] * ac[1] - ab[1] * ac[0]
+}
+
+/// Twice the area, positive if counter-clockwise.
+fn signed_area(points: &[[f64; 2]], ring: &[usize]) -> f64 {
+    (0..ring.len())
+        .map(|i| {
+            let (a, b) = (points[ring[i]], points[ring[(i + 1) % ring.len()]]);
+            a[0] * b[1] - b[0] * a[1]
+        })
+        .sum()
+}
+
+/// Inside or on the edge of the counter-clockwise triangle.
+fn in_triangle(p: [f64; 2], a: [f64; 2], b: [f64; 2], c: [f64; 2]) -> bool {
+    orient(a, b, p) >= 0.0 && orient(b, c, p) >= 0.0 && orient(c, a, p) >= 0.0
+}
+
+/// Whether `p` is inside `ring` by the even-odd rule.
+fn inside(ring: &[[f64; 2]], p: [f64; 2]) -> bool {
+    let mut inside = false;
+    for i in 0..ring.len() {
+        let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+        if (a[1] > p[1]) != (b[1] > p[1]) && p[0] < a[0] + (p[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0]) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Whether segments `ab` and `cd` have any point in common.
+fn segments_touch(a: [f64; 2], b: [f64; 2], c: [f64; 2], d: [f64; 2]) -> bool {
+    let (d1, d2) = (orient(a, b, c), orient(a, b, d));
+    let (d3, d4) = (orient(c, d, a), orient(c, d, b));
+    if ((d1 > 0.0 && d2 < 0.0) || (d1 < 0.0 && d2 > 0.0)) && ((d3 > 0.0 && d4 < 0.0) || (d3 < 0.0 && d4 > 0.0)) {
+        return true;
+    }
+    // An end on the other segment.
+    let on = |p: [f64; 2], q: [f64; 2], r: [f64; 2], turn: f64| {
+        turn == 0.0 && r[0] >= p[0].min(q[0]) && r[0] <= p[0].max(q[0]) && r[1] >= p[1].min(q[1]) && r[1] <= p[1].max(q[1])
+    };
+    on(a, b, c, d1) || on(a, b, d, d2) || on(c, d, a, d3) || on(c, d, b, d4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(outline: &[[f32; 2]], holes: &[Vec<[f32; 2]>], triangles: &[u32]) -> f64 {
+        let points: Vec<[f64; 2]> = outline.iter().chain(holes.iter().flatten()).map(|p| p.map(f64::from)).collect();
+        triangles
+            .chunks_exact(3)
+            .map(|t| {
+                let turn = orient(points[t[0] as usize], points[t[1] as usize], points[t[2] as usize]);
+                assert!(turn > 0.0, "triangle {:?} isn't counter-clockwise", t);
+                turn * 0.5
+            })
+            .sum()
+    }
+
+    const SQUARE: [[f32; 2]; 4] = [[0.0, 0.0], [4.0, 0.0], [4.0, 4.0], [0.0, 4.0]];
+
+    #[test]
+    fn convex() {
+        let triangles = triangulate(&SQUARE, &[]).unwrap();
+        assert_eq!(triangles.len(), 6);
+        assert_eq!(area(&SQUARE, &[], &triangles), 16.0);
+    }
+
+    #[test]
+    fn concave_clockwise() {
+        // An L, the wrong way round.
+        let outline = [[0.0, 0.0], [0.0, 3.0], [1.0, 3.0], [1.0, 1.0], [2.0, 1.0], [2.0, 0.0]];
+        let triangles = triangulate(&outline, &[]).unwrap();
+        assert_eq!(triangles.len(), 3 * 4);
+        assert_eq!(area(&outline, &[], &triangles), 4.0);
+    }
+
+    #[test]
+    fn holes() {
+        let holes = vec![
+            vec![[1.0, 1.0], [2.0, 1.0], [2.0, 2.0], [1.0, 2.0]],
+            vec![[2.5, 2.5], [3.5, 2.5], [3.0, 3.5]],
+        ];
+        let triangles = triangulate(&SQUARE, &holes).unwrap();
+        // n + 2h - 2 triangles.
+        assert_eq!(triangles.len(), 3 * (11 + 2 * 2 - 2));
+        assert_eq!(area(&SQUARE, &holes, &triangles), 16.0 - 1.0 - 0.5);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let bowtie = [[0.0, 0.0], [1.0, 1.0], [1.0, 0.0], [0.0, 1.0]];
+        let error = triangulate(&bowtie, &[]).unwrap_err().to_string();
+        assert_eq!(error, "edge 0 of the outline crosses or touches edge 2 of the outline");
+
+        let outside = vec![vec![[5.0, 5.0], [6.0, 5.0], [6.0, 6.0]]];
+        assert_eq!(triangulate(&SQUARE, &outside).unwrap_err().to_string(), "hole 0 isn't inside the outline");
+
+        let crossing = vec![vec![[1.0, 1.0], [5.0, 1.0], [1.0, 2.0]]];
+        assert!(triangulate(&SQUARE, &crossing).is_err());
+
+        let flat = [[0.0, 0.0], [1.0, 0.0], [2.0, 0.0]];
+        assert!(triangulate(&flat, &[]).is_err());
+    }
+
+    #[test]
+    fn vertices() {
+        let (vertices, indices) = polygon(&SQUARE, &[]).unwrap();
+        assert_eq!(indices.len(), 6);
+        assert_eq!(vertices[0].tex_coords, [0.0, 1.0]);
+        assert_eq!(vertices[2].tex_coords, [1.0, 0.0]);
+    }
+}
@@ -0,0 +1,403 @@
+use std::marker::PhantomData;
+use wgpu::util::DeviceExt;
+
+/// The shapes a uniform struct field can take on the WGSL side.
+///
+/// Alignment and size follow the uniform address space rules, which are
+/// stricter than the storage ones: arrays and nested structs are aligned
+/// to 16 bytes and array elements are strided by at least 16 bytes.
+#[derive(Clone, Copy, Debug)]
+pub enum WgslType {
+    F32,
+    I32,
+    U32,
+    Vec2,
+    Vec3,
+    Vec4,
+    Mat3x3,
+    Mat4x4,
+    Array(&'static WgslType, usize),
+    Struct { size: usize, align: usize },
+}
+
+impl WgslType {
+    pub const fn align(&self) -> usize {
+        match self {
+            WgslType::F32 | WgslType::I32 | WgslType::U32 => 4,
+            WgslType::Vec2 => 8,
+            WgslType::Vec3 | WgslType::Vec4 | WgslType::Mat3x3 | WgslType::Mat4x4 => 16,
+            WgslType::Array(element, _) => round_up(16, element.align()),
+            WgslType::Struct { align, .. } => round_up(16, *align),
+        }
+    }
+
+    pub const fn size(&self) -> usize {
+        match self {
+            WgslType::F32 | WgslType::I32 | WgslType::U32 => 4,
+            WgslType::Vec2 => 8,
+            WgslType::Vec3 => 12,
+            WgslType::Vec4 => 16,
+            // Each column of a mat3x3 is a vec3 padded out to 16 bytes.
+            WgslType::Mat3x3 => 48,
+            WgslType::Mat4x4 => 64,
+            WgslType::Array(element, count) => *count * array_stride(element),
+            WgslType::Struct { size, .. } => *size,
+        }
+    }
+
+    /// Describes a struct that itself implements [`WgslLayout`] so it can
+    /// be nested inside another uniform.
+    pub const fn of<T: WgslLayout>() -> WgslType {
+        WgslType::Struct {
+            size: T::SIZE,
+            align: struct_align(T::FIELDS),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WgslField {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+    pub ty: WgslType,
+}
+
+/// A `#[repr(C)]` struct whose memory layout has been checked against the
+/// WGSL struct it is uploaded into.
+///
+/// Implement this with [`wgsl_layout!`](crate::wgsl_layout) rather than by
+/// hand, the macro turns every mismatched field into a compile error.
+pub trait WgslLayout: bytemuck::Pod {
+    const FIELDS: &'static [WgslField];
+    /// The size WGSL expects for the struct, including tail padding.
+    const SIZE: usize = struct_size(Self::FIELDS);
+}
+
+const fn array_stride(element: &WgslType) -> usize {
+    round_up(16, round_up(element.align(), element.size()))
+}
+
+pub const fn round_up(align: usize, value: usize) -> usize {
+    value.div_ceil(align) * align
+}
+
+pub const fn struct_align(fields: &[WgslField]) -> usize {
+    let mut align = 1;
+    let mut i = 0;
+    while i < fields.len() {
+        if fields[i].ty.align() > align {
+            align = fields[i].ty.align();
+        }
+        i += 1;
+    }
+    align
+}
+
+pub const fn struct_size(fields: &[WgslField]) -> usize {
+    let mut end = 0;
+    let mut i = 0;
+    while i < fields.len() {
+        end = round_up(fields[i].ty.align(), end) + fields[i].ty.size();
+        i += 1;
+    }
+    round_up(struct_align(fields), end)
+}
+
+/// The offset WGSL gives the field called `name`, or `usize::MAX` if
+/// there is no such field.
+pub const fn wgsl_offset(fields: &[WgslField], name: &str) -> usize {
+    let mut end = 0;
+    let mut i = 0;
+    while i < fields.len() {
+        let offset = round_up(fields[i].ty.align(), end);
+        if str_eq(fields[i].name, name) {
+            return offset;
+        }
+        end = offset + fields[i].ty.size();
+        i += 1;
+    }
+    usize::MAX
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+#[doc(hidden)]
+pub const fn field_size<S, F>(_field: fn(&S) -> &F) -> usize {
+    std::mem::size_of::<F>()
+}
+
+/// Declares the WGSL layout of a uniform struct and checks it at compile
+/// time.
+///
+/// Every listed field must sit at the offset WGSL would give it and be
+/// exactly as large as its WGSL type. Rust-only padding fields are left
+/// out of the list. A Rust struct that is shorter than its WGSL size is
+/// fine, [`UniformBuffer`] pads the tail when uploading.
+///
+/// ```ignore
+/// wgsl_layout!(CameraUniform {
+///     view_position: WgslType::Vec4,
+///     view_proj: WgslType::Mat4x4,
+/// });
+/// ```
+#[macro_export]
+macro_rules! wgsl_layout {
+    ($ty:ty { $($field:ident : $wgsl:expr),* $(,)? }) => {
+        impl $crate::uniform::WgslLayout for $ty {
+            const FIELDS: &'static [$crate::uniform::WgslField] = &[
+                $($crate::uniform::WgslField {
+                    name: stringify!($field),
+                    offset: std::mem::offset_of!($ty, $field),
+                    size: $crate::uniform::field_size(|s: &$ty| &s.$field),
+                    ty: $wgsl,
+                }),*
+            ];
+        }
+
+        $(
+            const _: () = {
+                let fields = <$ty as $crate::uniform::WgslLayout>::FIELDS;
+                assert!(
+                    std::mem::offset_of!($ty, $field)
+                        == $crate::uniform::wgsl_offset(fields, stringify!($field)),
+                    concat!(
+                        "`", stringify!($ty), "::", stringify!($field),
+                        "` is not at the offset WGSL expects, add explicit padding before it"
+                    ),
+                );
+                assert!(
+                    $crate::uniform::field_size(|s: &$ty| &s.$field) == ($wgsl).size(),
+                    concat!(
+                        "`", stringify!($ty), "::", stringify!($field),
+                        "` is not the same size as its WGSL type"
+                    ),
+                );
+            };
+        )*
+
+        const _: () = assert!(
+            std::mem::size_of::<$ty>() <= <$ty as $crate::uniform::WgslLayout>::SIZE,
+            concat!("`", stringify!($ty), "` is larger than its WGSL struct"),
+        );
+    };
+}
+
+/// A uniform buffer holding a single `T`, together with the bind group
+/// layout and bind group that expose it at binding 0.
+///
+/// The buffer is sized to the WGSL struct rounded up to whole 16 byte
+/// blocks, which is what WebGL's std140 blocks expect, so any tail padding
+/// the Rust struct leaves out is filled with zeroes on upload.
+pub struct UniformBuffer<T: WgslLayout> {
+    pub buffer: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    _marker: PhantomData<T>,
+}
+
+impl<T: WgslLayout> UniformBuffer<T> {
+    /// The WGSL size rounded up to whole 16 byte blocks.
+    const BUFFER_SIZE: usize = round_up(16, T::SIZE);
+
+    pub fn new(
+        device: &wgpu::Device,
+        value: &T,
+        visibility: wgpu::ShaderStages,
+        label: &str,
+    ) -> Self {
+        Self::with_resources(device, value, visibility, &[], label)
+    }
+
+    /// Like [`UniformBuffer::new`], but the bind group also carries
+    /// `resources`, e.g. textures the shader reads next to the uniform.
+    /// Their bindings must not be 0.
+    pub fn with_resources(
+        device: &wgpu::Device,
+        value: &T,
+        visibility: wgpu::ShaderStages,
+        resources: &[(wgpu::BindGroupLayoutEntry, wgpu::BindingResource)],
+        label: &str,
+    ) -> Self {
+        let layout_entries: Vec<_> = std::iter::once(Self::layout_entry(0, visibility))
+            .chain(resources.iter().map(|(entry, _)| *entry))
+            .collect();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &layout_entries,
+            label: Some(label),
+        });
+
+        let resources: Vec<_> = resources
+            .iter()
+            .map(|(entry, resource)| (entry.binding, resource.clone()))
+            .collect();
+        Self::with_layout(device, value, &bind_group_layout, &resources, label)
+    }
+
+    /// Builds the bind group against an existing `layout`, for bind groups
+    /// that share one layout, e.g. every material of a kind. The layout must
+    /// have the uniform at binding 0 and an entry for each of `resources`.
+    pub fn with_layout(
+        device: &wgpu::Device,
+        value: &T,
+        layout: &wgpu::BindGroupLayout,
+        resources: &[(u32, wgpu::BindingResource)],
+        label: &str,
+    ) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: &Self::padded_bytes(value),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let entries: Vec<_> = std::iter::once(wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        })
+        .chain(resources.iter().map(|(binding, resource)| wgpu::BindGroupEntry {
+            binding: *binding,
+            resource: resource.clone(),
+        }))
+        .collect();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &entries,
+            label: Some(label),
+        });
+
+        Self {
+            buffer,
+            bind_group_layout: layout.clone(),
+            bind_group,
+            _marker: PhantomData,
+        }
+    }
+
+    /// A layout entry for binding this uniform inside a larger bind group.
+    pub fn layout_entry(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(Self::BUFFER_SIZE as u64),
+            },
+            count: None,
+        }
+    }
+
+    /// Queues a write of `value`, cheap enough to call every frame.
+    pub fn update(&self, queue: &wgpu::Queue, value: &T) {
+        let bytes = bytemuck::bytes_of(value);
+        if bytes.len() == Self::BUFFER_SIZE {
+            queue.write_buffer(&self.buffer, 0, bytes);
+            return;
+        }
+        // Straight into wgpu's staging memory, the tail zeroed there.
+        let size = wgpu::BufferSize::new(Self::BUFFER_SIZE as u64).expect("uniforms aren't empty");
+        if let Some(mut view) = queue.write_buffer_with(&self.buffer, 0, size) {
+            let view = view.as_mut();
+            view[..bytes.len()].copy_from_slice(bytes);
+            view[bytes.len()..].fill(0);
+        }
+    }
+
+    fn padded_bytes(value: &T) -> Vec<u8> {
+        let mut bytes = bytemuck::bytes_of(value).to_vec();
+        bytes.resize(Self::BUFFER_SIZE, 0);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+    struct Light {
+        position: [f32; 3],
+        intensity: f32,
+        color: [f32; 3],
+        _padding: f32,
+    }
+
+    crate::wgsl_layout!(Light {
+        position: WgslType::Vec3,
+        intensity: WgslType::F32,
+        color: WgslType::Vec3,
+    });
+
+    #[repr(C)]
+    #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+    struct Lights {
+        count: u32,
+        _padding: [u32; 3],
+        lights: [Light; 2],
+        exposure: f32,
+    }
+
+    const LIGHT: WgslType = WgslType::of::<Light>();
+
+    crate::wgsl_layout!(Lights {
+        count: WgslType::U32,
+        lights: WgslType::Array(&LIGHT, 2),
+        exposure: WgslType::F32,
+    });
+
+    #[test]
+    fn vec3_is_aligned_to_16_and_packs_a_scalar_after_it() {
+        let fields = <Light as WgslLayout>::FIELDS;
+        assert_eq!(WgslType::Vec3.align(), 16);
+        assert_eq!(wgsl_offset(fields, "position"), 0);
+        assert_eq!(wgsl_offset(fields, "intensity"), 12);
+        assert_eq!(wgsl_offset(fields, "color"), 16);
+        assert_eq!(wgsl_offset(fields, "missing"), usize::MAX);
+    }
+
+    #[test]
+    fn array_stride_is_rounded_up_to_16() {
+        assert_eq!(WgslType::Array(&WgslType::F32, 4).size(), 64);
+        assert_eq!(WgslType::Array(&WgslType::Vec2, 3).size(), 48);
+        assert_eq!(WgslType::Array(&WgslType::Vec3, 2).size(), 32);
+        assert_eq!(WgslType::Array(&WgslType::F32, 4).align(), 16);
+        assert_eq!(WgslType::Array(&WgslType::Mat4x4, 2).size(), 128);
+    }
+
+    #[test]
+    fn nested_structs_are_aligned_to_16() {
+        assert_eq!(<Light as WgslLayout>::SIZE, 32);
+        assert_eq!((LIGHT.size(), LIGHT.align()), (32, 16));
+        let fields = <Lights as WgslLayout>::FIELDS;
+        assert_eq!(wgsl_offset(fields, "lights"), 16);
+        assert_eq!(wgsl_offset(fields, "exposure"), 80);
+    }
+
+    #[test]
+    fn trailing_padding_rounds_up_to_the_struct_alignment() {
+        // The Rust struct stops after `exposure`, WGSL pads it to 96.
+        assert_eq!(std::mem::size_of::<Lights>(), 84);
+        assert_eq!(<Lights as WgslLayout>::SIZE, 96);
+        let scalars = [
+            WgslField { name: "a", offset: 0, size: 8, ty: WgslType::Vec2 },
+            WgslField { name: "b", offset: 8, size: 4, ty: WgslType::F32 },
+        ];
+        assert_eq!(struct_size(&scalars), 16);
+        assert_eq!(struct_align(&scalars), 8);
+        assert_eq!(UniformBuffer::<Lights>::BUFFER_SIZE, 96);
+    }
+}
@@ -0,0 +1,280 @@
+//! Paths of lines and curves, filled or stroked into triangles for the 2D
+//! [`Vertex`] pipeline. The tessellating is left to lyon.
+
+use cgmath::Deg;
+use lyon::math::{Angle, point, vector};
+use lyon::path::builder::SvgPathBuilder;
+use lyon::path::ArcFlags;
+use lyon::tessellation::{
+    BuffersBuilder, FillOptions, FillTessellator, FillVertex, StrokeOptions, StrokeTessellator, StrokeVertex,
+    VertexBuffers,
+};
+
+use crate::Vertex;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Segment {
+    Move([f32; 2]),
+    Line([f32; 2]),
+    Quadratic {
+        ctrl: [f32; 2],
+        to: [f32; 2],
+    },
+    Cubic {
+        ctrl1: [f32; 2],
+        ctrl2: [f32; 2],
+        to: [f32; 2],
+    },
+    Arc {
+        radii: [f32; 2],
+        x_rotation: Deg<f32>,
+        large_arc: bool,
+        sweep: bool,
+        to: [f32; 2],
+    },
+    Close,
+}
+
+/// A shape made of any number of subpaths, drawn like an SVG path. A
+/// subpath starts at a [`Path::move_to`], a curve or line without one
+/// starts from the origin.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Path {
+    segments: Vec<Segment>,
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a new subpath at `to`.
+    pub fn move_to(&mut self, to: [f32; 2]) -> &mut Self {
+        self.segments.push(Segment::Move(to));
+        self
+    }
+
+    pub fn line_to(&mut self, to: [f32; 2]) -> &mut Self {
+        self.segments.push(Segment::Line(to));
+        self
+    }
+
+    pub fn quadratic_to(&mut self, ctrl: [f32; 2], to: [f32; 2]) -> &mut Self {
+        self.segments.push(Segment::Quadratic { ctrl, to });
+        self
+    }
+
+    pub fn cubic_to(&mut self, ctrl1: [f32; 2], ctrl2: [f32; 2], to: [f32; 2]) -> &mut Self {
+        self.segments.push(Segment::Cubic { ctrl1, ctrl2, to });
+        self
+    }
+
+    /// An elliptical arc to `to` with the given radii, its x axis rotated
+    /// by `x_rotation`, going the long way round if `large_arc` and with
+    /// increasing angles if `sweep`, as in SVG's `A` command.
+    pub fn arc_to(&mut self, radii: [f32; 2], x_rotation: Deg<f32>, large_arc: bool, sweep: bool, to: [f32; 2]) -> &mut Self {
+        self.segments.push(Segment::Arc {
+            radii,
+            x_rotation,
+            large_arc,
+            sweep,
+            to,
+        });
+        self
+    }
+
+    /// Joins the end of the subpath back to its start.
+    pub fn close(&mut self) -> &mut Self {
+        self.segments.push(Segment::Close);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    fn to_lyon(&self) -> lyon::path::Path {
+        let mut builder = lyon::path::Path::svg_builder();
+        let p = |p: [f32; 2]| point(p[0], p[1]);
+        for segment in &self.segments {
+            match *segment {
+                Segment::Move(to) => {
+                    builder.move_to(p(to));
+                }
+                Segment::Line(to) => {
+                    builder.line_to(p(to));
+                }
+                Segment::Quadratic { ctrl, to } => {
+                    builder.quadratic_bezier_to(p(ctrl), p(to));
+                }
+                Segment::Cubic { ctrl1, ctrl2, to } => {
+                    builder.cubic_bezier_to(p(ctrl1), p(ctrl2), p(to));
+                }
+                Segment::Arc {
+                    radii,
+                    x_rotation,
+                    large_arc,
+                    sweep,
+                    to,
+                } => builder.arc_to(
+                    vector(radii[0], radii[1]),
+                    Angle::degrees(x_rotation.0),
+                    ArcFlags { large_arc, sweep },
+                    p(to),
+                ),
+                Segment::Close => builder.close(),
+            }
+        }
+        builder.build()
+    }
+
+    /// The triangles inside the path by `rule`, with curves flattened to
+    /// within `tolerance`.
+    pub fn fill(&self, rule: FillRule, tolerance: f32) -> anyhow::Result<Tessellation> {
+        let rule = match rule {
+            FillRule::NonZero => lyon::tessellation::FillRule::NonZero,
+            FillRule::EvenOdd => lyon::tessellation::FillRule::EvenOdd,
+        };
+        let options = FillOptions::tolerance(tolerance).with_fill_rule(rule);
+        let mut buffers: VertexBuffers<[f32; 2], u16> = VertexBuffers::new();
+        FillTessellator::new().tessellate_path(
+            &self.to_lyon(),
+            &options,
+            &mut BuffersBuilder::new(&mut buffers, |vertex: FillVertex| vertex.position().to_array()),
+        )?;
+        Ok(Tessellation {
+            positions: buffers.vertices,
+            indices: buffers.indices,
+        })
+    }
+
+    /// The triangles covering a line drawn along the path, with curves
+    /// flattened to within `tolerance`.
+    pub fn stroke(&self, stroke: &Stroke, tolerance: f32) -> anyhow::Result<Tessellation> {
+        let join = match stroke.join {
+            LineJoin::Miter => lyon::tessellation::LineJoin::Miter,
+            LineJoin::Round => lyon::tessellation::LineJoin::Round,
+            LineJoin::Bevel => lyon::tessellation::LineJoin::Bevel,
+        };
+        let cap = match stroke.cap {
+            LineCap::Butt => lyon::tessellation::LineCap::Butt,
+            LineCap::Round => lyon::tessellation::LineCap::Round,
+            LineCap::Square => lyon::tessellation::LineCap::Square,
+        };
+        let options = StrokeOptions::tolerance(tolerance)
+            .with_line_width(stroke.width)
+            .with_line_join(join)
+            .with_line_cap(cap)
+            .with_miter_limit(stroke.miter_limit.max(StrokeOptions::MINIMUM_MITER_LIMIT));
+        let mut buffers: VertexBuffers<[f32; 2], u16> = VertexBuffers::new();
+        StrokeTessellator::new().tessellate_path(
+            &self.to_lyon(),
+            &options,
+            &mut BuffersBuilder::new(&mut buffers, |vertex: StrokeVertex| vertex.position().to_array()),
+        )?;
+        Ok(Tessellation {
+            positions: buffers.vertices,
+            indices: buffers.indices,
+        })
+    }
+}
+
+/// Which points are inside a path whose subpaths overlap or wind around
+/// each other.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FillRule {
+    /// Inside if the subpaths wind around it more times one way than the
+    /// other.
+    #[default]
+    NonZero,
+    /// Inside if a line out from it crosses an odd number of edges.
+    EvenOdd,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LineJoin {
+    /// Extends the edges to a point, beveled past [`Stroke::miter_limit`].
+    #[default]
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LineCap {
+    /// Stops square at the end point.
+    #[default]
+    Butt,
+    Round,
+    /// Stops square half the width past the end point.
+    Square,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stroke {
+    pub width: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    /// How many times half the width a miter join can reach out before it's
+    /// beveled instead.
+    pub miter_limit: f32,
+}
+
+impl Default for Stroke {
+    /// SVG's defaults.
+    fn default() -> Self {
+        Self {
+            width: 1.0,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 4.0,
+        }
+    }
+}
+
+/// Triangles in the coordinates of the path they came from, in no
+/// particular winding.
+#[derive(Clone, Debug, Default)]
+pub struct Tessellation {
+    pub positions: Vec<[f32; 2]>,
+    pub indices: Vec<u16>,
+}
+
+/// Vertices and indices ready to go in buffers, with every triangle wound
+/// counter-clockwise so none get culled.
+#[derive(Clone, Debug, Default)]
+pub struct Geometry {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+}
+
+impl Geometry {
+    /// Adds the triangles of `tessellation`, placing each point with
+    /// `position` and texturing it with `tex_coords`.
+    pub fn push(
+        &mut self,
+        tessellation: &Tessellation,
+        position: impl Fn([f32; 2]) -> [f32; 3],
+        tex_coords: impl Fn([f32; 2]) -> [f32; 2],
+    ) -> anyhow::Result<()> {
+        let first = self.vertices.len();
+        if first + tessellation.positions.len() > u16::MAX as usize + 1 {
+            anyhow::bail!("more than {} vertices don't fit 16 bit indices", u16::MAX as usize + 1);
+        }
+        self.vertices.extend(tessellation.positions.iter().map(|&p| Vertex {
+            position: position(p),
+            tex_coords: tex_coords(p),
+        }));
+        for triangle in tessellation.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| first as u16 + triangle[i]);
+            let [pa, pb, pc] = [a, b, c].map(|i| self.vertices[i as usize].position);
+            let turn = (pb[0] - pa[0]) * (pc[1] - pa[1]) - (pb[1] - pa[1]) * (pc[0] - pa[0]);
+            if turn < 0.0 {
+                self.indices.extend_from_slice(&[a, c, b]);
+            } else {
+                self.indices.extend_from_slice(&[a, b, c]);
+            }
+        }
+        Ok(())
+    }
+}
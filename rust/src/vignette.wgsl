@@ -0,0 +1,8 @@
+// params: x = strength, y = radius, z = softness
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+    let dist = distance(in.uv, vec2<f32>(0.5, 0.5));
+    let vignette = smoothstep(post.params.y, post.params.y - post.params.z, dist);
+    return vec4<f32>(color.rgb * mix(1.0, vignette, post.params.x), color.a);
+}